@@ -1,8 +1,5 @@
-use bitcoin::secp256k1::Secp256k1;
-use bitcoin::sighash;
-use bitcoin::{absolute::LockTime, Sequence};
 use hex_lit::hex;
-use miniscript::interpreter::KeySigPair;
+use minikeys::{decode_tx, verify_input_signers, verify_tx_signers, InputReport, TxReport};
 
 //These are real blockchain transactions examples of computing sighash for:
 // - P2WPKH
@@ -10,9 +7,10 @@ use miniscript::interpreter::KeySigPair;
 // - P2SH 2of2 multisig
 // - P2WSH 2of2 multisig
 
-//run with: cargo run --example sighash
+//run with: cargo run --example validkeys
 
-//TODO add P2TR examples, ideally for both key-path and script-path spending
+//The P2TR examples are script-path spends; taproot_key_path.rs and musig.rs
+//cover key-path spends, on transactions signed there.
 
 fn main() {
     test_sighash_p2ms_multisig_2x3();
@@ -33,7 +31,7 @@ fn test_sighash_p2sh_multisig_2x2() {
     let raw_reftx = hex!("0100000001ade1ff3b7e2da73914f831ef20ab26bb861f60b7659ff7de3af07137d973a17402000000da00473044022039c919ba99640e2256b8b56c22278997bfdc24a78b72e9dd7e232d0a5650c01702204a15618469c4f0978e123d391f833f7bc6dc9e3b7d22f79d492e0c70a6aa704701483045022100983973c309c05ab8a6002e1ea663fb44227b1b8eaaaeb2d63e6c39a595f02a78022020fd7cc11660bf10cef660a0256276a9b378d236b30fe3a9f900448a7d5de0b201475221032d7306898e980c66aefdfb6b377eaf71597c449bf9ce741a3380c5646354f6de2103e8c742e1f283ef810c1cd0c8875e5c2998a05fc5b23c30160d3d33add7af565752aeffffffff02b5ba0000000000001976a914c91852f5780f02a7f8884f05037547594ac3cb8988ac5ce002000000000017a914da55145ca5c56ba01f1b0b98d896425aa4b0f4468700000000");
 
     println!("\n\n======== sighash_p2sh_multisig_2x2:\n");
-    vrfy_pks(&raw_tx, 0, &raw_reftx);
+    vrfy_pks(&raw_tx, 0, vec![&raw_reftx]);
 }
 
 fn test_sighash_p2sh_multisig_2x3() {
@@ -46,7 +44,7 @@ fn test_sighash_p2sh_multisig_2x3() {
     let raw_reftx = hex!("0100000002069dc300e8b6e7ab2bf730031d6a4c992c832901d9d5d812eaf6da4ebc741d40000000006a47304402204a02661c79ff20dec99fc96e7a2002ff8635094536a1b5a0029b6c5dcf9e2c0c02207f9e27917c9d95b9abcb36c3b92bc73cef481f4af37a0a4ea38b3c50c7b8f285012102f7714daa4075cd5d6b72a0e2f1224bb17df25e39b2b39a219a60ee4f8f32c00affffffff7ed464e27690f523437bdd4bdb3a2297ec01f04e135997e0136887ab83e7cec8020000006a473044022030f37e9eac368ad3c39ee8bad572c4fd89db0bd7da02366315053ad434dcf564022010647fa8c2e14c26e174d2fed880fb723ee64c72f460d883018f364701307e5c01210355239b80f3b7a2112edaf5b7fb48dc933f2a26ec359be90e32082163f1b57759ffffffff0294ff03010000000017a91424334fe9a4bb4bffdc2bc0d2e618625070f8362487f2ca0100000000001976a9147196755f5991d8595ed4f227f10fcc62fcd979d888ac00000000");
 
     println!("\n\n======== sighash_p2sh_multisig_2x3:\n");
-    vrfy_pks(&raw_tx, 0, &raw_reftx);
+    vrfy_pks(&raw_tx, 0, vec![&raw_reftx]);
 }
 fn test_sighash_p2wsh_multisig_2x2() {
     //The spending transaction is
@@ -61,7 +59,7 @@ fn test_sighash_p2wsh_multisig_2x2() {
     let raw_reftx = hex!("02000000000101828b350cf855b1a52b23a17dc89ed4e99e6d70503e1e1519945f75e19e7617c50000000000fdffffff0230c60e0000000000220020781ada670a98cfb276c6d2a78bbf21eb8f3617f4c2288cb16f5ad8741b5d83dd809698000000000016001449176d383a51877682b0f80e24776c015b2fa6d502473044022064b1047b43707baef72e69796c18340cfe74ff3fdb720bd8d6d814df2c61224802204c2c00fe218461eb84f01a885b90f73b90caae7b25e906e43618916cd7bd3a270121033d0f4a852ee6b3cec7bea4296bf6e4a88510fb5ac6da80b95fa8bb3bfc9fd1bb5fae0a00");
 
     println!("\n\n======== sighash_p2wsh_multisig_2x2:\n");
-    vrfy_pks(&raw_tx, 0, &raw_reftx);
+    vrfy_pks(&raw_tx, 0, vec![&raw_reftx]);
 }
 
 fn test_sighash_p2ms_multisig_2x3() {
@@ -74,7 +72,7 @@ fn test_sighash_p2ms_multisig_2x3() {
     let raw_reftx = hex!("01000000014563f26698c0ea3ebd85d4767457370d7e2ebbe922a7736dbf70e1d0f8a9aa9c000000008a473044022039294d5c8843a6776d4a2032cf03549f41c634ba5e65898c7816973919e485b902205af1f61f6d7d6a5f32cbe46676303c141fe499288b1be0d8f0c4e80d4c0ecb5701410454ffbc96ef3c26acffa431066915308865d990e044c507e0ab3d26af34a8ba5b4cb3028fe7c91926bb8be47d652dc70ab300e3022f8259db5f79306b601fc66effffffff0190c9190000000000c9524104d81fd577272bbe73308c93009eec5dc9fc319fc1ee2e7066e17220a5d47a18314578be2faea34b9f1f8ca078f8621acd4bc22897b03daa422b9bf56646b342a24104ec3afff0b2b66e8152e9018fe3be3fc92b30bf886b3487a525997d00fd9da2d012dce5d5275854adc3106572a5d1e12d4211b228429f5a7b2f7ba92eb0475bb14104b49b496684b02855bc32f5daefa2e2e406db4418f3b86bca5195600951c7d918cdbe5e6d3736ec2abf2dd7610995c3086976b2c0c7b4e459d10b34a316d5a5e753ae00000000");
 
     println!("\n\n======== sighash_p2ms_multisig_2x3:\n");
-    vrfy_pks(&raw_tx, 0, &raw_reftx);
+    vrfy_pks(&raw_tx, 0, vec![&raw_reftx]);
 }
 
 fn test_sighash_p2tr_multisig_1x2() {
@@ -88,7 +86,8 @@ fn test_sighash_p2tr_multisig_1x2() {
     let raw_reftx_vec : Vec<&[u8]> = vec![&hex!("010000000409cc8928f1d3ea4855dedbff8b783e3379735817b072df569776b5c5187d09ca010000006b483045022100a885cea8709cbb93b8311bf2fd5a30ff3e9fc02459652ebb040f47efc70cf51e02202194d53c2fe26cafcdf5748722949a275faf8575d15e2967d1bd3010d652c21b012102682e5ebd58a7d62a87b0490572776e4bb87dc868fb06419d8f33fe41c21160f9ffffffff0fbd54556226c210849929c0c50c00fc472ab4448be0333aa59f335c4e5a088b010000006b483045022100c4c368a8696a200e2d815c0d7cba690e415e3af6f6a0472b28c292ab85ffaa7002207911811c71ac927c48c47797fe8790a0d7ba172a7005ee8036e70e481909a375012102682e5ebd58a7d62a87b0490572776e4bb87dc868fb06419d8f33fe41c21160f9ffffffffa5091f20a2f91e56811e0d979b2dd7126c58dcd2d767d379e25c0a09c3c526fb010000006a473044022046bf081055f3409cee71cedb396a28060f1166195130cb8bedd6a13ecd1f6beb0220602ebd6e0a7b2c39bcfb59b42035dc246ec2a87e2e9f4b71ffa13feb15167615012102682e5ebd58a7d62a87b0490572776e4bb87dc868fb06419d8f33fe41c21160f9ffffffffb2c3b6434a7bda252db8aeb975ea5ca58da36a461545bb634dddadf5e35c6607010000006b483045022100a0466b24f77b68c54748d1c9ac43559eb91f952928b3fe28e452e619f814f23d022003853b255707400301cedd7922256d623ba2fc60d2734f19f79b2c6f0f61c3d4012102682e5ebd58a7d62a87b0490572776e4bb87dc868fb06419d8f33fe41c21160f9ffffffff01273f110000000000225120667bdd93c7c029767fd516d2ea292624b938fefefa175ac9f1220cf508963ff300000000"), &hex!("02000000000101fe9d111c806dbf9fa4f03869a42ff81972691b86db5c3ef89381456b4422d3be0000000000ffffffff023075000000000000225120667bdd93c7c029767fd516d2ea292624b938fefefa175ac9f1220cf508963ff30000000000000000116a0f676d20746170726f6f7420f09fa59502473044022001ce176bf7357e12a873b4e439d53eb02f1a642a043a6b7e9e5ae46d0d152f8c02204d603e93f49205624eb56c686fc759cc8d11000f4df76c24bda62d790f13d1ff012102e484e53bcce92e801a29454dae07812d6999bf1133aca94c8b03c65b56bdd08d00000000")];
 
     println!("\n\n======== sighash_p2tr_multisig_1x2\n");
-    vrfy_pks_tr(&raw_tx, 0, raw_reftx_vec);
+    vrfy_pks(&raw_tx, 0, raw_reftx_vec.clone());
+    vrfy_tx(&raw_tx, raw_reftx_vec);
 }
 
 fn test_sighash_p2tr_multisig_2x2() {
@@ -102,152 +101,90 @@ fn test_sighash_p2tr_multisig_2x2() {
     let raw_reftx_vec: Vec<&[u8]>  = vec![&hex!("0200000000010140b84131c5c582290126bbd8b8e2e5bbd7c2681a4b01314f1b874ea1b5fdf81c0000000000ffffffff014c1d0000000000002251202fcad7470279652cc5f88b8908678d6f4d57af5627183b03fc8404cb4e16d88902473044022066d6939ea701db5d306fb948aea64af196ae52fc34d62c2e7992f62cdabc791402200abdac6766105457ceabcbe55a2d33f064d515210085f7af1248d273442e2b2a012103476f0d6a85ced4a85b08cbabbff28564a1ba31091b38f10b167f4fe1e1c9c4f900d40a00")];
 
     println!("\n\n======== sighash_p2tr_multisig_2x2\n");
-    vrfy_pks_tr(&raw_tx, 0, raw_reftx_vec);
+    vrfy_pks(&raw_tx, 0, raw_reftx_vec);
 }
 
-/// Finds the valid PubKeys in a verified segwit multisig transaction input that spends a p2wsh output with "witness_v0_scripthash" scriptPubKey.type
+/// Decodes the spending tx and its funding txs and prints the keys that signed input `inp_idx`
 ///
 /// # Arguments
 ///
-/// * `raw_tx` - spending tx hex
+/// * `raw_tx` - spending tx
 /// * `inp_idx` - spending tx input index
-/// * `value` - ref tx output value in sats
-fn vrfy_pks(mut raw_tx: &[u8], inp_idx: usize, mut raw_reftx: &[u8]) -> Vec<bitcoin::PublicKey> {
-    let tx: bitcoin::Transaction =
-        bitcoin::consensus::Decodable::consensus_decode(&mut raw_tx).unwrap();
-    let reftx: bitcoin::Transaction =
-        bitcoin::consensus::Decodable::consensus_decode(&mut raw_reftx).unwrap();
-    let vout: usize = tx.input[inp_idx].previous_output.vout.try_into().unwrap();
-    let script_pubkey = &reftx.output[vout].script_pubkey;
-    let interpreter = miniscript::Interpreter::from_txdata(
-        script_pubkey,
-        &tx.input[inp_idx].script_sig,
-        &tx.input[inp_idx].witness,
-        Sequence::ZERO,
-        LockTime::ZERO,
-    )
-    .unwrap();
-    println!("is_p2pk()\t= {}", script_pubkey.is_p2pk());
-    println!("is_p2pkh()\t= {}", script_pubkey.is_p2pkh());
-    println!("is_v0_p2wpkh()\t= {}", script_pubkey.is_v0_p2wpkh());
-    println!("is_v0_p2wsh()\t= {}", script_pubkey.is_v0_p2wsh());
-    println!("is_v1_p2tr()\t= {}", script_pubkey.is_v1_p2tr());
-    println!("is_p2sh()\t= {}", script_pubkey.is_p2sh());
-    println!(
-        "legacy {} script_code {:?}",
-        interpreter.is_legacy(),
-        interpreter
-            .inferred_descriptor()
-            .unwrap()
-            .script_code()
-            .unwrap()
-    );
-
-    let secp = Secp256k1::new();
-    let outs_vec: Vec<bitcoin::TxOut>;
-    if interpreter.is_segwit_v0() {
-        //prevouts need to be aligned with inputs since miniscript::Interpreter::verify_sig looks for the prevout at inp_idx
-        outs_vec = vec![reftx.output[vout].clone(); inp_idx + 1];
-    } else {
-        outs_vec = vec![];
+/// * `raw_reftx_vec` - funding txs of the spending tx inputs, in any order
+fn vrfy_pks(raw_tx: &[u8], inp_idx: usize, raw_reftx_vec: Vec<&[u8]>) -> InputReport {
+    let tx = decode_tx(raw_tx).unwrap();
+    let reftx_vec: Vec<bitcoin::Transaction> = raw_reftx_vec
+        .into_iter()
+        .map(|raw_reftx| decode_tx(raw_reftx).unwrap())
+        .collect();
+    let report = verify_input_signers(&tx, inp_idx, &reftx_vec).unwrap();
+    println!("inferred_descriptor {}", report.descriptor);
+    for signer in &report.signers {
+        println!(
+            "key #{:?} {}<->\tsig #{:?} {} {} {}",
+            signer.key_index,
+            signer.key,
+            signer.sig_index,
+            signer.signature,
+            signer.sighash_type,
+            signer.verified
+        );
+    }
+    for pk in &report.non_signers {
+        println!("{} did not sign", pk);
+    }
+    if let Some(policy) = &report.policy {
+        println!(
+            "policy {}, satisfied {}",
+            policy.policy,
+            policy.description.as_deref().unwrap_or("no branch")
+        );
     }
-    let prevouts = sighash::Prevouts::All::<bitcoin::TxOut>(&outs_vec);
-    let mut verified_pk_vec = vec![];
-    let iter = interpreter.iter_custom(Box::new(|key_sig: &KeySigPair| {
-        let res = interpreter.verify_sig(&secp, &tx, inp_idx, &prevouts, key_sig);
-        let (pk, ecdsa_sig) = key_sig.as_ecdsa().expect("Ecdsa Sig");
-        //println!(" tx {:?}", &tx);
-        println!("{}<->\t{}", pk, ecdsa_sig.sig);
-        if res {
-            verified_pk_vec.push(pk);
-        }
-        res
-    }));
-
-    for _ in iter {}
-    if verified_pk_vec.len() > 0 {
+    if let Some(cb) = &report.control_block {
+        println!(
+            "control block: internal key {} nums {}, leaf hash {}, depth {}, merkle root {}, commitment verified {}",
+            cb.internal_key,
+            cb.nums_internal_key,
+            cb.leaf_hash,
+            cb.depth(),
+            cb.merkle_root,
+            cb.commitment_verified
+        );
+    }
+    let pks: Vec<String> = report.verified_keys().map(|pk| pk.to_string()).collect();
+    if !pks.is_empty() {
         println!(
             "\nsuccessfully verified {} pks: {}",
-            verified_pk_vec.len(),
-            verified_pk_vec
-                .iter()
-                .map(|n| n.to_string())
-                .collect::<Vec<String>>()
-                .join(", ")
+            pks.len(),
+            pks.join(", ")
         );
     } else {
         println!("\n*** failed to verify pks");
     }
-
-    verified_pk_vec
+    report
 }
 
-fn vrfy_pks_tr(
-    mut raw_tx: &[u8],
-    inp_idx: usize,
-    raw_reftx_vec: Vec<&[u8]>,
-) -> Vec<bitcoin::key::XOnlyPublicKey> {
-    let tx: bitcoin::Transaction =
-        bitcoin::consensus::Decodable::consensus_decode(&mut raw_tx).unwrap();
-    let mut reftx_vec: Vec<bitcoin::Transaction> = vec![];
-    for raw_reftx in raw_reftx_vec {
-        let mut raw: &[u8] = raw_reftx;
-        let reftx = bitcoin::consensus::Decodable::consensus_decode(&mut raw).unwrap();
-        reftx_vec.push(reftx);
+/// Prints the signers of every input of the spending tx
+fn vrfy_tx(raw_tx: &[u8], raw_reftx_vec: Vec<&[u8]>) -> TxReport {
+    let tx = decode_tx(raw_tx).unwrap();
+    let reftx_vec: Vec<bitcoin::Transaction> = raw_reftx_vec
+        .into_iter()
+        .map(|raw_reftx| decode_tx(raw_reftx).unwrap())
+        .collect();
+    let report = verify_tx_signers(&tx, &reftx_vec);
+    println!("\ntx {}", report.txid);
+    for input in &report.inputs {
+        let pks: Vec<String> = input.verified_keys().map(|pk| pk.to_string()).collect();
+        println!("input {}: {}", input.input_index, pks.join(", "));
     }
-    let vout: usize = tx.input[inp_idx].previous_output.vout.try_into().unwrap();
-    let script_pubkey = &reftx_vec[inp_idx].output[vout].script_pubkey;
-    let interpreter = miniscript::Interpreter::from_txdata(
-        script_pubkey,
-        &tx.input[inp_idx].script_sig,
-        &tx.input[inp_idx].witness,
-        Sequence::ZERO,
-        LockTime::ZERO,
-    )
-    .unwrap();
-    println!(
-        "is_v1_p2tr = {} is_taproot_v1_key_spend = {} is_taproot_v1_script_spend = {}",
-        script_pubkey.is_v1_p2tr(),
-        interpreter.is_taproot_v1_key_spend(),
-        interpreter.is_taproot_v1_script_spend()
-    );
-    println!(
-        "inferred_descriptor {:?}",
-        interpreter.inferred_descriptor_string()
-    );
-
-    let secp = Secp256k1::new();
-    let mut outs_vec: Vec<bitcoin::TxOut> = vec![];
-    for (i, reftx) in reftx_vec.iter().enumerate() {
-        let vout: usize = tx.input[i].previous_output.vout.try_into().unwrap();
-        outs_vec.push(reftx.output[vout].clone());
+    for signer in &report.summary.signers {
+        println!("{} signed inputs {:?}", signer.key, signer.inputs);
     }
-    let prevouts = sighash::Prevouts::All::<bitcoin::TxOut>(&outs_vec);
-    let mut verified_pk_vec = vec![];
-    let iter = interpreter.iter_custom(Box::new(|key_sig: &KeySigPair| {
-        let res = interpreter.verify_sig(&secp, &tx, inp_idx, &prevouts, key_sig);
-        let (pk, sig) = key_sig.as_schnorr().expect("Schnorr Sig");
-        //println!(" tx {:?}", &tx);
-        println!("{}<->\t{} {}", pk, sig.sig, res);
-        if res {
-            verified_pk_vec.push(pk);
-        }
-        res
-    }));
-
-    for _ in iter {}
-    if verified_pk_vec.len() > 0 {
+    for unanalyzed in &report.summary.unanalyzed {
         println!(
-            "\nsuccessfully verified {} pks: {}",
-            verified_pk_vec.len(),
-            verified_pk_vec
-                .iter()
-                .map(|n| n.to_string())
-                .collect::<Vec<String>>()
-                .join(", ")
+            "input {} skipped: {}",
+            unanalyzed.input_index, unanalyzed.error
         );
-    } else {
-        println!("\n*** failed to verify pks");
     }
-    verified_pk_vec
+    report
 }
@@ -0,0 +1,437 @@
+//! Bitcoin Core block files (`blocks/blk*.dat`) and signer scans over them.
+//!
+//! Each file is a sequence of `<network magic> <size: u32 LE> <block>`
+//! records, in download order rather than height order. Since Bitcoin Core
+//! 28 the files are XORed with the 8-byte key stored in `blocks/xor.dat`.
+//! The outputs spent by each block come from the undo files next to them,
+//! see [`RevFile`].
+
+mod undo;
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use bitcoin::consensus::deserialize;
+use bitcoin::ecdsa::Signature as EcdsaSignature;
+use bitcoin::{Block, BlockHash, Network, PublicKey, Transaction, TxIn, Txid};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::provider::{resolve_prevouts, FallbackProvider, PrevoutProvider};
+use crate::script::redeem_script;
+#[cfg(feature = "serde")]
+use crate::serde_utils::display_fromstr;
+pub use undo::{BlockUndo, RevFile};
+
+use crate::{verify_input_signers_with, InputReport, MinikeysError, PrevoutMap, SpendType};
+
+/// Size of the obfuscation key of `xor.dat`.
+pub const XOR_KEY_LEN: usize = 8;
+
+/// A `blocks` directory of Bitcoin Core: its block files and their
+/// obfuscation key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlocksDir {
+    path: PathBuf,
+    network: Network,
+    xor_key: [u8; XOR_KEY_LEN],
+}
+
+impl BlocksDir {
+    /// Opens `path` (e.g. `~/.bitcoin/blocks`), reading the key of `xor.dat`
+    /// if there is one. Nodes older than 28 have none and do not obfuscate.
+    pub fn open(path: impl Into<PathBuf>, network: Network) -> Result<Self, MinikeysError> {
+        let path = path.into();
+        let xor_key = match std::fs::read(path.join("xor.dat")) {
+            Ok(key) => key
+                .try_into()
+                .map_err(|_| block_file_err("xor.dat is not an 8-byte key"))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => [0; XOR_KEY_LEN],
+            Err(e) => return Err(block_file_err(format!("xor.dat: {}", e))),
+        };
+        Ok(BlocksDir {
+            path,
+            network,
+            xor_key,
+        })
+    }
+
+    pub fn xor_key(&self) -> [u8; XOR_KEY_LEN] {
+        self.xor_key
+    }
+
+    /// The `blk*.dat` files, in file number order.
+    pub fn block_files(&self) -> Result<Vec<PathBuf>, MinikeysError> {
+        numbered_files(&self.path, "blk")
+    }
+
+    /// Reader of one of the block files.
+    pub fn open_block_file(
+        &self,
+        path: &Path,
+    ) -> Result<BlockFileReader<BufReader<File>>, MinikeysError> {
+        BlockFileReader::open(path, self.network, self.xor_key)
+    }
+
+    /// The `rev*.dat` files, in file number order.
+    pub fn rev_files(&self) -> Result<Vec<PathBuf>, MinikeysError> {
+        numbered_files(&self.path, "rev")
+    }
+
+    /// Every block of every block file, file after file.
+    pub fn blocks(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<Block, MinikeysError>>, MinikeysError> {
+        let dir = self.clone();
+        let files = self.block_files()?;
+        Ok(files
+            .into_iter()
+            .flat_map(move |path| match dir.open_block_file(&path) {
+                Ok(reader) => Box::new(reader) as Box<dyn Iterator<Item = _>>,
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            }))
+    }
+}
+
+impl BlocksDir {
+    /// Every block of every block file with its undo record, `None` for
+    /// blocks without one (the genesis block, blocks never connected). The
+    /// undo records of a file are loaded when its first block is read.
+    pub fn blocks_with_undo(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<(Block, Option<BlockUndo>), MinikeysError>>,
+        MinikeysError,
+    > {
+        let dir = self.clone();
+        let files = self.block_files()?;
+        Ok(files.into_iter().flat_map(move |path| {
+            let opened = dir.open_block_file(&path).and_then(|reader| {
+                let rev_file = undo::rev_file_of(&path, dir.network, dir.xor_key)?;
+                Ok((reader, rev_file.unwrap_or_default()))
+            });
+            match opened {
+                Ok((reader, mut rev_file)) => Box::new(reader.map(move |block| {
+                    let block = block?;
+                    let undo = rev_file.take(&block);
+                    Ok((block, undo))
+                })) as Box<dyn Iterator<Item = _>>,
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            }
+        }))
+    }
+
+    /// Scans every block that has undo data with [`scan_block`], the undo
+    /// data being the prevout provider: no transaction index or node needed.
+    pub fn scan(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<Vec<ScanRecord>, MinikeysError>>, MinikeysError> {
+        Ok(self.blocks_with_undo()?.filter_map(|next| {
+            let (block, undo) = match next {
+                Ok((block, Some(undo))) => (block, undo),
+                Ok((_, None)) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+            Some(
+                undo.prevout_map(&block)
+                    .map(|prevout_map| scan_block(&block, &prevout_map)),
+            )
+        }))
+    }
+}
+
+/// The files of `dir` named `<prefix>NNNNN.dat`, sorted.
+pub(crate) fn numbered_files(dir: &Path, prefix: &str) -> Result<Vec<PathBuf>, MinikeysError> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| block_file_err(format!("{}: {}", dir.display(), e)))?;
+    let mut files = vec![];
+    for entry in entries {
+        let path = entry.map_err(block_file_err)?.path();
+        let numbered = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(prefix)?.strip_suffix(".dat"))
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+        if numbered {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Undoes the XOR obfuscation of Bitcoin Core data files, whose key is
+/// applied by position in the file.
+#[derive(Debug)]
+pub struct XorReader<R> {
+    inner: R,
+    key: [u8; XOR_KEY_LEN],
+    offset: u64,
+}
+
+impl<R: Read> XorReader<R> {
+    /// Reads `inner` from the start of its file.
+    pub fn new(inner: R, key: [u8; XOR_KEY_LEN]) -> Self {
+        XorReader {
+            inner,
+            key,
+            offset: 0,
+        }
+    }
+
+    /// Position in the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl<R: Read> Read for XorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for (i, byte) in buf[..n].iter_mut().enumerate() {
+            *byte ^= self.key[((self.offset + i as u64) % XOR_KEY_LEN as u64) as usize];
+        }
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+/// The `<magic> <size> <payload>` records of a block or undo file.
+#[derive(Debug)]
+pub(crate) struct Records<R> {
+    pub(crate) reader: XorReader<R>,
+    magic: [u8; 4],
+}
+
+impl<R: Read> Records<R> {
+    pub(crate) fn new(reader: R, network: Network, xor_key: [u8; XOR_KEY_LEN]) -> Self {
+        Records {
+            reader: XorReader::new(reader, xor_key),
+            magic: network.magic().to_bytes(),
+        }
+    }
+
+    /// The payload of the next record, `None` at the end of the file or at
+    /// the zeroed space Bitcoin Core preallocates past the last record.
+    pub(crate) fn next_payload(&mut self) -> Result<Option<Vec<u8>>, MinikeysError> {
+        let mut magic = [0; 4];
+        match read_exact_or_eof(&mut self.reader, &mut magic)? {
+            false => return Ok(None),
+            true if magic == [0; 4] => return Ok(None),
+            true if magic != self.magic => {
+                return Err(block_file_err(format!(
+                    "unexpected magic {} at offset {}",
+                    hex::encode(magic),
+                    self.reader.offset() - 4
+                )))
+            }
+            true => {}
+        }
+        let mut size = [0; 4];
+        self.reader.read_exact(&mut size).map_err(block_file_err)?;
+        let mut payload = vec![0; u32::from_le_bytes(size) as usize];
+        self.reader
+            .read_exact(&mut payload)
+            .map_err(block_file_err)?;
+        Ok(Some(payload))
+    }
+}
+
+/// Opens a block or undo file for reading.
+pub(crate) fn open_file(path: &Path) -> Result<BufReader<File>, MinikeysError> {
+    let file =
+        File::open(path).map_err(|e| block_file_err(format!("{}: {}", path.display(), e)))?;
+    Ok(BufReader::new(file))
+}
+
+/// Iterator over the blocks of one `blk*.dat` file.
+///
+/// Stops at the end of the file or at the zeroed space Bitcoin Core
+/// preallocates past the last block.
+#[derive(Debug)]
+pub struct BlockFileReader<R> {
+    records: Records<R>,
+    done: bool,
+}
+
+impl BlockFileReader<BufReader<File>> {
+    pub fn open(
+        path: &Path,
+        network: Network,
+        xor_key: [u8; XOR_KEY_LEN],
+    ) -> Result<Self, MinikeysError> {
+        Ok(Self::new(open_file(path)?, network, xor_key))
+    }
+}
+
+impl<R: Read> BlockFileReader<R> {
+    pub fn new(reader: R, network: Network, xor_key: [u8; XOR_KEY_LEN]) -> Self {
+        BlockFileReader {
+            records: Records::new(reader, network, xor_key),
+            done: false,
+        }
+    }
+
+    /// The next block, `None` at the end of the file.
+    fn next_block(&mut self) -> Result<Option<Block>, MinikeysError> {
+        let Some(raw) = self.records.next_payload()? else {
+            return Ok(None);
+        };
+        deserialize(&raw)
+            .map(Some)
+            .map_err(|e| block_file_err(format!("invalid block: {}", e)))
+    }
+}
+
+impl<R: Read> Iterator for BlockFileReader<R> {
+    type Item = Result<Block, MinikeysError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_block().transpose();
+        // Records cannot be resynchronized after a bad one
+        self.done = !matches!(next, Some(Ok(_)));
+        next
+    }
+}
+
+/// Fills `buf`, or returns false if the reader is at its end.
+pub(crate) fn read_exact_or_eof(
+    reader: &mut impl Read,
+    buf: &mut [u8],
+) -> Result<bool, MinikeysError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(block_file_err("truncated record")),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(block_file_err(e)),
+        }
+    }
+    Ok(true)
+}
+
+pub(crate) fn block_file_err(e: impl std::fmt::Display) -> MinikeysError {
+    MinikeysError::BlockFile(e.to_string())
+}
+
+/// Signer attribution of one input met by a scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScanRecord {
+    #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+    pub block_hash: BlockHash,
+    #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+    pub txid: Txid,
+    pub input_index: usize,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub result: ScanResult,
+}
+
+/// The report of a scanned input, or why it could not be analyzed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ScanResult {
+    /// Boxed, reports being much larger than errors.
+    Report(Box<InputReport>),
+    Error(MinikeysError),
+}
+
+/// Reports the signers of the multisig/miniscript inputs of `block`.
+///
+/// Inputs that plainly spend a single key (P2PKH, P2WPKH and their P2SH
+/// wrapping, taproot key path, P2PK) are skipped without resolving their
+/// prevouts; the others are verified and kept unless the spent output turns
+/// out to be single-key after all. Outputs created earlier in the block are
+/// resolved from the block itself, all others from `provider`.
+pub fn scan_block<P: PrevoutProvider + ?Sized>(block: &Block, provider: &P) -> Vec<ScanRecord> {
+    let in_block = PrevoutMap::from_txs(&block.txdata);
+    let provider = FallbackProvider::new(&in_block, provider);
+    let block_hash = block.block_hash();
+    let mut records = vec![];
+    for tx in block.txdata.iter().filter(|tx| !tx.is_coin_base()) {
+        records.extend(
+            scan_tx(tx, &provider)
+                .into_iter()
+                .map(|(input_index, result)| ScanRecord {
+                    block_hash,
+                    txid: tx.txid(),
+                    input_index,
+                    result,
+                }),
+        );
+    }
+    records
+}
+
+/// The scanned inputs of `tx` and their results, in input order.
+pub(crate) fn scan_tx<P: PrevoutProvider + ?Sized>(
+    tx: &Transaction,
+    provider: &P,
+) -> Vec<(usize, ScanResult)> {
+    let inputs: Vec<usize> = (0..tx.input.len())
+        .filter(|&i| !reveals_single_key(&tx.input[i]))
+        .collect();
+    if inputs.is_empty() {
+        return vec![];
+    }
+    let prevout_map = match resolve_prevouts(provider, tx, &inputs) {
+        Ok(prevout_map) => prevout_map,
+        Err(e) => {
+            return inputs
+                .into_iter()
+                .map(|i| (i, ScanResult::Error(e.clone())))
+                .collect()
+        }
+    };
+    inputs
+        .into_iter()
+        .filter_map(|i| match verify_input_signers_with(tx, i, &prevout_map) {
+            Ok(report) if is_single_key(report.spend_type) => None,
+            Ok(report) => Some((i, ScanResult::Report(Box::new(report)))),
+            Err(e) => Some((i, ScanResult::Error(e))),
+        })
+        .collect()
+}
+
+/// Whether the scriptSig and witness alone show a single-key spend.
+fn reveals_single_key(txin: &TxIn) -> bool {
+    let last_push = redeem_script(txin).map(|s| s.as_bytes());
+    let witness: Vec<&[u8]> = txin.witness.iter().collect();
+    match (txin.script_sig.instructions().count(), witness.as_slice()) {
+        // P2PK
+        (1, []) => last_push.is_some_and(|sig| EcdsaSignature::from_slice(sig).is_ok()),
+        // P2PKH
+        (2, []) => last_push.is_some_and(is_pubkey),
+        // P2WPKH, P2SH-P2WPKH
+        (0, [_, key]) => is_pubkey(key),
+        (1, [_, key]) => redeem_script(txin).is_some_and(|s| s.is_v0_p2wpkh()) && is_pubkey(key),
+        // Taproot key path
+        (0, [sig]) => sig.len() == 64 || sig.len() == 65,
+        _ => false,
+    }
+}
+
+fn is_pubkey(bytes: &[u8]) -> bool {
+    PublicKey::from_slice(bytes).is_ok()
+}
+
+fn is_single_key(spend_type: SpendType) -> bool {
+    matches!(
+        spend_type,
+        SpendType::P2pk
+            | SpendType::P2pkh
+            | SpendType::P2wpkh
+            | SpendType::P2shP2wpkh
+            | SpendType::TaprootKeyPath
+    )
+}
@@ -0,0 +1,196 @@
+//! Bitcoin Core undo files (`blocks/rev*.dat`): the outputs spent by the
+//! transactions of each connected block.
+//!
+//! A `rev` file holds the undo records of the blocks of the `blk` file with
+//! the same number, though not necessarily in the same order. Each record is
+//! followed by the double SHA256 of the previous block hash and the record,
+//! which is what ties it to its block.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::Path;
+
+use bitcoin::hashes::{sha256d, Hash, HashEngine};
+use bitcoin::{Block, Network, TxOut};
+
+use super::{block_file_err, open_file, Records, XOR_KEY_LEN};
+use crate::compress::{read_compact_size, read_compressed_txout, read_varint};
+use crate::{MinikeysError, PrevoutMap};
+
+/// The outputs spent by a block: for each transaction but the coinbase, the
+/// outputs spent by its inputs, in input order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockUndo {
+    pub spent_outputs: Vec<Vec<TxOut>>,
+}
+
+impl BlockUndo {
+    /// Decodes a serialized `CBlockUndo`.
+    pub fn decode(raw: &[u8]) -> Result<Self, MinikeysError> {
+        let mut reader = raw;
+        let undo = decode_block_undo(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(block_file_err("trailing data after undo record"));
+        }
+        Ok(undo)
+    }
+
+    /// Whether the undo record has one spent output per input of `block`.
+    pub fn fits(&self, block: &Block) -> bool {
+        self.shape() == block_shape(block)
+    }
+
+    /// The spent outputs indexed by the outpoints of `block`'s inputs.
+    pub fn prevout_map(&self, block: &Block) -> Result<PrevoutMap, MinikeysError> {
+        if !self.fits(block) {
+            return Err(block_file_err(format!(
+                "undo data does not match block {}",
+                block.block_hash()
+            )));
+        }
+        let mut map = PrevoutMap::new();
+        for (tx, txouts) in block.txdata.iter().skip(1).zip(&self.spent_outputs) {
+            for (txin, txout) in tx.input.iter().zip(txouts) {
+                map.insert(txin.previous_output, txout.clone());
+            }
+        }
+        Ok(map)
+    }
+
+    fn shape(&self) -> Vec<usize> {
+        self.spent_outputs.iter().map(Vec::len).collect()
+    }
+}
+
+/// Input counts of the non-coinbase transactions of `block`.
+fn block_shape(block: &Block) -> Vec<usize> {
+    block
+        .txdata
+        .iter()
+        .skip(1)
+        .map(|tx| tx.input.len())
+        .collect()
+}
+
+/// The undo records of one `rev*.dat` file, to be matched with the blocks of
+/// the corresponding `blk*.dat` file.
+#[derive(Debug, Clone, Default)]
+pub struct RevFile {
+    /// Records not taken yet, by shape.
+    records: HashMap<Vec<usize>, Vec<UndoRecord>>,
+}
+
+#[derive(Debug, Clone)]
+struct UndoRecord {
+    undo: BlockUndo,
+    raw: Vec<u8>,
+    checksum: sha256d::Hash,
+}
+
+impl RevFile {
+    /// Reads every record of the file at `path`.
+    pub fn open(
+        path: &Path,
+        network: Network,
+        xor_key: [u8; XOR_KEY_LEN],
+    ) -> Result<Self, MinikeysError> {
+        Self::read(open_file(path)?, network, xor_key)
+    }
+
+    /// Reads every record of `reader`, positioned at the start of the file.
+    pub fn read<R: Read>(
+        reader: R,
+        network: Network,
+        xor_key: [u8; XOR_KEY_LEN],
+    ) -> Result<Self, MinikeysError> {
+        let mut records = Records::new(reader, network, xor_key);
+        let mut rev_file = RevFile::default();
+        while let Some(raw) = records.next_payload()? {
+            let mut checksum = [0; 32];
+            records
+                .reader
+                .read_exact(&mut checksum)
+                .map_err(block_file_err)?;
+            let undo = BlockUndo::decode(&raw)?;
+            rev_file
+                .records
+                .entry(undo.shape())
+                .or_default()
+                .push(UndoRecord {
+                    undo,
+                    raw,
+                    checksum: sha256d::Hash::from_byte_array(checksum),
+                });
+        }
+        Ok(rev_file)
+    }
+
+    /// Number of records not taken yet.
+    pub fn len(&self) -> usize {
+        self.records.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes and returns the undo record of `block`, `None` if the file
+    /// has none (the genesis block, blocks never connected).
+    pub fn take(&mut self, block: &Block) -> Option<BlockUndo> {
+        let candidates = self.records.get_mut(&block_shape(block))?;
+        let position = candidates.iter().position(|record| {
+            let mut engine = sha256d::Hash::engine();
+            engine.input(block.header.prev_blockhash.as_byte_array());
+            engine.input(&record.raw);
+            sha256d::Hash::from_engine(engine) == record.checksum
+        })?;
+        Some(candidates.swap_remove(position).undo)
+    }
+}
+
+/// Opens the `rev` file of the `blk` file at `block_file`, if it exists.
+pub(crate) fn rev_file_of(
+    block_file: &Path,
+    network: Network,
+    xor_key: [u8; XOR_KEY_LEN],
+) -> Result<Option<RevFile>, MinikeysError> {
+    let Some(name) = block_file.file_name().and_then(|n| n.to_str()) else {
+        return Ok(None);
+    };
+    let Some(number) = name.strip_prefix("blk") else {
+        return Ok(None);
+    };
+    let path = block_file.with_file_name(format!("rev{}", number));
+    if !path.exists() {
+        return Ok(None);
+    }
+    RevFile::open(&path, network, xor_key).map(Some)
+}
+
+fn decode_block_undo(r: &mut &[u8]) -> Result<BlockUndo, MinikeysError> {
+    let txs = read_compact_size(r).map_err(undo_err)?;
+    let mut spent_outputs = Vec::with_capacity(txs.min(10_000) as usize);
+    for _ in 0..txs {
+        let coins = read_compact_size(r).map_err(undo_err)?;
+        let mut txouts = Vec::with_capacity(coins.min(10_000) as usize);
+        for _ in 0..coins {
+            txouts.push(decode_coin(r).map_err(undo_err)?);
+        }
+        spent_outputs.push(txouts);
+    }
+    Ok(BlockUndo { spent_outputs })
+}
+
+/// A spent coin: height and coinbase flag, then the compressed output.
+fn decode_coin(r: &mut &[u8]) -> io::Result<TxOut> {
+    let code = read_varint(r)?;
+    if code >> 1 > 0 {
+        // Transaction version of old undo formats, now always 0
+        read_varint(r)?;
+    }
+    read_compressed_txout(r)
+}
+
+fn undo_err(e: io::Error) -> MinikeysError {
+    block_file_err(format!("invalid undo record: {}", e))
+}
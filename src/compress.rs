@@ -0,0 +1,121 @@
+//! Bitcoin Core's compact serialization of coins, shared by undo files and
+//! UTXO snapshots.
+
+use std::io::{self, Read};
+
+use bitcoin::consensus::encode::VarInt;
+use bitcoin::consensus::Decodable;
+use bitcoin::hashes::Hash;
+use bitcoin::key::PublicKey;
+use bitcoin::{PubkeyHash, ScriptBuf, ScriptHash, TxOut};
+
+/// Scripts longer than this are stored as their length only.
+const MAX_SCRIPT_SIZE: u64 = 10_000;
+
+/// Bitcoin Core's `VARINT`: base 128, most significant group first, each
+/// continuation adding one.
+pub(crate) fn read_varint<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut n: u64 = 0;
+    loop {
+        let [b] = read_array(r)?;
+        if n > u64::MAX >> 7 {
+            return Err(invalid_data("VARINT overflow"));
+        }
+        n = (n << 7) | u64::from(b & 0x7f);
+        if b & 0x80 == 0 {
+            return Ok(n);
+        }
+        n = n
+            .checked_add(1)
+            .ok_or_else(|| invalid_data("VARINT overflow"))?;
+    }
+}
+
+/// The `CompactSize` of consensus serialization.
+pub(crate) fn read_compact_size<R: Read>(r: &mut R) -> io::Result<u64> {
+    VarInt::consensus_decode(r)
+        .map(|v| v.0)
+        .map_err(|e| invalid_data(e.to_string()))
+}
+
+/// A `TxOutCompression` output: compressed amount, then compressed script.
+pub(crate) fn read_compressed_txout<R: Read>(r: &mut R) -> io::Result<TxOut> {
+    let value = decompress_amount(read_varint(r)?);
+    let script_pubkey = read_compressed_script(r)?;
+    Ok(TxOut {
+        value,
+        script_pubkey,
+    })
+}
+
+/// Bitcoin Core's `CompressAmount` in reverse.
+fn decompress_amount(x: u64) -> u64 {
+    if x == 0 {
+        return 0;
+    }
+    let mut x = x - 1;
+    let mut e = x % 10;
+    x /= 10;
+    let mut n = if e < 9 {
+        let d = x % 9 + 1;
+        x /= 9;
+        x * 10 + d
+    } else {
+        x + 1
+    };
+    while e > 0 {
+        n = n.wrapping_mul(10);
+        e -= 1;
+    }
+    n
+}
+
+/// A `ScriptCompression` script: sizes 0 to 5 stand for P2PKH, P2SH and
+/// P2PK scripts stored as their hash or key, others for raw scripts of
+/// 6 bytes less.
+fn read_compressed_script<R: Read>(r: &mut R) -> io::Result<ScriptBuf> {
+    let size = read_varint(r)?;
+    match size {
+        0 => Ok(ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array(
+            read_array(r)?,
+        ))),
+        1 => Ok(ScriptBuf::new_p2sh(&ScriptHash::from_byte_array(
+            read_array(r)?,
+        ))),
+        2..=5 => {
+            let x: [u8; 32] = read_array(r)?;
+            let mut compressed = [0; 33];
+            compressed[0] = if size < 4 { size as u8 } else { size as u8 - 2 };
+            compressed[1..].copy_from_slice(&x);
+            let mut key = PublicKey::from_slice(&compressed)
+                .map_err(|e| invalid_data(format!("invalid P2PK key: {}", e)))?;
+            key.compressed = size < 4;
+            Ok(ScriptBuf::new_p2pk(&key))
+        }
+        _ => {
+            let len = size - 6;
+            if len > MAX_SCRIPT_SIZE {
+                // Unspendable, only the length was kept
+                if io::copy(&mut r.take(len), &mut io::sink())? != len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                return Ok(ScriptBuf::from(vec![
+                    bitcoin::opcodes::all::OP_RETURN.to_u8()
+                ]));
+            }
+            let mut script = vec![0; len as usize];
+            r.read_exact(&mut script)?;
+            Ok(ScriptBuf::from(script))
+        }
+    }
+}
+
+pub(crate) fn read_array<R: Read, const N: usize>(r: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn invalid_data(e: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.into())
+}
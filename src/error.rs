@@ -0,0 +1,153 @@
+//! Errors returned by the minikeys API.
+
+use std::fmt;
+
+use bitcoin::{OutPoint, ScriptBuf, Txid};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "serde")]
+use crate::serde_utils::{display_fromstr, script_hex};
+
+/// Everything that can go wrong while attributing the signers of an input.
+///
+/// Errors coming from rust-bitcoin and rust-miniscript are kept as their
+/// display message so that the enum stays `Clone` and comparable and can be
+/// stored inside per-input reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "kind", content = "details", rename_all = "snake_case")
+)]
+pub enum MinikeysError {
+    /// A raw transaction could not be consensus-decoded.
+    TxDecode(String),
+    /// The requested input does not exist in the spending transaction.
+    InputIndexOutOfRange { index: usize, inputs: usize },
+    /// No funding transaction was supplied for the outpoint spent by an input.
+    MissingPrevout {
+        input_index: usize,
+        #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+        outpoint: OutPoint,
+    },
+    /// The funding transaction supplied for an input, as a PSBT
+    /// `non_witness_utxo`, is not the one its outpoint references.
+    PrevoutTxidMismatch {
+        input_index: usize,
+        #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+        expected: Txid,
+        #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+        found: Txid,
+    },
+    /// The supplied funding transaction has no output at the spent vout.
+    PrevoutMismatch {
+        input_index: usize,
+        #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+        outpoint: OutPoint,
+    },
+    /// The miniscript interpreter could not be built for the input.
+    Interpreter { input_index: usize, reason: String },
+    /// The spent scriptPubKey can never be interpreted (unknown witness
+    /// version, OP_RETURN, ...).
+    UnsupportedScript {
+        input_index: usize,
+        #[cfg_attr(feature = "serde", serde(with = "script_hex"))]
+        script_pubkey: ScriptBuf,
+    },
+    /// An ECDSA signature showed up in a taproot spend or a Schnorr signature
+    /// in a pre-taproot spend.
+    SignatureTypeMismatch { input_index: usize, taproot: bool },
+    /// Only the amount of the spent output was supplied and the input does
+    /// not reveal enough to derive its scriptPubKey.
+    UnderivableScriptPubKey { input_index: usize },
+    /// A [`PrevoutProvider`](crate::PrevoutProvider) failed to look up
+    /// spent outputs (I/O, server or parse error).
+    Provider(String),
+    /// Verbose transaction or block JSON from Bitcoin Core could not be used.
+    NodeJson(String),
+    /// A Bitcoin Core block file could not be read or parsed.
+    BlockFile(String),
+}
+
+impl fmt::Display for MinikeysError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MinikeysError::TxDecode(e) => write!(f, "failed to decode transaction: {}", e),
+            MinikeysError::InputIndexOutOfRange { index, inputs } => write!(
+                f,
+                "input index {} out of range, transaction has {} inputs",
+                index, inputs
+            ),
+            MinikeysError::MissingPrevout {
+                input_index,
+                outpoint,
+            } => write!(
+                f,
+                "input {}: no funding transaction supplied for {}",
+                input_index, outpoint
+            ),
+            MinikeysError::PrevoutTxidMismatch {
+                input_index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "input {}: spends an output of {} but funding transaction {} was supplied",
+                input_index, expected, found
+            ),
+            MinikeysError::PrevoutMismatch {
+                input_index,
+                outpoint,
+            } => write!(
+                f,
+                "input {}: funding transaction has no output {}",
+                input_index, outpoint
+            ),
+            MinikeysError::Interpreter {
+                input_index,
+                reason,
+            } => write!(f, "input {}: interpreter error: {}", input_index, reason),
+            MinikeysError::UnsupportedScript {
+                input_index,
+                script_pubkey,
+            } => write!(
+                f,
+                "input {}: unsupported scriptPubKey {}",
+                input_index, script_pubkey
+            ),
+            MinikeysError::SignatureTypeMismatch {
+                input_index,
+                taproot: true,
+            } => write!(
+                f,
+                "input {}: ECDSA signature in a taproot spend",
+                input_index
+            ),
+            MinikeysError::SignatureTypeMismatch {
+                input_index,
+                taproot: false,
+            } => write!(
+                f,
+                "input {}: Schnorr signature in a pre-taproot spend",
+                input_index
+            ),
+            MinikeysError::UnderivableScriptPubKey { input_index } => write!(
+                f,
+                "input {}: scriptPubKey cannot be derived from the input, supply it",
+                input_index
+            ),
+            MinikeysError::Provider(e) => write!(f, "prevout provider error: {}", e),
+            MinikeysError::NodeJson(e) => write!(f, "invalid node JSON: {}", e),
+            MinikeysError::BlockFile(e) => write!(f, "block file error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MinikeysError {}
+
+impl From<bitcoin::consensus::encode::Error> for MinikeysError {
+    fn from(e: bitcoin::consensus::encode::Error) -> Self {
+        MinikeysError::TxDecode(e.to_string())
+    }
+}
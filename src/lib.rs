@@ -0,0 +1,56 @@
+//! Find which keys signed a multisig (or any miniscript) input of a confirmed transaction.
+//!
+//! The spent script is rebuilt by the miniscript interpreter from the input's
+//! scriptSig/witness and the scriptPubKey of the output it spends. Every
+//! key/signature pair met during interpretation is checked against the
+//! transaction sighash, and the keys whose signatures verify are reported.
+
+pub mod blockfile;
+mod compress;
+mod error;
+#[cfg(feature = "serde")]
+mod node_json;
+mod policy;
+mod prevout;
+pub mod provider;
+mod report;
+mod script;
+#[cfg(feature = "serde")]
+mod serde_utils;
+mod taproot;
+mod verify;
+
+use bitcoin::Transaction;
+
+pub use error::MinikeysError;
+#[cfg(feature = "serde")]
+pub use node_json::{parse_verbose, parse_verbose_block, parse_verbose_tx, TxWithPrevouts};
+pub use policy::PolicyReport;
+pub use prevout::{derive_script_pubkey, PrevoutMap, SighashPrevouts};
+#[cfg(feature = "async")]
+pub use provider::AsyncPrevoutProvider;
+pub use provider::PrevoutProvider;
+pub use report::{
+    InputReport, SighashType, SignerKey, SignerReport, SignerSignature, SpendType, TxReport,
+    TxSigner, TxSummary, UnanalyzedInput,
+};
+pub use taproot::{
+    find_musig_commitment, find_taproot_commitment, is_nums_key, musig_key_agg, musig_key_sort,
+    ControlBlockReport, KeyOrdering, MusigAggregation, PartialTapTree, TapTreeNode, TapTrees,
+    TaprootCommitment, MAX_PERMUTED_PARTICIPANTS, NUMS_INTERNAL_KEY,
+};
+pub use verify::{
+    verify_input_signers, verify_input_signers_from, verify_input_signers_with, verify_tx_signers,
+    verify_tx_signers_from, verify_tx_signers_with,
+};
+#[cfg(feature = "async")]
+pub use verify::{verify_input_signers_from_async, verify_tx_signers_from_async};
+
+/// Version of the JSON representation of reports, bumped on any
+/// incompatible change to it (see JSON_SCHEMA.md).
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Decodes a consensus-serialized transaction.
+pub fn decode_tx(raw_tx: &[u8]) -> Result<Transaction, MinikeysError> {
+    Ok(bitcoin::consensus::deserialize(raw_tx)?)
+}
@@ -1 +1,809 @@
-fn main() {}
+//! minikeys command line: report which keys signed the inputs of a transaction.
+//!
+//! Transactions are given as hex on the command line, as `@file` (hex text or
+//! raw binary) or as `-` for stdin.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use bitcoin::consensus::Decodable;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::psbt::{self, PartiallySignedTransaction};
+use bitcoin::secp256k1::{Parity, PublicKey};
+use bitcoin::taproot::TapNodeHash;
+use bitcoin::{Amount, Network, OutPoint, ScriptBuf, Transaction, TxOut};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use minikeys::blockfile::{BlocksDir, ScanRecord, ScanResult};
+use minikeys::provider::SnapshotProvider;
+use minikeys::{
+    ControlBlockReport, InputReport, KeyOrdering, PartialTapTree, PrevoutMap, PrevoutProvider,
+    TapTrees, TxReport,
+};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// At least one signature verified (`taptree`: a script tree was found).
+const EXIT_VERIFIED: u8 = 0;
+/// The input(s) could be analyzed but no signature verified (`taptree`: no
+/// script path spend).
+const EXIT_NOT_VERIFIED: u8 = 1;
+/// Bad arguments, undecodable data or an input that cannot be analyzed.
+const EXIT_ERROR: u8 = 2;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Find which keys signed multisig/miniscript transaction inputs"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Output format; JSON follows the versioned schema of JSON_SCHEMA.md
+    #[arg(long, value_enum, global = true, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Report the signers of one input
+    Input {
+        #[command(flatten)]
+        txs: TxArgs,
+        /// Index of the input to analyze
+        #[arg(short, long, default_value_t = 0)]
+        index: usize,
+    },
+    /// Report the signers of every input
+    Tx {
+        #[command(flatten)]
+        txs: TxArgs,
+    },
+    /// Report the signers of every input of a finalized PSBT. The internal
+    /// key and merkle root of its taproot inputs are candidates besides
+    /// `--internal-key` and `--merkle-root`
+    Psbt {
+        /// Base64 or hex PSBT, `@file` or `-` for stdin
+        psbt: String,
+        #[command(flatten)]
+        key_path: KeyPathArgs,
+    },
+    /// Report the signers of every input of the output of `bitcoin-cli
+    /// getrawtransaction <txid> 3` or `bitcoin-cli getblock <hash> 3`
+    #[cfg(feature = "serde")]
+    NodeJson {
+        /// Verbose JSON, `@file` or `-` for stdin
+        json: String,
+    },
+    /// Report the signers of every multisig/miniscript input of the blocks of
+    /// a Bitcoin Core blocks directory, the spent outputs coming from its
+    /// undo files. Prints one line per input as it goes
+    Scan {
+        /// Blocks directory, e.g. ~/.bitcoin/blocks
+        blocks_dir: PathBuf,
+        /// Network the blocks belong to
+        #[arg(long, default_value_t = Network::Bitcoin)]
+        network: Network,
+    },
+    /// Rebuild the script trees of taproot outputs from the leaves and merkle
+    /// paths revealed by their script path spends
+    Taptree {
+        /// Spending transaction(s): hex, `@file` or `-` for stdin. Files and
+        /// stdin may hold several whitespace-separated hex transactions
+        #[arg(required = true)]
+        txs: Vec<String>,
+        /// Funding transaction(s) of the spent outputs, checked against the
+        /// control blocks. Without them the output is the one each control
+        /// block commits to
+        #[arg(short, long = "prevout")]
+        prevouts: Vec<String>,
+        /// Print the trees as Graphviz DOT diagrams
+        #[arg(long)]
+        dot: bool,
+    },
+}
+
+#[derive(Args)]
+struct TxArgs {
+    /// Spending transaction: hex, `@file` or `-` for stdin
+    tx: String,
+    /// Funding transaction(s): hex, `@file` or `-` for stdin. Files and stdin
+    /// may hold several whitespace-separated hex transactions
+    #[arg(short, long = "prevout")]
+    prevouts: Vec<String>,
+    /// Spent output of an input without its funding transaction, as
+    /// `<INPUT>:<SATS>[:<SCRIPT_PUBKEY_HEX>]`. The scriptPubKey can be left out
+    /// when the input reveals it (P2PKH, P2SH, P2WPKH, P2WSH, taproot script
+    /// path)
+    #[arg(short, long = "spent", value_name = "INPUT:SATS[:SCRIPT]")]
+    spent: Vec<String>,
+    /// UTXO set snapshot of `bitcoin-cli dumptxoutset`, looked up for the
+    /// spent outputs still missing
+    #[arg(long, value_name = "FILE")]
+    utxo_snapshot: Option<PathBuf>,
+    /// Network of the UTXO snapshot
+    #[arg(long, default_value_t = Network::Bitcoin)]
+    network: Network,
+    #[command(flatten)]
+    key_path: KeyPathArgs,
+}
+
+/// Candidates of what the output key of taproot key path spends commits to.
+#[derive(Args)]
+struct KeyPathArgs {
+    /// Candidate internal key (x-only hex) of taproot key path spends,
+    /// reported if the output key commits to it
+    #[arg(long = "internal-key", value_name = "KEY")]
+    internal_keys: Vec<XOnlyPublicKey>,
+    /// Candidate script tree merkle root of taproot key path spends, tried
+    /// with each internal key besides no script tree
+    #[arg(long = "merkle-root", value_name = "HASH")]
+    merkle_roots: Vec<TapNodeHash>,
+    /// Candidate MuSig2 participant (compressed hex key) of taproot key path
+    /// spends; the output key is checked against the BIP327 aggregate of all
+    /// of them, in every order
+    #[arg(long = "musig-key", value_name = "KEY")]
+    musig_keys: Vec<PublicKey>,
+    /// Only aggregate the MuSig2 participants in sorted (KeySort) order
+    #[arg(long)]
+    musig_sorted: bool,
+}
+
+/// Result of a command, printed according to `--format`.
+#[cfg_attr(feature = "serde", derive(Serialize), serde(untagged))]
+enum Output {
+    Input(InputReport),
+    Tx(TxReport),
+    #[cfg(feature = "serde")]
+    Txs(Vec<TxReport>),
+    Trees(Vec<TreeReport>),
+}
+
+impl Output {
+    /// Whether the command exits with `EXIT_VERIFIED`.
+    fn succeeded(&self) -> bool {
+        match self {
+            Output::Input(report) => report.has_verified_signer(),
+            Output::Tx(report) => report.inputs.iter().any(|i| i.has_verified_signer()),
+            #[cfg(feature = "serde")]
+            Output::Txs(reports) => reports
+                .iter()
+                .any(|r| r.inputs.iter().any(|i| i.has_verified_signer())),
+            Output::Trees(trees) => !trees.is_empty(),
+        }
+    }
+}
+
+/// A script tree rebuilt by `taptree`.
+#[cfg_attr(feature = "serde", derive(Serialize))]
+struct TreeReport {
+    internal_key: String,
+    merkle_root: String,
+    output_key: String,
+    complete: bool,
+    leaves: Vec<TreeLeaf>,
+    hidden: Vec<TreeHidden>,
+    descriptor: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    dot: Option<String>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize))]
+struct TreeLeaf {
+    depth: usize,
+    leaf_version: u8,
+    /// Hex, for JSON; text output prints `asm`
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    script: String,
+    asm: String,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize))]
+struct TreeHidden {
+    depth: usize,
+    hash: String,
+}
+
+impl TreeReport {
+    fn new(tree: &PartialTapTree, dot: bool) -> Self {
+        TreeReport {
+            internal_key: tree.internal_key().to_string(),
+            merkle_root: tree.merkle_root().to_string(),
+            output_key: tree.output_key().to_string(),
+            complete: tree.is_complete(),
+            leaves: tree
+                .leaves()
+                .into_iter()
+                .map(|(depth, script, version)| TreeLeaf {
+                    depth,
+                    leaf_version: version.to_consensus(),
+                    script: hex::encode(script.as_bytes()),
+                    asm: script.to_asm_string(),
+                })
+                .collect(),
+            hidden: tree
+                .hidden()
+                .into_iter()
+                .map(|(depth, hash)| TreeHidden {
+                    depth,
+                    hash: hash.to_string(),
+                })
+                .collect(),
+            descriptor: tree.descriptor(),
+            dot: dot.then(|| tree.to_dot()),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let format = cli.format;
+    let command = cli.command.name();
+    if let Command::Scan {
+        blocks_dir,
+        network,
+    } = &cli.command
+    {
+        return scan(blocks_dir, *network, format);
+    }
+    match run(cli) {
+        Ok(output) => {
+            let verified = output.succeeded();
+            let printed = match format {
+                Format::Text => {
+                    print_text(&output);
+                    Ok(())
+                }
+                Format::Json => print_json(command, &output),
+            };
+            match printed {
+                Ok(()) if verified => ExitCode::from(EXIT_VERIFIED),
+                Ok(()) => ExitCode::from(EXIT_NOT_VERIFIED),
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    ExitCode::from(EXIT_ERROR)
+                }
+            }
+        }
+        Err(e) => {
+            if format == Format::Json {
+                print_json_error(command, e.as_ref());
+            }
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<Output, Box<dyn Error>> {
+    match cli.command {
+        Command::Input { txs, index } => {
+            let (tx, prevout_map) = txs.load()?;
+            let mut report = minikeys::verify_input_signers_with(&tx, index, &prevout_map)?;
+            txs.key_path.attribute(&mut report, None);
+            Ok(Output::Input(report))
+        }
+        Command::Tx { txs } => {
+            let (tx, prevout_map) = txs.load()?;
+            let mut report = minikeys::verify_tx_signers_with(&tx, &prevout_map);
+            report
+                .inputs
+                .iter_mut()
+                .for_each(|input| txs.key_path.attribute(input, None));
+            Ok(Output::Tx(report))
+        }
+        Command::Psbt { psbt, key_path } => {
+            let psbt = decode_psbt(&read_source(&psbt)?)?;
+            let prevout_map = PrevoutMap::from_psbt(&psbt);
+            let inputs = psbt.inputs.clone();
+            let tx = psbt.extract_tx();
+            let mut report = minikeys::verify_tx_signers_with(&tx, &prevout_map);
+            // Inputs that could not be analyzed have no report, so pair them
+            // by index rather than position
+            for report in &mut report.inputs {
+                key_path.attribute(report, inputs.get(report.input_index));
+            }
+            Ok(Output::Tx(report))
+        }
+        #[cfg(feature = "serde")]
+        Command::NodeJson { json } => {
+            let json = String::from_utf8(read_source(&json)?)?;
+            let reports = minikeys::parse_verbose(&json)?
+                .iter()
+                .map(|t| minikeys::verify_tx_signers_with(&t.tx, &t.prevouts))
+                .collect();
+            Ok(Output::Txs(reports))
+        }
+        Command::Scan { .. } => unreachable!("scans are printed as they go"),
+        Command::Taptree { txs, prevouts, dot } => {
+            let sources = || txs.iter().chain(prevouts.iter());
+            if sources().filter(|s| *s == "-").count() > 1 {
+                return Err("stdin (`-`) can only be used once".into());
+            }
+            let mut prevout_map = PrevoutMap::new();
+            for source in &prevouts {
+                for prevout in decode_txs(&read_source(source)?)? {
+                    prevout_map.insert_tx(&prevout);
+                }
+            }
+            let mut trees = TapTrees::new();
+            for source in &txs {
+                for tx in decode_txs(&read_source(source)?)? {
+                    for txin in &tx.input {
+                        let script_pubkey = match prevout_map.get(&txin.previous_output) {
+                            Some(txout) => Some(txout.script_pubkey.clone()),
+                            None => minikeys::derive_script_pubkey(txin),
+                        };
+                        if let Some(script_pubkey) = script_pubkey {
+                            trees.add_spend(txin, &script_pubkey);
+                        }
+                    }
+                }
+            }
+            Ok(Output::Trees(
+                trees.iter().map(|t| TreeReport::new(t, dot)).collect(),
+            ))
+        }
+    }
+}
+
+/// Runs the `scan` command, printing each record as soon as its block is
+/// scanned.
+fn scan(blocks_dir: &Path, network: Network, format: Format) -> ExitCode {
+    let mut verified = false;
+    let mut scan = || -> Result<(), Box<dyn Error>> {
+        for records in BlocksDir::open(blocks_dir, network)?.scan()? {
+            for record in records? {
+                verified |=
+                    matches!(&record.result, ScanResult::Report(r) if r.has_verified_signer());
+                match format {
+                    Format::Text => print_scan_record(&record),
+                    Format::Json => print_json_line(&record)?,
+                }
+            }
+        }
+        Ok(())
+    };
+    match scan() {
+        Ok(()) if verified => ExitCode::from(EXIT_VERIFIED),
+        Ok(()) => ExitCode::from(EXIT_NOT_VERIFIED),
+        Err(e) => {
+            if format == Format::Json {
+                print_json_error("scan", e.as_ref());
+            }
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+impl Command {
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Input { .. } => "input",
+            Command::Tx { .. } => "tx",
+            Command::Psbt { .. } => "psbt",
+            #[cfg(feature = "serde")]
+            Command::NodeJson { .. } => "node-json",
+            Command::Scan { .. } => "scan",
+            Command::Taptree { .. } => "taptree",
+        }
+    }
+}
+
+impl TxArgs {
+    fn load(&self) -> Result<(Transaction, PrevoutMap), Box<dyn Error>> {
+        let stdin_uses = std::iter::once(&self.tx)
+            .chain(self.prevouts.iter())
+            .filter(|s| *s == "-")
+            .count();
+        if stdin_uses > 1 {
+            return Err("stdin (`-`) can only be used once".into());
+        }
+        let mut txs = decode_txs(&read_source(&self.tx)?)?;
+        if txs.len() != 1 {
+            return Err(format!("expected one spending transaction, got {}", txs.len()).into());
+        }
+        let tx = txs.remove(0);
+        let mut prevout_map = PrevoutMap::new();
+        for source in &self.prevouts {
+            for prevout in decode_txs(&read_source(source)?)? {
+                prevout_map.insert_tx(&prevout);
+            }
+        }
+        for spent in &self.spent {
+            let (input_index, amount, script_pubkey) = parse_spent(spent)?;
+            match script_pubkey {
+                Some(script_pubkey) => prevout_map.insert_spent(
+                    &tx,
+                    input_index,
+                    TxOut {
+                        value: amount.to_sat(),
+                        script_pubkey,
+                    },
+                )?,
+                None => prevout_map.insert_amount(&tx, input_index, amount)?,
+            }
+        }
+        if let Some(path) = &self.utxo_snapshot {
+            let missing: HashSet<OutPoint> = tx
+                .input
+                .iter()
+                .map(|txin| txin.previous_output)
+                .filter(|o| !o.is_null() && prevout_map.get(o).is_none())
+                .collect();
+            let snapshot =
+                SnapshotProvider::open_filtered(path, self.network, |o| missing.contains(o))?;
+            for outpoint in &missing {
+                if let Some(txout) = snapshot.prevout(outpoint)? {
+                    prevout_map.insert(*outpoint, txout);
+                }
+            }
+        }
+        Ok((tx, prevout_map))
+    }
+}
+
+impl KeyPathArgs {
+    /// Looks for the candidate internal key or MuSig2 participants of a key
+    /// path spend, if any were given, along with the internal key and merkle
+    /// root of its PSBT input.
+    fn attribute(&self, report: &mut InputReport, psbt_input: Option<&psbt::Input>) {
+        let internal_keys: Vec<XOnlyPublicKey> = psbt_input
+            .and_then(|input| input.tap_internal_key)
+            .into_iter()
+            .chain(self.internal_keys.iter().copied())
+            .collect();
+        let merkle_roots: Vec<TapNodeHash> = psbt_input
+            .and_then(|input| input.tap_merkle_root)
+            .into_iter()
+            .chain(self.merkle_roots.iter().copied())
+            .collect();
+        if !internal_keys.is_empty() {
+            report.attribute_key_path(&internal_keys, &merkle_roots);
+        }
+        if report.taproot_commitment.is_none() && !self.musig_keys.is_empty() {
+            let ordering = if self.musig_sorted {
+                KeyOrdering::Sorted
+            } else {
+                KeyOrdering::All
+            };
+            report.attribute_musig(&self.musig_keys, &merkle_roots, ordering);
+        }
+    }
+}
+
+/// Parses a `--spent` value, `<INPUT>:<SATS>[:<SCRIPT_PUBKEY_HEX>]`.
+fn parse_spent(arg: &str) -> Result<(usize, Amount, Option<ScriptBuf>), Box<dyn Error>> {
+    let invalid = || format!("invalid --spent {:?}, expected INPUT:SATS[:SCRIPT]", arg);
+    let mut parts = arg.split(':');
+    let (Some(input), Some(sats)) = (parts.next(), parts.next()) else {
+        return Err(invalid().into());
+    };
+    let input_index = input.parse().map_err(|_| invalid())?;
+    let amount = Amount::from_sat(sats.parse().map_err(|_| invalid())?);
+    let script_pubkey = match parts.next() {
+        Some(script) => Some(ScriptBuf::from(hex::decode(script)?)),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return Err(invalid().into());
+    }
+    Ok((input_index, amount, script_pubkey))
+}
+
+/// Reads an argument value: `-` is stdin, `@path` a file, anything else the
+/// value itself.
+fn read_source(arg: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    if arg == "-" {
+        let mut data = vec![];
+        std::io::stdin().read_to_end(&mut data)?;
+        Ok(data)
+    } else if let Some(path) = arg.strip_prefix('@') {
+        std::fs::read(path).map_err(|e| format!("{}: {}", path, e).into())
+    } else {
+        Ok(arg.as_bytes().to_vec())
+    }
+}
+
+/// Whitespace-separated hex transactions, or concatenated raw binary ones.
+fn decode_txs(data: &[u8]) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    match std::str::from_utf8(data) {
+        Ok(text)
+            if text
+                .bytes()
+                .all(|b| b.is_ascii_hexdigit() || b.is_ascii_whitespace()) =>
+        {
+            text.split_whitespace()
+                .map(|h| Ok(minikeys::decode_tx(&hex::decode(h)?)?))
+                .collect()
+        }
+        _ => {
+            let mut cursor = data;
+            let mut txs = vec![];
+            while !cursor.is_empty() {
+                let tx = Transaction::consensus_decode(&mut cursor)
+                    .map_err(minikeys::MinikeysError::from)?;
+                txs.push(tx);
+            }
+            Ok(txs)
+        }
+    }
+}
+
+/// A PSBT as base64 or hex text, or raw binary.
+fn decode_psbt(data: &[u8]) -> Result<PartiallySignedTransaction, Box<dyn Error>> {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let text = text.trim();
+        if let Ok(raw) = hex::decode(text) {
+            return Ok(PartiallySignedTransaction::deserialize(&raw)?);
+        }
+        if let Ok(psbt) = PartiallySignedTransaction::from_str(text) {
+            return Ok(psbt);
+        }
+    }
+    Ok(PartiallySignedTransaction::deserialize(data)?)
+}
+
+/// Top-level JSON document, see JSON_SCHEMA.md.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct JsonDocument<'a> {
+    schema_version: u32,
+    command: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'a Output>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JsonError<'a>>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct JsonError<'a> {
+    message: String,
+    /// Structured error when it comes from the minikeys library.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<&'a minikeys::MinikeysError>,
+}
+
+#[cfg(feature = "serde")]
+fn print_json(command: &'static str, output: &Output) -> Result<(), Box<dyn Error>> {
+    let doc = JsonDocument {
+        schema_version: minikeys::JSON_SCHEMA_VERSION,
+        command,
+        result: Some(output),
+        error: None,
+    };
+    println!("{}", serde_json::to_string_pretty(&doc)?);
+    Ok(())
+}
+
+/// Prints one scan record as a single-line JSON document.
+#[cfg(feature = "serde")]
+fn print_json_line(record: &ScanRecord) -> Result<(), Box<dyn Error>> {
+    #[derive(Serialize)]
+    struct JsonLine<'a> {
+        schema_version: u32,
+        command: &'static str,
+        result: &'a ScanRecord,
+    }
+    let line = JsonLine {
+        schema_version: minikeys::JSON_SCHEMA_VERSION,
+        command: "scan",
+        result: record,
+    };
+    println!("{}", serde_json::to_string(&line)?);
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+fn print_json_error(command: &'static str, e: &(dyn Error + 'static)) {
+    let doc = JsonDocument {
+        schema_version: minikeys::JSON_SCHEMA_VERSION,
+        command,
+        result: None,
+        error: Some(JsonError {
+            message: e.to_string(),
+            details: e.downcast_ref(),
+        }),
+    };
+    if let Ok(json) = serde_json::to_string_pretty(&doc) {
+        println!("{}", json);
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json(_command: &'static str, _output: &Output) -> Result<(), Box<dyn Error>> {
+    Err("JSON output needs minikeys built with the `serde` feature".into())
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json_line(_record: &ScanRecord) -> Result<(), Box<dyn Error>> {
+    Err("JSON output needs minikeys built with the `serde` feature".into())
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_json_error(_command: &'static str, _e: &(dyn Error + 'static)) {}
+
+fn print_text(output: &Output) {
+    match output {
+        Output::Input(report) => print_input(report),
+        Output::Tx(report) => print_tx(report),
+        #[cfg(feature = "serde")]
+        Output::Txs(reports) => reports.iter().for_each(print_tx),
+        Output::Trees(trees) => trees.iter().for_each(print_tree),
+    }
+}
+
+fn print_input(report: &InputReport) {
+    println!(
+        "input {} ({}): {}",
+        report.input_index, report.spend_type, report.descriptor
+    );
+    for signer in &report.signers {
+        println!(
+            "  key #{} {}\tsig #{} {} {} {}",
+            index_str(signer.key_index),
+            signer.key,
+            index_str(signer.sig_index),
+            signer.signature,
+            signer.sighash_type,
+            if signer.verified {
+                "verified"
+            } else {
+                "INVALID"
+            }
+        );
+    }
+    for key in &report.non_signers {
+        println!("  not signed: {}", key);
+    }
+    if let Some(policy) = &report.policy {
+        println!("  policy: {}", policy.policy);
+        match (&policy.satisfied, &policy.description) {
+            (Some(satisfied), Some(description)) => {
+                let branch = policy
+                    .branch
+                    .map_or_else(String::new, |i| format!("branch #{}, ", i));
+                println!("  satisfied: {}{} ({})", branch, description, satisfied);
+            }
+            _ => println!("  satisfied: no branch"),
+        }
+    }
+    if let Some(control_block) = &report.control_block {
+        print_control_block(control_block);
+    }
+    if let Some(commitment) = &report.taproot_commitment {
+        match commitment.merkle_root {
+            Some(root) => println!(
+                "  internal key {}, merkle root {}",
+                commitment.internal_key, root
+            ),
+            None => println!("  internal key {}, no script tree", commitment.internal_key),
+        }
+    }
+    if let Some(musig) = &report.musig {
+        println!(
+            "  MuSig2 aggregate of {} keys{}:",
+            musig.participants.len(),
+            if musig.key_sorted { ", sorted" } else { "" }
+        );
+        for key in &musig.participants {
+            println!("    {}", key);
+        }
+    }
+}
+
+fn print_control_block(control_block: &ControlBlockReport) {
+    println!(
+        "  control block: internal key {}{}, leaf version {:#x}, leaf hash {}, depth {}",
+        control_block.internal_key,
+        if control_block.nums_internal_key {
+            " (NUMS, no key path)"
+        } else {
+            ""
+        },
+        control_block.leaf_version,
+        control_block.leaf_hash,
+        control_block.depth()
+    );
+    for sibling in &control_block.merkle_path {
+        println!("    sibling {}", sibling);
+    }
+    println!(
+        "  merkle root {}, output key {} ({}): {}",
+        control_block.merkle_root,
+        control_block.output_key,
+        match control_block.output_key_parity {
+            Parity::Even => "even",
+            Parity::Odd => "odd",
+        },
+        if control_block.commitment_verified {
+            "commitment verified"
+        } else {
+            "commitment MISMATCH"
+        }
+    );
+}
+
+fn print_tx(report: &TxReport) {
+    println!("tx {}", report.txid);
+    if report.coinbase {
+        println!("coinbase, nothing to verify");
+        return;
+    }
+    for input in &report.inputs {
+        print_input(input);
+    }
+    for unanalyzed in &report.summary.unanalyzed {
+        println!(
+            "input {}: skipped, {}",
+            unanalyzed.input_index, unanalyzed.error
+        );
+    }
+    println!("signers:");
+    for signer in &report.summary.signers {
+        println!("  {} inputs {:?}", signer.key, signer.inputs);
+    }
+}
+
+fn print_tree(tree: &TreeReport) {
+    if let Some(dot) = &tree.dot {
+        print!("{}", dot);
+        return;
+    }
+    println!(
+        "output key {}: internal key {}, merkle root {}, {}",
+        tree.output_key,
+        tree.internal_key,
+        tree.merkle_root,
+        if tree.complete { "complete" } else { "partial" }
+    );
+    for leaf in &tree.leaves {
+        println!(
+            "  leaf depth {} version {:#x}: {}",
+            leaf.depth, leaf.leaf_version, leaf.asm
+        );
+    }
+    for hidden in &tree.hidden {
+        println!("  hidden depth {}: {}", hidden.depth, hidden.hash);
+    }
+    println!("  {}", tree.descriptor);
+}
+
+fn print_scan_record(record: &ScanRecord) {
+    match &record.result {
+        ScanResult::Report(report) => {
+            let keys: Vec<String> = report.verified_keys().map(|k| k.to_string()).collect();
+            println!(
+                "block {} tx {} input {} ({}): {}",
+                record.block_hash,
+                record.txid,
+                record.input_index,
+                report.spend_type,
+                if keys.is_empty() {
+                    "no verified signer".to_string()
+                } else {
+                    keys.join(", ")
+                }
+            );
+        }
+        ScanResult::Error(e) => println!(
+            "block {} tx {} input {}: skipped, {}",
+            record.block_hash, record.txid, record.input_index, e
+        ),
+    }
+}
+
+fn index_str(index: Option<usize>) -> String {
+    index.map_or_else(|| "?".to_string(), |i| i.to_string())
+}
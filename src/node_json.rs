@@ -0,0 +1,133 @@
+//! Transactions and their spent outputs from Bitcoin Core verbose JSON.
+//!
+//! `getrawtransaction <txid> 3` and `getblock <hash> 3` give, next to each
+//! transaction's `hex`, the `prevout` (value and scriptPubKey) of every
+//! non-coinbase input, which is all the verification needs.
+
+use bitcoin::{Amount, OutPoint, ScriptBuf, Transaction, TxOut, Txid};
+use serde::Deserialize;
+
+use crate::{MinikeysError, PrevoutMap};
+
+/// A transaction together with the outputs its inputs spend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxWithPrevouts {
+    pub tx: Transaction,
+    pub prevouts: PrevoutMap,
+}
+
+#[derive(Deserialize)]
+struct VerboseBlock {
+    tx: Vec<VerboseTx>,
+}
+
+#[derive(Deserialize)]
+struct VerboseTx {
+    txid: String,
+    hex: String,
+    vin: Vec<VerboseTxIn>,
+}
+
+#[derive(Deserialize)]
+struct VerboseTxIn {
+    prevout: Option<VerbosePrevout>,
+}
+
+#[derive(Deserialize)]
+struct VerbosePrevout {
+    /// In BTC.
+    value: f64,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: VerboseScript,
+}
+
+#[derive(Deserialize)]
+struct VerboseScript {
+    hex: String,
+}
+
+/// Parses the output of `bitcoin-cli getrawtransaction <txid> 3`.
+pub fn parse_verbose_tx(json: &str) -> Result<TxWithPrevouts, MinikeysError> {
+    let tx: VerboseTx = serde_json::from_str(json).map_err(json_err)?;
+    tx.into_tx_with_prevouts()
+}
+
+/// Parses the output of `bitcoin-cli getblock <hash> 3`, transactions in
+/// block order.
+pub fn parse_verbose_block(json: &str) -> Result<Vec<TxWithPrevouts>, MinikeysError> {
+    let block: VerboseBlock = serde_json::from_str(json).map_err(json_err)?;
+    block.into_txs()
+}
+
+/// Parses either of the above, told apart by the `tx` array of blocks.
+pub fn parse_verbose(json: &str) -> Result<Vec<TxWithPrevouts>, MinikeysError> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(json_err)?;
+    if value.get("tx").is_some() {
+        let block: VerboseBlock = serde_json::from_value(value).map_err(json_err)?;
+        block.into_txs()
+    } else {
+        let tx: VerboseTx = serde_json::from_value(value).map_err(json_err)?;
+        Ok(vec![tx.into_tx_with_prevouts()?])
+    }
+}
+
+impl VerboseBlock {
+    fn into_txs(self) -> Result<Vec<TxWithPrevouts>, MinikeysError> {
+        self.tx
+            .into_iter()
+            .map(VerboseTx::into_tx_with_prevouts)
+            .collect()
+    }
+}
+
+impl VerboseTx {
+    fn into_tx_with_prevouts(self) -> Result<TxWithPrevouts, MinikeysError> {
+        let raw = hex::decode(&self.hex).map_err(json_err)?;
+        let tx = crate::decode_tx(&raw)?;
+        let txid: Txid = self.txid.parse().map_err(json_err)?;
+        if tx.txid() != txid {
+            return Err(json_err(format!(
+                "hex decodes to {}, not {}",
+                tx.txid(),
+                txid
+            )));
+        }
+        if self.vin.len() != tx.input.len() {
+            return Err(json_err(format!("{}: vin does not match hex", txid)));
+        }
+        let mut prevouts = PrevoutMap::new();
+        for (txin, vin) in tx.input.iter().zip(self.vin) {
+            if let Some(prevout) = vin.prevout {
+                prevouts.insert(txin.previous_output, prevout.into_txout()?);
+            }
+        }
+        Ok(TxWithPrevouts { tx, prevouts })
+    }
+}
+
+impl VerbosePrevout {
+    fn into_txout(self) -> Result<TxOut, MinikeysError> {
+        let value = Amount::from_btc(self.value).map_err(json_err)?;
+        let script = hex::decode(&self.script_pubkey.hex).map_err(json_err)?;
+        Ok(TxOut {
+            value: value.to_sat(),
+            script_pubkey: ScriptBuf::from(script),
+        })
+    }
+}
+
+impl TxWithPrevouts {
+    /// The outpoints whose `prevout` the JSON did not give, coinbase excluded.
+    pub fn missing_prevouts(&self) -> Vec<OutPoint> {
+        self.tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .filter(|o| !o.is_null() && self.prevouts.get(o).is_none())
+            .collect()
+    }
+}
+
+fn json_err(e: impl std::fmt::Display) -> MinikeysError {
+    MinikeysError::NodeJson(e.to_string())
+}
@@ -0,0 +1,273 @@
+//! Semantic policy of spent scripts, and the branch of it a spend satisfied.
+//!
+//! The spent script (the leaf of a taproot script path spend) is lifted to a
+//! semantic policy, only keeping what it takes to spend: keys, timelocks and
+//! hash preimages combined by thresholds. Each condition is then checked
+//! against the spend, keys by their verified signature, timelocks against
+//! the transaction's lock time and the input's sequence, hashes against the
+//! pushed 32-byte preimages; the satisfied conditions make up the branch.
+//! Where more conditions of a threshold are satisfied than it takes, those
+//! with a signature are kept over timelocks, which a spend may meet without
+//! using them.
+
+use std::collections::HashSet;
+
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::{absolute, relative, Script, Transaction, TxIn};
+use miniscript::policy::semantic::Policy;
+use miniscript::policy::Liftable;
+use miniscript::{Interpreter, Miniscript, MiniscriptKey, Tap};
+
+use crate::SignerKey;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The semantic policy of a spent script and the branch the spend satisfied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PolicyReport {
+    /// Normalized semantic policy, e.g.
+    /// `or(thresh(2,pk(A),pk(B),pk(C)),and(pk(D),older(52560)))`.
+    pub policy: String,
+    /// The part of `policy` the spend satisfied, with `k` of the satisfied
+    /// conditions of each threshold, e.g. `and(pk(A),pk(C))`. `None` when no
+    /// branch is satisfied, e.g. for lack of valid signatures.
+    pub satisfied: Option<String>,
+    /// The satisfied branch in words, e.g. `2-of-3 keys: A, C` or `key D
+    /// and after 52560 blocks`.
+    pub description: Option<String>,
+    /// When `policy` is an `or` of several alternatives, the index of the one
+    /// `satisfied` describes.
+    pub branch: Option<usize>,
+}
+
+/// What a spend satisfies, independently of the policy.
+struct Spend<'a> {
+    tx: &'a Transaction,
+    txin: &'a TxIn,
+    /// Hex digests of the pushed 32-byte elements, which may be preimages:
+    /// sha256, hash256, ripemd160 and hash160, in that order.
+    digests: [HashSet<String>; 4],
+}
+
+impl<'a> Spend<'a> {
+    fn new(tx: &'a Transaction, txin: &'a TxIn) -> Self {
+        let pushes = txin
+            .script_sig
+            .instructions()
+            .filter_map(|ins| match ins {
+                Ok(Instruction::PushBytes(bytes)) => Some(bytes.as_bytes()),
+                _ => None,
+            })
+            .chain(txin.witness.iter());
+        let mut digests: [HashSet<String>; 4] = Default::default();
+        for preimage in pushes.filter(|p| p.len() == 32) {
+            digests[0].insert(sha256::Hash::hash(preimage).to_string());
+            digests[1].insert(hex::encode(sha256d::Hash::hash(preimage).to_byte_array()));
+            digests[2].insert(ripemd160::Hash::hash(preimage).to_string());
+            digests[3].insert(hash160::Hash::hash(preimage).to_string());
+        }
+        Spend { tx, txin, digests }
+    }
+
+    fn after(&self, lock_time: absolute::LockTime) -> bool {
+        self.txin.sequence.enables_absolute_lock_time()
+            && lock_time.is_implied_by(self.tx.lock_time)
+    }
+
+    fn older(&self, lock_time: relative::LockTime) -> bool {
+        self.tx.version >= 2
+            && self
+                .txin
+                .sequence
+                .to_relative_lock_time()
+                .is_some_and(|sequence| lock_time.is_implied_by(sequence))
+    }
+
+    /// Whether a pushed element hashes to `hash`, `hash_type` indexing
+    /// `digests`.
+    fn preimage(&self, hash_type: usize, hash: &impl ToString) -> bool {
+        self.digests[hash_type].contains(&hash.to_string())
+    }
+}
+
+/// The policy report of input `input_index` of `tx`, whose signatures by
+/// `signers` verified. `None` if the spent script cannot be lifted.
+pub(crate) fn policy_report(
+    interpreter: &Interpreter,
+    tx: &Transaction,
+    input_index: usize,
+    script_pubkey: &Script,
+    signers: &[SignerKey],
+) -> Option<PolicyReport> {
+    let txin = &tx.input[input_index];
+    let spend = Spend::new(tx, txin);
+    if interpreter.is_taproot_v1_key_spend() {
+        let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).ok()?;
+        let signed = |pk: &XOnlyPublicKey| signers.contains(&SignerKey::XOnly(*pk));
+        Some(report(Policy::Key(output_key), &spend, &signed))
+    } else if interpreter.is_taproot_v1_script_spend() {
+        let leaf = crate::script::tapscript(&txin.witness)?;
+        let ms = Miniscript::<XOnlyPublicKey, Tap>::parse_insane(leaf).ok()?;
+        let signed = |pk: &XOnlyPublicKey| signers.contains(&SignerKey::XOnly(*pk));
+        Some(report(ms.lift().ok()?, &spend, &signed))
+    } else {
+        let descriptor = interpreter.inferred_descriptor().ok()?;
+        let signed = |pk: &bitcoin::PublicKey| signers.contains(&SignerKey::Full(*pk));
+        Some(report(descriptor.lift().ok()?, &spend, &signed))
+    }
+}
+
+fn report<Pk: MiniscriptKey>(
+    policy: Policy<Pk>,
+    spend: &Spend,
+    signed: &dyn Fn(&Pk) -> bool,
+) -> PolicyReport {
+    let policy = policy.normalized();
+    let branch = match &policy {
+        Policy::Threshold(1, subs) if subs.len() > 1 => {
+            select(1, subs, spend, signed).map(|chosen| chosen[0].0)
+        }
+        _ => None,
+    };
+    let satisfied = satisfied_branch(&policy, spend, signed);
+    PolicyReport {
+        policy: policy.to_string(),
+        satisfied: satisfied.as_ref().map(|sat| sat.policy.to_string()),
+        description: satisfied.map(|sat| sat.description),
+        branch,
+    }
+}
+
+/// A satisfied part of a policy.
+struct Satisfied<Pk: MiniscriptKey> {
+    policy: Policy<Pk>,
+    description: String,
+    /// Whether it takes a verified signature, as opposed to being satisfied
+    /// by timelocks or preimages only.
+    signed: bool,
+    /// Whether it only takes timelocks, which the transaction's lock time
+    /// and sequence meet whichever branch it used.
+    timelock_only: bool,
+}
+
+impl<Pk: MiniscriptKey> Satisfied<Pk> {
+    /// Rank among satisfied alternatives, lowest first: signed parts, then
+    /// preimages, then timelocks alone.
+    fn rank(&self) -> u8 {
+        match (self.signed, self.timelock_only) {
+            (true, _) => 0,
+            (false, false) => 1,
+            (false, true) => 2,
+        }
+    }
+}
+
+/// The satisfied part of `policy`, `None` if `policy` is not satisfied.
+fn satisfied_branch<Pk: MiniscriptKey>(
+    policy: &Policy<Pk>,
+    spend: &Spend,
+    signed: &dyn Fn(&Pk) -> bool,
+) -> Option<Satisfied<Pk>> {
+    let satisfied = |ok: bool, description: String| {
+        ok.then(|| Satisfied {
+            policy: policy.clone(),
+            description,
+            signed: matches!(policy, Policy::Key(_)),
+            timelock_only: matches!(policy, Policy::After(_) | Policy::Older(_)),
+        })
+    };
+    match policy {
+        Policy::Unsatisfiable => None,
+        Policy::Trivial => satisfied(true, "no condition".to_string()),
+        Policy::Key(pk) => satisfied(signed(pk), format!("key {}", pk)),
+        Policy::After(n) => {
+            let lock_time = absolute::LockTime::from_consensus(n.to_consensus_u32());
+            let description = match lock_time {
+                absolute::LockTime::Blocks(height) => format!("after block {}", height),
+                absolute::LockTime::Seconds(time) => format!("after time {}", time),
+            };
+            satisfied(spend.after(lock_time), description)
+        }
+        Policy::Older(n) => {
+            let lock_time = n.to_relative_lock_time()?;
+            let description = match lock_time {
+                relative::LockTime::Blocks(height) => {
+                    format!("after {} blocks", height.value())
+                }
+                relative::LockTime::Time(time) => {
+                    format!("after {} seconds", u32::from(time.value()) * 512)
+                }
+            };
+            satisfied(spend.older(lock_time), description)
+        }
+        Policy::Sha256(hash) => satisfied(spend.preimage(0, hash), preimage("sha256", hash)),
+        Policy::Hash256(hash) => satisfied(spend.preimage(1, hash), preimage("hash256", hash)),
+        Policy::Ripemd160(hash) => satisfied(spend.preimage(2, hash), preimage("ripemd160", hash)),
+        Policy::Hash160(hash) => satisfied(spend.preimage(3, hash), preimage("hash160", hash)),
+        Policy::Threshold(k, subs) => {
+            let chosen = select(*k, subs, spend, signed)?;
+            let signed = chosen.iter().any(|(_, sat)| sat.signed);
+            let timelock_only = chosen.iter().all(|(_, sat)| sat.timelock_only);
+            let (kept, descriptions): (Vec<_>, Vec<_>) = chosen
+                .into_iter()
+                .map(|(_, sat)| (sat.policy, sat.description))
+                .unzip();
+            let description = if subs.iter().all(|sub| matches!(sub, Policy::Key(_))) {
+                let keys: Vec<String> = kept
+                    .iter()
+                    .filter_map(|sub| match sub {
+                        Policy::Key(pk) => Some(pk.to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                format!("{}-of-{} keys: {}", k, subs.len(), keys.join(", "))
+            } else if *k == 1 || *k == subs.len() {
+                descriptions.join(" and ")
+            } else {
+                format!("{}-of-{}: {}", k, subs.len(), descriptions.join("; "))
+            };
+            let policy = match kept.len() {
+                1 => kept.into_iter().next().expect("one"),
+                _ => Policy::Threshold(*k, kept),
+            };
+            Some(Satisfied {
+                policy,
+                description,
+                signed,
+                timelock_only,
+            })
+        }
+    }
+}
+
+/// The `k` satisfied subs of a threshold the spend most likely used, with
+/// their index, in policy order. Subs taking a verified signature come
+/// first: the transaction's lock time and sequence may meet the timelocks of
+/// branches it did not use, so timelocks alone only fill the remaining
+/// places. `None` if fewer than `k` subs are satisfied.
+fn select<Pk: MiniscriptKey>(
+    k: usize,
+    subs: &[Policy<Pk>],
+    spend: &Spend,
+    signed: &dyn Fn(&Pk) -> bool,
+) -> Option<Vec<(usize, Satisfied<Pk>)>> {
+    let mut satisfied: Vec<(usize, Satisfied<Pk>)> = subs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, sub)| Some((i, satisfied_branch(sub, spend, signed)?)))
+        .collect();
+    if satisfied.len() < k {
+        return None;
+    }
+    satisfied.sort_by_key(|(i, sat)| (sat.rank(), *i));
+    satisfied.truncate(k);
+    satisfied.sort_by_key(|(i, _)| *i);
+    Some(satisfied)
+}
+
+fn preimage(name: &str, hash: &impl std::fmt::Display) -> String {
+    format!("{} preimage of {}", name, hash)
+}
@@ -0,0 +1,278 @@
+//! Resolution of the outputs spent by a transaction's inputs.
+
+use std::collections::{HashMap, HashSet};
+
+use bitcoin::key::{TapTweak, XOnlyPublicKey};
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::sighash::Prevouts;
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash};
+use bitcoin::{Amount, OutPoint, PublicKey, Script, ScriptBuf, Transaction, TxIn, TxOut, Txid};
+
+use crate::script::{control_block, redeem_script, tapscript, without_annex};
+use crate::taproot::merkle_root;
+use crate::MinikeysError;
+
+/// Spent outputs indexed by the outpoint that references them.
+///
+/// Built from any set of funding transactions, in any order and with
+/// duplicates, so callers don't have to line them up with the inputs.
+/// Outputs are keyed by the txid computed from the funding transaction
+/// itself, so a wrong funding transaction is never matched to an input: the
+/// input's prevout is reported missing. Only a PSBT pairs funding
+/// transactions with inputs, so only a wrong `non_witness_utxo` is reported
+/// as a [`MinikeysError::PrevoutTxidMismatch`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrevoutMap {
+    outputs: HashMap<OutPoint, TxOut>,
+    /// Transactions indexed in full, to tell a bad vout from a missing tx.
+    txids: HashSet<Txid>,
+    /// Txids of the PSBT `non_witness_utxo`s not matching the outpoint of
+    /// their input.
+    mismatched: HashMap<OutPoint, Txid>,
+}
+
+impl PrevoutMap {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes every output of every transaction in `txs`.
+    pub fn from_txs<'a, I>(txs: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Transaction>,
+    {
+        let mut map = Self::new();
+        for tx in txs {
+            map.insert_tx(tx);
+        }
+        map
+    }
+
+    /// Collects the spent outputs carried by a PSBT's inputs, from either
+    /// their `non_witness_utxo` or their `witness_utxo`. A `non_witness_utxo`
+    /// that is not the transaction its input spends from is remembered, to
+    /// be reported by [`PrevoutMap::spent_output`].
+    pub fn from_psbt(psbt: &PartiallySignedTransaction) -> Self {
+        let mut map = Self::new();
+        for (txin, input) in psbt.unsigned_tx.input.iter().zip(psbt.inputs.iter()) {
+            if let Some(tx) = &input.non_witness_utxo {
+                let txid = tx.txid();
+                if txid != txin.previous_output.txid {
+                    map.mismatched.insert(txin.previous_output, txid);
+                }
+                map.insert_tx(tx);
+            }
+            if let Some(txout) = &input.witness_utxo {
+                map.insert(txin.previous_output, txout.clone());
+            }
+        }
+        map
+    }
+
+    /// Indexes every output of `tx` under its txid.
+    pub fn insert_tx(&mut self, tx: &Transaction) {
+        let txid = tx.txid();
+        self.txids.insert(txid);
+        for (vout, txout) in tx.output.iter().enumerate() {
+            self.outputs
+                .insert(OutPoint::new(txid, vout as u32), txout.clone());
+        }
+    }
+
+    /// Records a single spent output.
+    pub fn insert(&mut self, outpoint: OutPoint, txout: TxOut) {
+        self.outputs.insert(outpoint, txout);
+    }
+
+    /// Records the output spent by input `input_index` of `tx` from its
+    /// scriptPubKey and amount, for when the funding transaction itself is
+    /// not at hand.
+    pub fn insert_spent(
+        &mut self,
+        tx: &Transaction,
+        input_index: usize,
+        txout: TxOut,
+    ) -> Result<(), MinikeysError> {
+        let txin = input(tx, input_index)?;
+        self.insert(txin.previous_output, txout);
+        Ok(())
+    }
+
+    /// Records the output spent by input `input_index` of `tx` from its
+    /// amount alone, the scriptPubKey being derived from the input with
+    /// [`derive_script_pubkey`].
+    pub fn insert_amount(
+        &mut self,
+        tx: &Transaction,
+        input_index: usize,
+        amount: Amount,
+    ) -> Result<(), MinikeysError> {
+        let txin = input(tx, input_index)?;
+        let script_pubkey = derive_script_pubkey(txin)
+            .ok_or(MinikeysError::UnderivableScriptPubKey { input_index })?;
+        self.insert(
+            txin.previous_output,
+            TxOut {
+                value: amount.to_sat(),
+                script_pubkey,
+            },
+        );
+        Ok(())
+    }
+
+    /// The output referenced by `outpoint`, if known.
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&TxOut> {
+        self.outputs.get(outpoint)
+    }
+
+    /// The output spent by input `input_index` of `tx`.
+    pub fn spent_output(
+        &self,
+        tx: &Transaction,
+        input_index: usize,
+    ) -> Result<&TxOut, MinikeysError> {
+        let outpoint = input(tx, input_index)?.previous_output;
+        match self.get(&outpoint) {
+            Some(txout) => Ok(txout),
+            None => match self.mismatched.get(&outpoint) {
+                Some(&found) => Err(MinikeysError::PrevoutTxidMismatch {
+                    input_index,
+                    expected: outpoint.txid,
+                    found,
+                }),
+                None if self.txids.contains(&outpoint.txid) => {
+                    Err(MinikeysError::PrevoutMismatch {
+                        input_index,
+                        outpoint,
+                    })
+                }
+                None => Err(MinikeysError::MissingPrevout {
+                    input_index,
+                    outpoint,
+                }),
+            },
+        }
+    }
+
+    /// The outputs spent by all inputs of `tx`, in input order.
+    pub fn spent_outputs(&self, tx: &Transaction) -> Result<Vec<TxOut>, MinikeysError> {
+        (0..tx.input.len())
+            .map(|i| self.spent_output(tx, i).cloned())
+            .collect()
+    }
+
+    /// The spent outputs needed to compute the sighash of input
+    /// `input_index`: all of them when `all_inputs` is set (taproot, unless
+    /// every signature is `SIGHASH_ANYONECANPAY`), only the input's own
+    /// otherwise.
+    pub fn sighash_prevouts(
+        &self,
+        tx: &Transaction,
+        input_index: usize,
+        all_inputs: bool,
+    ) -> Result<SighashPrevouts, MinikeysError> {
+        if all_inputs {
+            Ok(SighashPrevouts::All(self.spent_outputs(tx)?))
+        } else {
+            let txout = self.spent_output(tx, input_index)?.clone();
+            Ok(SighashPrevouts::One(input_index, txout))
+        }
+    }
+}
+
+/// The scriptPubKey spent by `txin`, derived from the script or key the
+/// input reveals: P2PKH, P2SH (wrapped segwit included), P2WPKH, P2WSH and
+/// taproot script path spends.
+///
+/// `None` when the input does not commit to its scriptPubKey: taproot key
+/// path spends, P2PK, bare scripts and coinbase inputs. Spends are told
+/// apart by the shape of the scriptSig and witness only, so the result is a
+/// best guess for inputs that are not standard.
+pub fn derive_script_pubkey(txin: &TxIn) -> Option<ScriptBuf> {
+    if txin.previous_output.is_null() {
+        return None;
+    }
+    let witness = without_annex(&txin.witness);
+    if !txin.script_sig.is_empty() {
+        let redeem = redeem_script(txin)?;
+        if !witness.is_empty() {
+            return Some(ScriptBuf::new_p2sh(&redeem.script_hash()));
+        }
+        let pushes = txin.script_sig.instructions().count();
+        if pushes == 2 {
+            if let Ok(key) = PublicKey::from_slice(redeem.as_bytes()) {
+                return Some(ScriptBuf::new_p2pkh(&key.pubkey_hash()));
+            }
+        }
+        // A lone signature (P2PK) or the signatures of a bare multisig
+        if pushes == 1 || bitcoin::ecdsa::Signature::from_slice(redeem.as_bytes()).is_ok() {
+            return None;
+        }
+        return Some(ScriptBuf::new_p2sh(&redeem.script_hash()));
+    }
+    match witness.as_slice() {
+        [] => None,
+        // Key path: only a Schnorr signature (the annex dropped above), the
+        // output key is not revealed
+        [sig] if sig.len() == 64 || sig.len() == 65 => None,
+        // A 33-byte control block (0xc0/0xc1 prefix) is a taproot script
+        // path spend of a single leaf, left to the arm below
+        [_, key] if key.len() == 33 && matches!(key[0], 0x02 | 0x03) => {
+            let key = PublicKey::from_slice(key).ok()?;
+            Some(ScriptBuf::new_v0_p2wpkh(&key.wpubkey_hash()?))
+        }
+        [.., last] => taproot_script_pubkey(txin).or_else(|| {
+            Some(ScriptBuf::new_v0_p2wsh(
+                &Script::from_bytes(last).wscript_hash(),
+            ))
+        }),
+    }
+}
+
+/// The output of a taproot script path spend, recomputed from the control
+/// block and the tapscript. Only tapscript leaves are recognized: a witness
+/// script starting with their leaf version byte could never execute.
+fn taproot_script_pubkey(txin: &TxIn) -> Option<ScriptBuf> {
+    let leaf = tapscript(&txin.witness)?;
+    let control = ControlBlock::decode(control_block(&txin.witness)?).ok()?;
+    if control.leaf_version != LeafVersion::TapScript {
+        return None;
+    }
+    let leaf_hash = TapLeafHash::from_script(leaf, control.leaf_version);
+    let root = merkle_root(leaf_hash, control.merkle_branch.as_inner());
+    let internal_key: XOnlyPublicKey = control.internal_key;
+    let (output_key, parity) = internal_key.tap_tweak(&Secp256k1::verification_only(), Some(root));
+    (parity == control.output_key_parity).then(|| ScriptBuf::new_v1_p2tr_tweaked(output_key))
+}
+
+/// Input `input_index` of `tx`.
+fn input(tx: &Transaction, input_index: usize) -> Result<&TxIn, MinikeysError> {
+    tx.input
+        .get(input_index)
+        .ok_or(MinikeysError::InputIndexOutOfRange {
+            index: input_index,
+            inputs: tx.input.len(),
+        })
+}
+
+/// Owned spent outputs for one input's sighash, see [`Prevouts`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SighashPrevouts {
+    /// The output spent by the input at the given index. Enough for legacy
+    /// and segwit v0 sighashes, and taproot `SIGHASH_ANYONECANPAY`.
+    One(usize, TxOut),
+    /// The outputs spent by every input, in input order, as taproot requires.
+    All(Vec<TxOut>),
+}
+
+impl SighashPrevouts {
+    /// Borrows as the rust-bitcoin type expected by sighash computations.
+    pub fn as_prevouts(&self) -> Prevouts<'_, TxOut> {
+        match self {
+            SighashPrevouts::One(input_index, txout) => Prevouts::One(*input_index, txout.clone()),
+            SighashPrevouts::All(txouts) => Prevouts::All(txouts),
+        }
+    }
+}
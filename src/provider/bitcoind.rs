@@ -0,0 +1,391 @@
+//! Bitcoin Core backends: the JSON-RPC interface and the REST interface.
+//!
+//! `getrawtransaction` resolves any output, spent or not, when the node runs
+//! with `-txindex` (or the transaction is in its mempool). Outputs it cannot
+//! find are then looked up in the UTXO set, which only knows unspent ones.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bitcoin::consensus::deserialize;
+use bitcoin::{Amount, Block, BlockHash, OutPoint, ScriptBuf, Transaction, TxOut, Txid};
+use serde_json::{json, Value};
+
+use super::{outputs_from_txs, PrevoutProvider};
+use crate::MinikeysError;
+
+/// `RPC_INVALID_ADDRESS_OR_KEY`, returned for unknown transactions and blocks.
+const RPC_NOT_FOUND: i64 = -5;
+/// Calls sent in one JSON-RPC batch unless configured otherwise.
+const DEFAULT_BATCH_SIZE: usize = 100;
+/// Most outpoints the REST `getutxos` endpoint accepts per request.
+const REST_GETUTXOS_MAX: usize = 15;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Credentials for the JSON-RPC interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BitcoindAuth {
+    None,
+    /// `-rpcuser`/`-rpcpassword` or an `-rpcauth` entry.
+    UserPass {
+        user: String,
+        password: String,
+    },
+    /// The `.cookie` file of the node's data directory. It is read on every
+    /// request since the node rewrites it on restart.
+    CookieFile(PathBuf),
+}
+
+impl BitcoindAuth {
+    fn header(&self) -> Result<Option<String>, MinikeysError> {
+        let credentials = match self {
+            BitcoindAuth::None => return Ok(None),
+            BitcoindAuth::UserPass { user, password } => format!("{}:{}", user, password),
+            BitcoindAuth::CookieFile(path) => std::fs::read_to_string(path)
+                .map_err(|e| provider_err(format!("{}: {}", path.display(), e)))?
+                .trim()
+                .to_string(),
+        };
+        Ok(Some(format!(
+            "Basic {}",
+            bitcoin::base64::encode(credentials)
+        )))
+    }
+}
+
+/// An error object returned by bitcoind for one call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+/// Prevout provider backed by bitcoind's JSON-RPC interface.
+#[derive(Debug, Clone)]
+pub struct BitcoindRpc {
+    url: String,
+    auth: BitcoindAuth,
+    batch_size: usize,
+    agent: ureq::Agent,
+}
+
+impl BitcoindRpc {
+    /// `url` is the RPC endpoint, e.g. `http://127.0.0.1:8332`.
+    pub fn new(url: &str, auth: BitcoindAuth) -> Self {
+        BitcoindRpc {
+            url: url.to_string(),
+            auth,
+            batch_size: DEFAULT_BATCH_SIZE,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// Maximum number of calls sent in one HTTP request.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// `getrawtransaction`, `None` if the node does not know the transaction.
+    /// `block` lets nodes without `-txindex` find confirmed transactions.
+    pub fn get_raw_transaction(
+        &self,
+        txid: &Txid,
+        block: Option<&BlockHash>,
+    ) -> Result<Option<Transaction>, MinikeysError> {
+        let params = match block {
+            Some(block) => json!([txid.to_string(), false, block.to_string()]),
+            None => json!([txid.to_string(), false]),
+        };
+        let mut res = self.call_batch(&[("getrawtransaction", params)])?;
+        raw_tx_result(txid, res.remove(0))
+    }
+
+    /// `getrawtransaction` for several transactions, in batches.
+    pub fn get_raw_transactions(
+        &self,
+        txids: &[Txid],
+    ) -> Result<Vec<Option<Transaction>>, MinikeysError> {
+        let calls: Vec<_> = txids
+            .iter()
+            .map(|txid| ("getrawtransaction", json!([txid.to_string(), false])))
+            .collect();
+        self.call_batch(&calls)?
+            .into_iter()
+            .zip(txids)
+            .map(|(res, txid)| raw_tx_result(txid, res))
+            .collect()
+    }
+
+    /// `gettxout`, mempool included. `None` for spent or unknown outputs.
+    pub fn get_tx_out(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.get_tx_outs(std::slice::from_ref(outpoint))?.remove(0))
+    }
+
+    /// `gettxout` for several outputs, in batches.
+    pub fn get_tx_outs(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let calls: Vec<_> = outpoints
+            .iter()
+            .map(|o| ("gettxout", json!([o.txid.to_string(), o.vout, true])))
+            .collect();
+        self.call_batch(&calls)?
+            .into_iter()
+            .map(|res| match res.map_err(rpc_err)? {
+                Value::Null => Ok(None),
+                utxo => utxo_json(&utxo).map(Some),
+            })
+            .collect()
+    }
+
+    /// `getblock` at verbosity 0, `None` if the node does not know the block.
+    pub fn get_block(&self, hash: &BlockHash) -> Result<Option<Block>, MinikeysError> {
+        let mut res = self.call_batch(&[("getblock", json!([hash.to_string(), 0]))])?;
+        match res.remove(0) {
+            Ok(hex) => Ok(Some(decode_hex(&hex)?)),
+            Err(e) if e.code == RPC_NOT_FOUND => Ok(None),
+            Err(e) => Err(rpc_err(e)),
+        }
+    }
+
+    /// Sends `calls` as JSON-RPC batches of at most `batch_size` calls and
+    /// returns their results in order.
+    fn call_batch(
+        &self,
+        calls: &[(&str, Value)],
+    ) -> Result<Vec<Result<Value, RpcError>>, MinikeysError> {
+        let mut results = Vec::with_capacity(calls.len());
+        for chunk in calls.chunks(self.batch_size) {
+            let body: Vec<Value> = chunk
+                .iter()
+                .enumerate()
+                .map(|(id, (method, params))| {
+                    json!({"jsonrpc": "1.0", "id": id, "method": method, "params": params})
+                })
+                .collect();
+            let mut request = self.agent.post(&self.url);
+            if let Some(auth) = self.auth.header()? {
+                request = request.set("Authorization", &auth);
+            }
+            let response = match request.send_json(Value::Array(body)) {
+                Ok(response) => response,
+                Err(ureq::Error::Status(401, _)) => {
+                    return Err(provider_err("bitcoind: authentication failed"))
+                }
+                // Errors of a batch are reported per call with a 200 status,
+                // anything else is a transport or server failure.
+                Err(e) => return Err(provider_err(format!("bitcoind: {}", e))),
+            };
+            let replies: Vec<Value> = response
+                .into_json()
+                .map_err(|e| provider_err(format!("bitcoind: invalid response: {}", e)))?;
+            let mut by_id: HashMap<u64, Value> = replies
+                .into_iter()
+                .filter_map(|r| Some((r["id"].as_u64()?, r)))
+                .collect();
+            for id in 0..chunk.len() as u64 {
+                let reply = by_id
+                    .remove(&id)
+                    .ok_or_else(|| provider_err(format!("bitcoind: no reply to call {}", id)))?;
+                results.push(match &reply["error"] {
+                    Value::Null => Ok(reply["result"].clone()),
+                    error => Err(RpcError {
+                        code: error["code"].as_i64().unwrap_or(0),
+                        message: error["message"].as_str().unwrap_or_default().to_string(),
+                    }),
+                });
+            }
+        }
+        Ok(results)
+    }
+}
+
+impl PrevoutProvider for BitcoindRpc {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.prevouts(std::slice::from_ref(outpoint))?.remove(0))
+    }
+
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let mut txouts = outputs_from_txs(outpoints, |txids| self.get_raw_transactions(txids))?;
+        resolve_unspent(outpoints, &mut txouts, |missing| self.get_tx_outs(missing))?;
+        Ok(txouts)
+    }
+}
+
+/// Prevout provider backed by bitcoind's REST interface (`-rest`), which
+/// needs no credentials.
+#[derive(Debug, Clone)]
+pub struct BitcoindRest {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl BitcoindRest {
+    /// `url` is the node's HTTP root, e.g. `http://127.0.0.1:8332`.
+    pub fn new(url: &str) -> Self {
+        BitcoindRest {
+            url: url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// `/rest/tx/<txid>.bin`, `None` if the node does not know the transaction.
+    pub fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, MinikeysError> {
+        let Some(raw) = self.get_bin(&format!("tx/{}.bin", txid))? else {
+            return Ok(None);
+        };
+        let tx = deserialize(&raw).map_err(|e| provider_err(format!("bitcoind: {}", e)))?;
+        check_txid(txid, tx).map(Some)
+    }
+
+    /// `/rest/block/<hash>.bin`, `None` if the node does not know the block.
+    pub fn get_block(&self, hash: &BlockHash) -> Result<Option<Block>, MinikeysError> {
+        self.get_bin(&format!("block/{}.bin", hash))?
+            .map(|raw| deserialize(&raw).map_err(|e| provider_err(format!("bitcoind: {}", e))))
+            .transpose()
+    }
+
+    /// `/rest/getutxos/checkmempool/...`, `None` for spent or unknown outputs.
+    pub fn get_utxos(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let mut txouts = Vec::with_capacity(outpoints.len());
+        for chunk in outpoints.chunks(REST_GETUTXOS_MAX) {
+            let path: Vec<String> = chunk
+                .iter()
+                .map(|o| format!("{}-{}", o.txid, o.vout))
+                .collect();
+            let url = format!(
+                "{}/rest/getutxos/checkmempool/{}.json",
+                self.url,
+                path.join("/")
+            );
+            let reply: Value = self
+                .agent
+                .get(&url)
+                .call()
+                .map_err(|e| provider_err(format!("bitcoind: {}", e)))?
+                .into_json()
+                .map_err(|e| provider_err(format!("bitcoind: invalid response: {}", e)))?;
+            // `bitmap` has one digit per requested outpoint, `utxos` one entry
+            // per `1` in it.
+            let bitmap = reply["bitmap"].as_str().unwrap_or_default();
+            let mut utxos = reply["utxos"].as_array().into_iter().flatten();
+            for i in 0..chunk.len() {
+                if bitmap.as_bytes().get(i) == Some(&b'1') {
+                    let utxo = utxos
+                        .next()
+                        .ok_or_else(|| provider_err("bitcoind: getutxos bitmap mismatch"))?;
+                    txouts.push(Some(utxo_json(utxo)?));
+                } else {
+                    txouts.push(None);
+                }
+            }
+        }
+        Ok(txouts)
+    }
+
+    fn get_bin(&self, path: &str) -> Result<Option<Vec<u8>>, MinikeysError> {
+        let url = format!("{}/rest/{}", self.url, path);
+        match self.agent.get(&url).call() {
+            Ok(response) => {
+                let mut raw = vec![];
+                std::io::Read::read_to_end(&mut response.into_reader(), &mut raw)
+                    .map_err(|e| provider_err(format!("bitcoind: {}", e)))?;
+                Ok(Some(raw))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(provider_err(format!("bitcoind: {}", e))),
+        }
+    }
+}
+
+impl PrevoutProvider for BitcoindRest {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.prevouts(std::slice::from_ref(outpoint))?.remove(0))
+    }
+
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let mut txouts = outputs_from_txs(outpoints, |txids| {
+            txids.iter().map(|t| self.get_tx(t)).collect()
+        })?;
+        resolve_unspent(outpoints, &mut txouts, |missing| self.get_utxos(missing))?;
+        Ok(txouts)
+    }
+}
+
+fn provider_err(e: impl Into<String>) -> MinikeysError {
+    MinikeysError::Provider(e.into())
+}
+
+fn rpc_err(e: RpcError) -> MinikeysError {
+    provider_err(format!("bitcoind: {} (code {})", e.message, e.code))
+}
+
+fn raw_tx_result(
+    txid: &Txid,
+    res: Result<Value, RpcError>,
+) -> Result<Option<Transaction>, MinikeysError> {
+    match res {
+        Ok(hex) => check_txid(txid, decode_hex(&hex)?).map(Some),
+        Err(e) if e.code == RPC_NOT_FOUND => Ok(None),
+        Err(e) => Err(rpc_err(e)),
+    }
+}
+
+/// `tx` if it is the transaction `txid` was requested for.
+fn check_txid(txid: &Txid, tx: Transaction) -> Result<Transaction, MinikeysError> {
+    if tx.txid() != *txid {
+        return Err(provider_err(format!(
+            "bitcoind: got transaction {} for {}",
+            tx.txid(),
+            txid
+        )));
+    }
+    Ok(tx)
+}
+
+fn decode_hex<T: bitcoin::consensus::Decodable>(hex: &Value) -> Result<T, MinikeysError> {
+    let raw = hex
+        .as_str()
+        .and_then(|h| hex::decode(h).ok())
+        .ok_or_else(|| provider_err("bitcoind: expected a hex string"))?;
+    deserialize(&raw).map_err(|e| provider_err(format!("bitcoind: {}", e)))
+}
+
+/// An output as described by `gettxout` and REST `getutxos`: a BTC `value`
+/// and a `scriptPubKey` object with its `hex`.
+fn utxo_json(utxo: &Value) -> Result<TxOut, MinikeysError> {
+    let value = utxo["value"]
+        .as_f64()
+        .and_then(|btc| Amount::from_btc(btc).ok())
+        .ok_or_else(|| provider_err("bitcoind: output without a valid value"))?;
+    let script = utxo["scriptPubKey"]["hex"]
+        .as_str()
+        .and_then(|h| hex::decode(h).ok())
+        .ok_or_else(|| provider_err("bitcoind: output without a valid scriptPubKey"))?;
+    Ok(TxOut {
+        value: value.to_sat(),
+        script_pubkey: ScriptBuf::from(script),
+    })
+}
+
+/// Fills the outputs whose transaction was not found from the UTXO set.
+fn resolve_unspent(
+    outpoints: &[OutPoint],
+    txouts: &mut [Option<TxOut>],
+    lookup: impl FnOnce(&[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError>,
+) -> Result<(), MinikeysError> {
+    let missing: Vec<OutPoint> = outpoints
+        .iter()
+        .zip(txouts.iter())
+        .filter(|(_, txout)| txout.is_none())
+        .map(|(o, _)| *o)
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let mut found = lookup(&missing)?.into_iter();
+    for txout in txouts.iter_mut().filter(|t| t.is_none()) {
+        *txout = found.next().flatten();
+    }
+    Ok(())
+}
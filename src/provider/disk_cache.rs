@@ -0,0 +1,303 @@
+//! Prevout cache persisted on disk, in a sled database.
+//!
+//! A spent output never changes once its funding transaction is confirmed,
+//! so cached answers stay valid forever; only known outputs are cached,
+//! since an unknown one may show up later. Entries are evicted least
+//! recently used first once a size limit is exceeded.
+
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::Hash;
+use bitcoin::{OutPoint, TxOut};
+
+#[cfg(feature = "async")]
+use super::AsyncPrevoutProvider;
+use super::PrevoutProvider;
+use crate::MinikeysError;
+
+const OUTPOINT_LEN: usize = 36;
+const TICK_LEN: usize = 8;
+
+/// Keeps on disk every output resolved by the inner provider, so that
+/// re-running an analysis hits the inner provider only for new outputs and
+/// works offline once everything it needs was fetched.
+#[derive(Debug)]
+pub struct DiskCachedProvider<P> {
+    inner: P,
+    db: sled::Db,
+    /// Outpoint -> last access tick, then the consensus-encoded output.
+    entries: sled::Tree,
+    /// Access tick, then outpoint -> nothing: entries oldest access first.
+    /// Ticks left behind by an interrupted update are skipped on eviction.
+    by_access: sled::Tree,
+    max_entries: Option<u64>,
+    max_bytes: Option<u64>,
+    usage: Mutex<Usage>,
+}
+
+/// What the limits are checked against.
+#[derive(Debug, Default)]
+struct Usage {
+    entries: u64,
+    /// Size of the keys and values of `entries`.
+    bytes: u64,
+}
+
+impl<P> DiskCachedProvider<P> {
+    /// Opens (or creates) the cache database in the directory `path`.
+    pub fn open(path: impl AsRef<Path>, inner: P) -> Result<Self, MinikeysError> {
+        let db = sled::Config::new().path(path).open().map_err(cache_err)?;
+        let entries = db.open_tree("prevouts").map_err(cache_err)?;
+        let by_access = db.open_tree("by_access").map_err(cache_err)?;
+        let mut usage = Usage::default();
+        for entry in entries.iter() {
+            let (key, value) = entry.map_err(cache_err)?;
+            usage.entries += 1;
+            usage.bytes += (key.len() + value.len()) as u64;
+        }
+        Ok(DiskCachedProvider {
+            inner,
+            db,
+            entries,
+            by_access,
+            max_entries: None,
+            max_bytes: None,
+            usage: Mutex::new(usage),
+        })
+    }
+
+    /// Maximum number of cached outputs.
+    pub fn max_entries(mut self, max_entries: u64) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Maximum size of the cached outputs, in bytes of keys and values. The
+    /// database files take more, for their own bookkeeping.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// The wrapped provider.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Number of cached outputs.
+    pub fn len(&self) -> u64 {
+        self.lock().entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Size of the cached outputs, as counted by [`Self::max_bytes`].
+    pub fn size_bytes(&self) -> u64 {
+        self.lock().bytes
+    }
+
+    pub fn clear(&self) -> Result<(), MinikeysError> {
+        let mut usage = self.lock();
+        self.entries.clear().map_err(cache_err)?;
+        self.by_access.clear().map_err(cache_err)?;
+        *usage = Usage::default();
+        Ok(())
+    }
+
+    /// Writes pending changes to disk, which also happens periodically and
+    /// when the provider is dropped.
+    pub fn flush(&self) -> Result<(), MinikeysError> {
+        self.db.flush().map(drop).map_err(cache_err)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Usage> {
+        // Counters are only ever updated with the database, a poisoned lock
+        // is still usable.
+        self.usage.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Cached answers, and the outpoints still to be fetched.
+    fn split(
+        &self,
+        outpoints: &[OutPoint],
+    ) -> Result<(Vec<Option<TxOut>>, Vec<OutPoint>), MinikeysError> {
+        let _usage = self.lock();
+        let mut cached = Vec::with_capacity(outpoints.len());
+        let mut missing = vec![];
+        for outpoint in outpoints {
+            let txout = self.get(outpoint)?;
+            if txout.is_none() {
+                missing.push(*outpoint);
+            }
+            cached.push(txout);
+        }
+        Ok((cached, missing))
+    }
+
+    /// Stores `fetched` (answers for the `None`s of `cached`, in order),
+    /// evicts what exceeds the limits and merges them into `cached`.
+    fn merge(
+        &self,
+        outpoints: &[OutPoint],
+        mut cached: Vec<Option<TxOut>>,
+        fetched: Vec<Option<TxOut>>,
+    ) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let mut usage = self.lock();
+        let mut fetched = fetched.into_iter();
+        for (outpoint, txout) in outpoints.iter().zip(cached.iter_mut()) {
+            if txout.is_none() {
+                *txout = fetched.next().flatten();
+                if let Some(txout) = txout {
+                    self.put(&mut usage, outpoint, txout)?;
+                }
+            }
+        }
+        self.evict(&mut usage)?;
+        Ok(cached)
+    }
+
+    /// Looks up `outpoint`, marking it as just used.
+    fn get(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        let key = outpoint_key(outpoint);
+        let Some(value) = self.entries.get(key).map_err(cache_err)? else {
+            return Ok(None);
+        };
+        if value.len() < TICK_LEN {
+            return Err(cache_err("corrupted entry"));
+        }
+        let (old_tick, raw) = value.split_at(TICK_LEN);
+        let txout: TxOut = deserialize(raw).map_err(cache_err)?;
+        let tick = self.tick()?;
+        self.by_access
+            .insert(access_key(tick, &key), &[])
+            .map_err(cache_err)?;
+        self.entries
+            .insert(key, [&tick[..], raw].concat())
+            .map_err(cache_err)?;
+        self.by_access
+            .remove([old_tick, &key[..]].concat())
+            .map_err(cache_err)?;
+        Ok(Some(txout))
+    }
+
+    fn put(
+        &self,
+        usage: &mut Usage,
+        outpoint: &OutPoint,
+        txout: &TxOut,
+    ) -> Result<(), MinikeysError> {
+        let key = outpoint_key(outpoint);
+        let tick = self.tick()?;
+        let value = [&tick[..], &serialize(txout)].concat();
+        let new_bytes = (key.len() + value.len()) as u64;
+        self.by_access
+            .insert(access_key(tick, &key), &[])
+            .map_err(cache_err)?;
+        match self.entries.insert(key, value).map_err(cache_err)? {
+            Some(old) => {
+                usage.bytes = usage.bytes - (key.len() + old.len()) as u64 + new_bytes;
+                if old.len() >= TICK_LEN {
+                    self.by_access
+                        .remove([&old[..TICK_LEN], &key[..]].concat())
+                        .map_err(cache_err)?;
+                }
+            }
+            None => {
+                usage.entries += 1;
+                usage.bytes += new_bytes;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the least recently used entries until the limits are met.
+    fn evict(&self, usage: &mut Usage) -> Result<(), MinikeysError> {
+        let over = |usage: &Usage| {
+            self.max_entries.is_some_and(|max| usage.entries > max)
+                || self.max_bytes.is_some_and(|max| usage.bytes > max)
+        };
+        while over(usage) {
+            let Some((access, _)) = self.by_access.pop_min().map_err(cache_err)? else {
+                break;
+            };
+            let (tick, key) = access.split_at(TICK_LEN);
+            let Some(value) = self.entries.get(key).map_err(cache_err)? else {
+                continue;
+            };
+            // Stale access key of an entry used since
+            if value.get(..TICK_LEN) != Some(tick) {
+                continue;
+            }
+            self.entries.remove(key).map_err(cache_err)?;
+            usage.entries -= 1;
+            usage.bytes -= (key.len() + value.len()) as u64;
+        }
+        Ok(())
+    }
+
+    /// A new access tick, increasing across runs.
+    fn tick(&self) -> Result<[u8; TICK_LEN], MinikeysError> {
+        Ok(self.db.generate_id().map_err(cache_err)?.to_be_bytes())
+    }
+}
+
+impl<P> Drop for DiskCachedProvider<P> {
+    fn drop(&mut self) {
+        let _ = self.db.flush();
+    }
+}
+
+impl<P: PrevoutProvider> PrevoutProvider for DiskCachedProvider<P> {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.prevouts(std::slice::from_ref(outpoint))?.remove(0))
+    }
+
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let (cached, missing) = self.split(outpoints)?;
+        if missing.is_empty() {
+            return Ok(cached);
+        }
+        let fetched = self.inner.prevouts(&missing)?;
+        self.merge(outpoints, cached, fetched)
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl<P: AsyncPrevoutProvider> AsyncPrevoutProvider for DiskCachedProvider<P> {
+    async fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self
+            .prevouts(std::slice::from_ref(outpoint))
+            .await?
+            .remove(0))
+    }
+
+    async fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let (cached, missing) = self.split(outpoints)?;
+        if missing.is_empty() {
+            return Ok(cached);
+        }
+        let fetched = self.inner.prevouts(&missing).await?;
+        self.merge(outpoints, cached, fetched)
+    }
+}
+
+/// Txid bytes then big-endian vout.
+fn outpoint_key(outpoint: &OutPoint) -> [u8; OUTPOINT_LEN] {
+    let mut key = [0; OUTPOINT_LEN];
+    key[..32].copy_from_slice(outpoint.txid.as_byte_array());
+    key[32..].copy_from_slice(&outpoint.vout.to_be_bytes());
+    key
+}
+
+fn access_key(tick: [u8; TICK_LEN], key: &[u8; OUTPOINT_LEN]) -> Vec<u8> {
+    [&tick[..], &key[..]].concat()
+}
+
+fn cache_err(e: impl std::fmt::Display) -> MinikeysError {
+    MinikeysError::Provider(format!("disk cache: {}", e))
+}
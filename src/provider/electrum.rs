@@ -0,0 +1,325 @@
+//! Electrum protocol backend, over TCP or TLS.
+//!
+//! Requests are newline-delimited JSON-RPC; a batch is written in one go and
+//! the replies, which servers may send in any order, matched by id.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bitcoin::consensus::deserialize;
+use bitcoin::{OutPoint, Transaction, TxOut, Txid};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use serde_json::{json, Value};
+
+use super::{outputs_from_txs, PrevoutProvider};
+use crate::MinikeysError;
+
+/// Messages of the errors servers return for unknown transactions, lower
+/// case: bitcoind's when relayed as a daemon error (ElectrumX, Fulcrum,
+/// electrs), and those of servers looking transactions up in their own
+/// index.
+const NOT_FOUND_MESSAGES: [&str; 3] = [
+    "no such mempool",
+    "transaction not found",
+    "missing transaction",
+];
+/// Calls sent in one batch unless configured otherwise.
+const DEFAULT_BATCH_SIZE: usize = 100;
+/// Protocol version negotiated with `server.version`.
+const PROTOCOL_VERSION: &str = "1.4";
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How the certificate of a TLS server is checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElectrumTls {
+    /// Against the Mozilla root certificates.
+    WebpkiRoots,
+    /// Against these DER-encoded root certificates, e.g. the server's own
+    /// self-signed certificate.
+    Roots(Vec<Vec<u8>>),
+    /// Not at all. Many Electrum servers use self-signed certificates; this
+    /// still encrypts the connection but does not authenticate the server.
+    AcceptInvalidCerts,
+}
+
+trait Stream: Read + Write + Send {}
+impl<T: Read + Write + Send> Stream for T {}
+
+struct Connection {
+    reader: BufReader<Box<dyn Stream>>,
+    next_id: u64,
+}
+
+/// Prevout provider backed by an Electrum server.
+pub struct ElectrumProvider {
+    conn: Mutex<Connection>,
+    batch_size: usize,
+}
+
+impl ElectrumProvider {
+    /// Connects over plain TCP, e.g. to `127.0.0.1:50001`.
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> Result<Self, MinikeysError> {
+        let tcp = connect(addr)?;
+        Self::handshake(Box::new(tcp))
+    }
+
+    /// Connects over TLS, e.g. to `electrum.example.com:50002`. `domain` is
+    /// the server name presented to and checked against the certificate.
+    pub fn connect_tls(
+        addr: impl ToSocketAddrs,
+        domain: &str,
+        tls: ElectrumTls,
+    ) -> Result<Self, MinikeysError> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(provider_err)?;
+        let config = match tls {
+            ElectrumTls::WebpkiRoots => {
+                let roots = RootCertStore {
+                    roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+                };
+                builder.with_root_certificates(roots)
+            }
+            ElectrumTls::Roots(certs) => {
+                let mut roots = RootCertStore::empty();
+                for cert in certs {
+                    roots
+                        .add(CertificateDer::from(cert))
+                        .map_err(provider_err)?;
+                }
+                builder.with_root_certificates(roots)
+            }
+            ElectrumTls::AcceptInvalidCerts => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyCert(provider))),
+        }
+        .with_no_client_auth();
+        let name = ServerName::try_from(domain.to_string()).map_err(provider_err)?;
+        let tls = ClientConnection::new(Arc::new(config), name).map_err(provider_err)?;
+        let tcp = connect(addr)?;
+        Self::handshake(Box::new(rustls::StreamOwned::new(tls, tcp)))
+    }
+
+    /// Maximum number of calls sent in one batch.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// `blockchain.transaction.get`, `None` if the server does not know the
+    /// transaction.
+    pub fn get_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, MinikeysError> {
+        Ok(self.get_transactions(std::slice::from_ref(txid))?.remove(0))
+    }
+
+    /// `blockchain.transaction.get` for several transactions, in batches.
+    pub fn get_transactions(
+        &self,
+        txids: &[Txid],
+    ) -> Result<Vec<Option<Transaction>>, MinikeysError> {
+        let params: Vec<Value> = txids
+            .iter()
+            .map(|t| json!([t.to_string(), false]))
+            .collect();
+        let results = self.call_batch("blockchain.transaction.get", &params)?;
+        txids
+            .iter()
+            .zip(results)
+            .map(|(txid, res)| {
+                // Servers report unknown transactions as call errors, along
+                // with rate limits, overload and daemon failures
+                let hex = match res {
+                    Ok(hex) => hex,
+                    Err(error) if is_not_found(&error) => return Ok(None),
+                    Err(error) => {
+                        return Err(provider_err(format!(
+                            "blockchain.transaction.get {}: {}",
+                            txid, error
+                        )))
+                    }
+                };
+                let raw = hex
+                    .as_str()
+                    .and_then(|h| hex::decode(h).ok())
+                    .ok_or_else(|| provider_err("expected a hex string"))?;
+                let tx: Transaction = deserialize(&raw).map_err(provider_err)?;
+                if tx.txid() != *txid {
+                    return Err(provider_err(format!(
+                        "got transaction {} for {}",
+                        tx.txid(),
+                        txid
+                    )));
+                }
+                Ok(Some(tx))
+            })
+            .collect()
+    }
+
+    fn handshake(stream: Box<dyn Stream>) -> Result<Self, MinikeysError> {
+        let provider = ElectrumProvider {
+            conn: Mutex::new(Connection {
+                reader: BufReader::new(stream),
+                next_id: 0,
+            }),
+            batch_size: DEFAULT_BATCH_SIZE,
+        };
+        let params = json!([
+            concat!("minikeys ", env!("CARGO_PKG_VERSION")),
+            PROTOCOL_VERSION
+        ]);
+        provider
+            .call_batch("server.version", &[params])?
+            .remove(0)
+            .map_err(|e| provider_err(format!("server.version: {}", e)))?;
+        Ok(provider)
+    }
+
+    /// Calls `method` once per element of `params`, `batch_size` calls at a
+    /// time, and returns the results (or error objects) in order.
+    fn call_batch(
+        &self,
+        method: &str,
+        params: &[Value],
+    ) -> Result<Vec<Result<Value, Value>>, MinikeysError> {
+        let mut conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let mut results = Vec::with_capacity(params.len());
+        for chunk in params.chunks(self.batch_size) {
+            let first_id = conn.next_id;
+            conn.next_id += chunk.len() as u64;
+            let mut request = vec![];
+            for (id, params) in (first_id..).zip(chunk) {
+                let call = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+                serde_json::to_writer(&mut request, &call).map_err(provider_err)?;
+                request.push(b'\n');
+            }
+            let stream = conn.reader.get_mut();
+            stream
+                .write_all(&request)
+                .and_then(|()| stream.flush())
+                .map_err(provider_err)?;
+
+            let mut replies = HashMap::new();
+            while replies.len() < chunk.len() {
+                let mut line = String::new();
+                if conn.reader.read_line(&mut line).map_err(provider_err)? == 0 {
+                    return Err(provider_err("connection closed by server"));
+                }
+                let reply: Value = serde_json::from_str(&line).map_err(provider_err)?;
+                // Subscription notifications carry no id
+                match reply["id"].as_u64() {
+                    Some(id) if (first_id..conn.next_id).contains(&id) => {
+                        replies.insert(id, reply);
+                    }
+                    _ => {}
+                }
+            }
+            for id in first_id..conn.next_id {
+                let mut reply = replies.remove(&id).unwrap_or_default();
+                results.push(match reply["error"].take() {
+                    Value::Null => Ok(reply["result"].take()),
+                    error => Err(error),
+                });
+            }
+        }
+        Ok(results)
+    }
+}
+
+impl std::fmt::Debug for ElectrumProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ElectrumProvider")
+            .field("batch_size", &self.batch_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PrevoutProvider for ElectrumProvider {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.prevouts(std::slice::from_ref(outpoint))?.remove(0))
+    }
+
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        outputs_from_txs(outpoints, |txids| self.get_transactions(txids))
+    }
+}
+
+fn connect(addr: impl ToSocketAddrs) -> Result<TcpStream, MinikeysError> {
+    let tcp = TcpStream::connect(addr).map_err(provider_err)?;
+    tcp.set_read_timeout(Some(TIMEOUT)).map_err(provider_err)?;
+    tcp.set_write_timeout(Some(TIMEOUT)).map_err(provider_err)?;
+    Ok(tcp)
+}
+
+/// Whether the error object of a `blockchain.transaction.get` call reports
+/// an unknown transaction, as opposed to a failure of the server.
+fn is_not_found(error: &Value) -> bool {
+    let message = error["message"]
+        .as_str()
+        .or_else(|| error.as_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    NOT_FOUND_MESSAGES.iter().any(|m| message.contains(m))
+}
+
+fn provider_err(e: impl std::fmt::Display) -> MinikeysError {
+    MinikeysError::Provider(format!("electrum: {}", e))
+}
+
+/// Certificate verifier of [`ElectrumTls::AcceptInvalidCerts`]: any
+/// certificate is accepted, handshake signatures are still checked.
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,122 @@
+//! Esplora HTTP API backend (Blockstream's electrs, mempool.space, ...).
+
+use std::io::Read;
+use std::time::Duration;
+
+use bitcoin::consensus::deserialize;
+use bitcoin::{OutPoint, Transaction, TxOut, Txid};
+use serde_json::Value;
+
+use super::{outputs_from_txs, PrevoutProvider};
+use crate::MinikeysError;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The input spending an output, as reported by `/tx/:txid/outspend/:vout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outspend {
+    pub txid: Txid,
+    pub vin: u32,
+}
+
+/// Prevout provider backed by an Esplora instance.
+#[derive(Debug, Clone)]
+pub struct EsploraProvider {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl EsploraProvider {
+    /// `url` is the API root, e.g. `https://blockstream.info/api`.
+    pub fn new(url: &str) -> Self {
+        EsploraProvider {
+            url: url.trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        }
+    }
+
+    /// `/tx/:txid/hex`, `None` if the indexer does not know the transaction.
+    pub fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, MinikeysError> {
+        let Some(hex) = self.get(&format!("tx/{}/hex", txid))? else {
+            return Ok(None);
+        };
+        let raw = hex::decode(hex.trim()).map_err(|e| provider_err(e.to_string()))?;
+        let tx: Transaction = deserialize(&raw).map_err(|e| provider_err(e.to_string()))?;
+        if tx.txid() != *txid {
+            return Err(provider_err(format!(
+                "got transaction {} for {}",
+                tx.txid(),
+                txid
+            )));
+        }
+        Ok(Some(tx))
+    }
+
+    /// `/tx/:txid/outspend/:vout`: the input spending `outpoint`, `None` if
+    /// it is unspent or unknown.
+    pub fn get_outspend(&self, outpoint: &OutPoint) -> Result<Option<Outspend>, MinikeysError> {
+        let path = format!("tx/{}/outspend/{}", outpoint.txid, outpoint.vout);
+        let Some(body) = self.get(&path)? else {
+            return Ok(None);
+        };
+        let json: Value = serde_json::from_str(&body).map_err(|e| provider_err(e.to_string()))?;
+        if json["spent"] != Value::Bool(true) {
+            return Ok(None);
+        }
+        let txid = json["txid"].as_str().and_then(|t| t.parse().ok());
+        let vin = json["vin"].as_u64().and_then(|v| u32::try_from(v).ok());
+        match (txid, vin) {
+            (Some(txid), Some(vin)) => Ok(Some(Outspend { txid, vin })),
+            _ => Err(provider_err(format!("invalid outspend for {}", outpoint))),
+        }
+    }
+
+    /// The transaction spending `outpoint` and the index of the spending
+    /// input, `None` if it is unspent or unknown.
+    pub fn spending_tx(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<(Transaction, usize)>, MinikeysError> {
+        let Some(outspend) = self.get_outspend(outpoint)? else {
+            return Ok(None);
+        };
+        let tx = self.get_tx(&outspend.txid)?.ok_or_else(|| {
+            provider_err(format!("spending transaction {} not found", outspend.txid))
+        })?;
+        Ok(Some((tx, outspend.vin as usize)))
+    }
+
+    /// GETs `path` as text, `None` on a 404.
+    fn get(&self, path: &str) -> Result<Option<String>, MinikeysError> {
+        let url = format!("{}/{}", self.url, path);
+        match self.agent.get(&url).call() {
+            Ok(response) => {
+                let mut body = String::new();
+                response
+                    .into_reader()
+                    .read_to_string(&mut body)
+                    .map_err(|e| provider_err(e.to_string()))?;
+                Ok(Some(body))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(provider_err(e.to_string())),
+        }
+    }
+}
+
+impl PrevoutProvider for EsploraProvider {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.prevouts(std::slice::from_ref(outpoint))?.remove(0))
+    }
+
+    /// Fetches every distinct funding transaction once.
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        outputs_from_txs(outpoints, |txids| {
+            txids.iter().map(|txid| self.get_tx(txid)).collect()
+        })
+    }
+}
+
+fn provider_err(e: impl std::fmt::Display) -> MinikeysError {
+    MinikeysError::Provider(format!("esplora: {}", e))
+}
@@ -0,0 +1,238 @@
+//! Providers wrapping other providers.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use bitcoin::{OutPoint, TxOut};
+
+#[cfg(feature = "async")]
+use super::AsyncPrevoutProvider;
+use super::PrevoutProvider;
+use crate::MinikeysError;
+
+/// The answers of a wrapped provider, if there is one per outpoint asked for
+/// as [`PrevoutProvider::prevouts`] requires.
+fn checked(
+    txouts: Result<Vec<Option<TxOut>>, MinikeysError>,
+    asked: usize,
+) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+    match txouts {
+        Ok(txouts) if txouts.len() != asked => Err(MinikeysError::Provider(format!(
+            "{} outputs returned for {} outpoints",
+            txouts.len(),
+            asked
+        ))),
+        txouts => txouts,
+    }
+}
+
+/// Keeps in memory every output resolved by the inner provider, so repeated
+/// lookups (e.g. the inputs of a taproot transaction) hit it only once.
+#[derive(Debug, Default)]
+pub struct CachedProvider<P> {
+    inner: P,
+    cache: Mutex<HashMap<OutPoint, TxOut>>,
+}
+
+impl<P> CachedProvider<P> {
+    pub fn new(inner: P) -> Self {
+        CachedProvider {
+            inner,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The wrapped provider.
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Number of cached outputs.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<OutPoint, TxOut>> {
+        // The map is never left half-updated, a poisoned lock is still usable.
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Cached answers, and the outpoints still to be fetched.
+    fn split(&self, outpoints: &[OutPoint]) -> (Vec<Option<TxOut>>, Vec<OutPoint>) {
+        let cache = self.lock();
+        let cached: Vec<Option<TxOut>> = outpoints.iter().map(|o| cache.get(o).cloned()).collect();
+        let missing = outpoints
+            .iter()
+            .zip(&cached)
+            .filter(|(_, txout)| txout.is_none())
+            .map(|(o, _)| *o)
+            .collect();
+        (cached, missing)
+    }
+
+    /// Stores `fetched` (answers for the `None`s of `cached`, in order) and
+    /// merges them into `cached`.
+    fn merge(
+        &self,
+        outpoints: &[OutPoint],
+        mut cached: Vec<Option<TxOut>>,
+        fetched: Vec<Option<TxOut>>,
+    ) -> Vec<Option<TxOut>> {
+        let mut cache = self.lock();
+        let mut fetched = fetched.into_iter();
+        for (outpoint, txout) in outpoints.iter().zip(cached.iter_mut()) {
+            if txout.is_none() {
+                *txout = fetched.next().flatten();
+                if let Some(txout) = txout {
+                    cache.insert(*outpoint, txout.clone());
+                }
+            }
+        }
+        cached
+    }
+}
+
+impl<P: PrevoutProvider> PrevoutProvider for CachedProvider<P> {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.prevouts(std::slice::from_ref(outpoint))?.remove(0))
+    }
+
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let (cached, missing) = self.split(outpoints);
+        if missing.is_empty() {
+            return Ok(cached);
+        }
+        let fetched = checked(self.inner.prevouts(&missing), missing.len())?;
+        Ok(self.merge(outpoints, cached, fetched))
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl<P: AsyncPrevoutProvider> AsyncPrevoutProvider for CachedProvider<P> {
+    async fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self
+            .prevouts(std::slice::from_ref(outpoint))
+            .await?
+            .remove(0))
+    }
+
+    async fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let (cached, missing) = self.split(outpoints);
+        if missing.is_empty() {
+            return Ok(cached);
+        }
+        let fetched = checked(self.inner.prevouts(&missing).await, missing.len())?;
+        Ok(self.merge(outpoints, cached, fetched))
+    }
+}
+
+/// Asks `primary` first and `fallback` for whatever it did not know or
+/// failed to fetch. The primary's error is returned only when the fallback
+/// does not know the output either.
+#[derive(Debug, Clone, Default)]
+pub struct FallbackProvider<A, B> {
+    primary: A,
+    fallback: B,
+}
+
+impl<A, B> FallbackProvider<A, B> {
+    pub fn new(primary: A, fallback: B) -> Self {
+        FallbackProvider { primary, fallback }
+    }
+
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+
+    pub fn fallback(&self) -> &B {
+        &self.fallback
+    }
+}
+
+/// Answers of the fallback merged into those of the primary.
+fn merge_fallback(
+    primary: Result<Vec<Option<TxOut>>, MinikeysError>,
+    fallback: Result<Vec<Option<TxOut>>, MinikeysError>,
+) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+    match (primary, fallback) {
+        (Ok(primary), Ok(fallback)) => Ok(fill_missing(primary, fallback)),
+        (Ok(_), Err(e)) => Err(e),
+        (Err(_), Ok(fallback)) if fallback.iter().all(Option::is_some) => Ok(fallback),
+        (Err(e), _) => Err(e),
+    }
+}
+
+fn fill_missing(primary: Vec<Option<TxOut>>, fallback: Vec<Option<TxOut>>) -> Vec<Option<TxOut>> {
+    let mut fallback = fallback.into_iter();
+    primary
+        .into_iter()
+        .map(|txout| match txout {
+            Some(txout) => Some(txout),
+            None => fallback.next().flatten(),
+        })
+        .collect()
+}
+
+/// Outpoints the primary could not resolve, `outpoints` itself if it failed.
+fn unresolved(
+    outpoints: &[OutPoint],
+    primary: &Result<Vec<Option<TxOut>>, MinikeysError>,
+) -> Vec<OutPoint> {
+    match primary {
+        Ok(txouts) => outpoints
+            .iter()
+            .zip(txouts)
+            .filter(|(_, txout)| txout.is_none())
+            .map(|(o, _)| *o)
+            .collect(),
+        Err(_) => outpoints.to_vec(),
+    }
+}
+
+impl<A: PrevoutProvider, B: PrevoutProvider> PrevoutProvider for FallbackProvider<A, B> {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.prevouts(std::slice::from_ref(outpoint))?.remove(0))
+    }
+
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let primary = checked(self.primary.prevouts(outpoints), outpoints.len());
+        let missing = unresolved(outpoints, &primary);
+        if missing.is_empty() {
+            return primary;
+        }
+        let fallback = checked(self.fallback.prevouts(&missing), missing.len());
+        merge_fallback(primary, fallback)
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl<A: AsyncPrevoutProvider, B: AsyncPrevoutProvider> AsyncPrevoutProvider
+    for FallbackProvider<A, B>
+{
+    async fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self
+            .prevouts(std::slice::from_ref(outpoint))
+            .await?
+            .remove(0))
+    }
+
+    async fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let primary = checked(self.primary.prevouts(outpoints).await, outpoints.len());
+        let missing = unresolved(outpoints, &primary);
+        if missing.is_empty() {
+            return primary;
+        }
+        let fallback = checked(self.fallback.prevouts(&missing).await, missing.len());
+        merge_fallback(primary, fallback)
+    }
+}
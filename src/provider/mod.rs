@@ -0,0 +1,231 @@
+//! Pluggable sources of spent outputs.
+//!
+//! A [`PrevoutProvider`] answers `OutPoint -> TxOut` lookups. The
+//! verification functions taking a provider ask it for exactly the outputs
+//! an input needs: its own spent output, plus those of every other input
+//! when it spends a taproot output.
+
+#[cfg(feature = "bitcoind")]
+mod bitcoind;
+#[cfg(feature = "disk-cache")]
+mod disk_cache;
+#[cfg(feature = "electrum")]
+mod electrum;
+#[cfg(feature = "esplora")]
+mod esplora;
+mod layer;
+mod snapshot;
+
+use std::sync::Arc;
+
+#[cfg(any(feature = "bitcoind", feature = "electrum", feature = "esplora"))]
+use bitcoin::Txid;
+use bitcoin::{OutPoint, Transaction, TxOut};
+
+use crate::{script, MinikeysError, PrevoutMap};
+
+#[cfg(feature = "bitcoind")]
+pub use bitcoind::{BitcoindAuth, BitcoindRest, BitcoindRpc};
+#[cfg(feature = "disk-cache")]
+pub use disk_cache::DiskCachedProvider;
+#[cfg(feature = "electrum")]
+pub use electrum::{ElectrumProvider, ElectrumTls};
+#[cfg(feature = "esplora")]
+pub use esplora::{EsploraProvider, Outspend};
+pub use layer::{CachedProvider, FallbackProvider};
+pub use snapshot::SnapshotProvider;
+
+/// Resolves the outputs spent by transaction inputs.
+pub trait PrevoutProvider {
+    /// The output referenced by `outpoint`, `None` if the provider does not
+    /// know it. Errors are reserved for failures of the provider itself.
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError>;
+
+    /// Batch version of [`PrevoutProvider::prevout`], answers are in the order
+    /// of `outpoints`. Providers talking to a server should override it.
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        outpoints.iter().map(|o| self.prevout(o)).collect()
+    }
+}
+
+/// Async counterpart of [`PrevoutProvider`], for providers doing network I/O
+/// on an async runtime.
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+pub trait AsyncPrevoutProvider: Send + Sync {
+    /// See [`PrevoutProvider::prevout`].
+    async fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError>;
+
+    /// See [`PrevoutProvider::prevouts`].
+    async fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        let mut txouts = Vec::with_capacity(outpoints.len());
+        for outpoint in outpoints {
+            txouts.push(self.prevout(outpoint).await?);
+        }
+        Ok(txouts)
+    }
+}
+
+impl PrevoutProvider for PrevoutMap {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.get(outpoint).cloned())
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl AsyncPrevoutProvider for PrevoutMap {
+    async fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.get(outpoint).cloned())
+    }
+}
+
+impl<P: PrevoutProvider + ?Sized> PrevoutProvider for &P {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        (**self).prevout(outpoint)
+    }
+
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        (**self).prevouts(outpoints)
+    }
+}
+
+impl<P: PrevoutProvider + ?Sized> PrevoutProvider for Box<P> {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        (**self).prevout(outpoint)
+    }
+
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        (**self).prevouts(outpoints)
+    }
+}
+
+impl<P: PrevoutProvider + ?Sized> PrevoutProvider for Arc<P> {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        (**self).prevout(outpoint)
+    }
+
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        (**self).prevouts(outpoints)
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl<P: AsyncPrevoutProvider + ?Sized> AsyncPrevoutProvider for Arc<P> {
+    async fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        (**self).prevout(outpoint).await
+    }
+
+    async fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        (**self).prevouts(outpoints).await
+    }
+}
+
+/// Fetches from `provider` the spent outputs needed to verify `inputs` of
+/// `tx`. Outputs the provider does not know are left out of the map, so
+/// the verification reports them as missing for the inputs concerned.
+pub fn resolve_prevouts<P: PrevoutProvider + ?Sized>(
+    provider: &P,
+    tx: &Transaction,
+    inputs: &[usize],
+) -> Result<PrevoutMap, MinikeysError> {
+    let mut map = PrevoutMap::new();
+    let own = own_outpoints(tx, inputs)?;
+    insert_all(&mut map, &own, provider.prevouts(&own)?);
+    let others = taproot_outpoints(tx, &map);
+    if !others.is_empty() {
+        insert_all(&mut map, &others, provider.prevouts(&others)?);
+    }
+    Ok(map)
+}
+
+/// Async version of [`resolve_prevouts`].
+#[cfg(feature = "async")]
+pub async fn resolve_prevouts_async<P: AsyncPrevoutProvider + ?Sized>(
+    provider: &P,
+    tx: &Transaction,
+    inputs: &[usize],
+) -> Result<PrevoutMap, MinikeysError> {
+    let mut map = PrevoutMap::new();
+    let own = own_outpoints(tx, inputs)?;
+    insert_all(&mut map, &own, provider.prevouts(&own).await?);
+    let others = taproot_outpoints(tx, &map);
+    if !others.is_empty() {
+        insert_all(&mut map, &others, provider.prevouts(&others).await?);
+    }
+    Ok(map)
+}
+
+/// Outpoints spent by `inputs`, coinbase ones excluded.
+fn own_outpoints(tx: &Transaction, inputs: &[usize]) -> Result<Vec<OutPoint>, MinikeysError> {
+    let mut outpoints = vec![];
+    for &index in inputs {
+        let txin = tx
+            .input
+            .get(index)
+            .ok_or(MinikeysError::InputIndexOutOfRange {
+                index,
+                inputs: tx.input.len(),
+            })?;
+        if !txin.previous_output.is_null() && !outpoints.contains(&txin.previous_output) {
+            outpoints.push(txin.previous_output);
+        }
+    }
+    Ok(outpoints)
+}
+
+/// If one of the outputs resolved so far is taproot, the outpoints of every
+/// other input, since taproot sighashes commit to all spent outputs. Not
+/// when all the signatures of those inputs are `SIGHASH_ANYONECANPAY`.
+fn taproot_outpoints(tx: &Transaction, map: &PrevoutMap) -> Vec<OutPoint> {
+    let spends_taproot = tx.input.iter().any(|txin| {
+        map.get(&txin.previous_output)
+            .is_some_and(|txout| txout.script_pubkey.is_v1_p2tr())
+            && !script::taproot_anyonecanpay_only(&txin.witness)
+    });
+    if !spends_taproot {
+        return vec![];
+    }
+    let mut outpoints = vec![];
+    for txin in &tx.input {
+        if map.get(&txin.previous_output).is_none() && !outpoints.contains(&txin.previous_output) {
+            outpoints.push(txin.previous_output);
+        }
+    }
+    outpoints
+}
+
+fn insert_all(map: &mut PrevoutMap, outpoints: &[OutPoint], txouts: Vec<Option<TxOut>>) {
+    for (outpoint, txout) in outpoints.iter().zip(txouts) {
+        if let Some(txout) = txout {
+            map.insert(*outpoint, txout);
+        }
+    }
+}
+
+/// Resolves `outpoints` from the transactions holding them. `fetch_txs` is
+/// called once, with each txid once, and answers in the same order.
+#[cfg(any(feature = "bitcoind", feature = "electrum", feature = "esplora"))]
+fn outputs_from_txs<F>(
+    outpoints: &[OutPoint],
+    fetch_txs: F,
+) -> Result<Vec<Option<TxOut>>, MinikeysError>
+where
+    F: FnOnce(&[Txid]) -> Result<Vec<Option<Transaction>>, MinikeysError>,
+{
+    let mut txids = vec![];
+    for outpoint in outpoints {
+        if !txids.contains(&outpoint.txid) {
+            txids.push(outpoint.txid);
+        }
+    }
+    let txs = fetch_txs(&txids)?;
+    Ok(outpoints
+        .iter()
+        .map(|o| {
+            let i = txids.iter().position(|txid| *txid == o.txid)?;
+            txs.get(i)?.as_ref()?.output.get(o.vout as usize).cloned()
+        })
+        .collect())
+}
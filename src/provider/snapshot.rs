@@ -0,0 +1,156 @@
+//! UTXO set snapshots written by `bitcoin-cli dumptxoutset`.
+//!
+//! Since Bitcoin Core 28 a snapshot starts with the `utxo\xff` magic, a
+//! format version, the network magic, the base block hash and the number of
+//! coins, and the coins follow grouped by txid. Snapshots of Core 25 to 27
+//! only have the base block hash and coin count, then one outpoint per coin.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use bitcoin::hashes::Hash;
+use bitcoin::{BlockHash, Network, OutPoint, TxOut, Txid};
+
+use super::PrevoutProvider;
+use crate::compress::{read_array, read_compact_size, read_compressed_txout, read_varint};
+use crate::MinikeysError;
+
+const SNAPSHOT_MAGIC: [u8; 5] = *b"utxo\xff";
+const SNAPSHOT_VERSION: u16 = 2;
+
+/// Prevout provider answering from the coins of a UTXO snapshot, kept in
+/// memory.
+///
+/// A full mainnet snapshot holds well over a hundred million coins; when
+/// only some transactions are analyzed, [`SnapshotProvider::open_filtered`]
+/// keeps just the coins they spend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotProvider {
+    base_blockhash: BlockHash,
+    coins_count: u64,
+    coins: HashMap<OutPoint, TxOut>,
+}
+
+impl SnapshotProvider {
+    /// Loads every coin of the snapshot at `path`. `network` is checked
+    /// against the snapshot's when the format records it.
+    pub fn open(path: &Path, network: Network) -> Result<Self, MinikeysError> {
+        Self::open_filtered(path, network, |_| true)
+    }
+
+    /// Loads the coins of the snapshot at `path` whose outpoint passes
+    /// `filter`.
+    pub fn open_filtered(
+        path: &Path,
+        network: Network,
+        filter: impl FnMut(&OutPoint) -> bool,
+    ) -> Result<Self, MinikeysError> {
+        let file =
+            File::open(path).map_err(|e| provider_err(format!("{}: {}", path.display(), e)))?;
+        Self::read(BufReader::new(file), network, filter)
+    }
+
+    /// Same as [`SnapshotProvider::open_filtered`], reading from `reader`.
+    pub fn read<R: Read>(
+        mut reader: R,
+        network: Network,
+        mut filter: impl FnMut(&OutPoint) -> bool,
+    ) -> Result<Self, MinikeysError> {
+        let start: [u8; 5] = read_array(&mut reader).map_err(provider_err)?;
+        let (base_blockhash, grouped) = if start == SNAPSHOT_MAGIC {
+            let version = u16::from_le_bytes(read_array(&mut reader).map_err(provider_err)?);
+            if version != SNAPSHOT_VERSION {
+                return Err(provider_err(format!(
+                    "unsupported snapshot version {}",
+                    version
+                )));
+            }
+            let magic: [u8; 4] = read_array(&mut reader).map_err(provider_err)?;
+            if magic != network.magic().to_bytes() {
+                return Err(provider_err(format!("not a {} snapshot", network)));
+            }
+            let hash: [u8; 32] = read_array(&mut reader).map_err(provider_err)?;
+            (hash, true)
+        } else {
+            let rest: [u8; 27] = read_array(&mut reader).map_err(provider_err)?;
+            let mut hash = [0; 32];
+            hash[..5].copy_from_slice(&start);
+            hash[5..].copy_from_slice(&rest);
+            (hash, false)
+        };
+        let coins_count = u64::from_le_bytes(read_array(&mut reader).map_err(provider_err)?);
+
+        let mut coins = HashMap::new();
+        let mut keep = |outpoint: OutPoint, txout: TxOut| {
+            if filter(&outpoint) {
+                coins.insert(outpoint, txout);
+            }
+        };
+        let mut read = 0;
+        while read < coins_count {
+            if grouped {
+                let txid = Txid::from_byte_array(read_array(&mut reader).map_err(provider_err)?);
+                let group = read_compact_size(&mut reader).map_err(provider_err)?;
+                if group == 0 || group > coins_count - read {
+                    return Err(provider_err(format!("invalid coin count for {}", txid)));
+                }
+                for _ in 0..group {
+                    let vout = read_compact_size(&mut reader)
+                        .map_err(provider_err)?
+                        .try_into()
+                        .map_err(|_| provider_err(format!("invalid vout for {}", txid)))?;
+                    keep(OutPoint::new(txid, vout), read_coin(&mut reader)?);
+                }
+                read += group;
+            } else {
+                let txid = Txid::from_byte_array(read_array(&mut reader).map_err(provider_err)?);
+                let vout = u32::from_le_bytes(read_array(&mut reader).map_err(provider_err)?);
+                keep(OutPoint::new(txid, vout), read_coin(&mut reader)?);
+                read += 1;
+            }
+        }
+        Ok(SnapshotProvider {
+            base_blockhash: BlockHash::from_byte_array(base_blockhash),
+            coins_count,
+            coins,
+        })
+    }
+
+    /// Hash of the block the snapshot was taken at.
+    pub fn base_blockhash(&self) -> BlockHash {
+        self.base_blockhash
+    }
+
+    /// Number of coins in the snapshot, loaded or not.
+    pub fn coins_count(&self) -> u64 {
+        self.coins_count
+    }
+
+    /// Number of coins loaded.
+    pub fn len(&self) -> usize {
+        self.coins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coins.is_empty()
+    }
+}
+
+impl PrevoutProvider for SnapshotProvider {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(self.coins.get(outpoint).cloned())
+    }
+}
+
+/// A `Coin`: height and coinbase flag, then the compressed output.
+fn read_coin<R: Read>(reader: &mut R) -> Result<TxOut, MinikeysError> {
+    read_varint(reader)
+        .and_then(|_code| read_compressed_txout(reader))
+        .map_err(provider_err)
+}
+
+fn provider_err(e: impl std::fmt::Display) -> MinikeysError {
+    MinikeysError::Provider(format!("snapshot: {}", e))
+}
@@ -0,0 +1,410 @@
+//! Result types describing who signed an input.
+
+use std::fmt;
+
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
+use bitcoin::taproot::TapNodeHash;
+use bitcoin::Txid;
+use miniscript::interpreter::KeySigPair;
+
+#[cfg(feature = "serde")]
+use crate::serde_utils::display_fromstr;
+use crate::taproot::{
+    find_musig_commitment, find_taproot_commitment, ControlBlockReport, KeyOrdering,
+    MusigAggregation, TaprootCommitment,
+};
+use crate::{MinikeysError, PolicyReport};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Public key of a signer, as it appears in the spent script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SignerKey {
+    /// Full (compressed or uncompressed) key of a legacy or segwit v0 script.
+    Full(bitcoin::PublicKey),
+    /// x-only key of a taproot output or tapscript.
+    XOnly(XOnlyPublicKey),
+}
+
+impl fmt::Display for SignerKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignerKey::Full(pk) => pk.fmt(f),
+            SignerKey::XOnly(pk) => pk.fmt(f),
+        }
+    }
+}
+
+/// Serialized as the key's hex; 32-byte keys are x-only.
+#[cfg(feature = "serde")]
+impl Serialize for SignerKey {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for SignerKey {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let hex = String::deserialize(d)?;
+        if hex.len() == 64 {
+            hex.parse().map(SignerKey::XOnly).map_err(D::Error::custom)
+        } else {
+            hex.parse().map(SignerKey::Full).map_err(D::Error::custom)
+        }
+    }
+}
+
+/// Signature found in the scriptSig or witness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "serde_repr::Signature", try_from = "serde_repr::Signature")
+)]
+pub enum SignerSignature {
+    /// DER-encoded ECDSA signature of a legacy or segwit v0 spend.
+    Ecdsa(bitcoin::ecdsa::Signature),
+    /// BIP340 Schnorr signature of a taproot spend.
+    Schnorr(bitcoin::taproot::Signature),
+}
+
+impl SignerSignature {
+    /// The sighash flag committed to by the signature.
+    pub fn sighash_type(&self) -> SighashType {
+        match self {
+            SignerSignature::Ecdsa(sig) => SighashType::Ecdsa(sig.hash_ty),
+            SignerSignature::Schnorr(sig) => SighashType::Taproot(sig.hash_ty),
+        }
+    }
+}
+
+impl fmt::Display for SignerSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignerSignature::Ecdsa(sig) => sig.sig.fmt(f),
+            SignerSignature::Schnorr(sig) => sig.sig.fmt(f),
+        }
+    }
+}
+
+/// Sighash flag of a signature, for either signature kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "serde_repr::Sighash", try_from = "serde_repr::Sighash")
+)]
+pub enum SighashType {
+    /// Legacy and segwit v0 flag.
+    Ecdsa(EcdsaSighashType),
+    /// Taproot flag, `SIGHASH_DEFAULT` included.
+    Taproot(TapSighashType),
+}
+
+impl fmt::Display for SighashType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SighashType::Ecdsa(ty) => ty.fmt(f),
+            SighashType::Taproot(ty) => ty.fmt(f),
+        }
+    }
+}
+
+/// How an input spends its output, i.e. the script type of the spend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SpendType {
+    /// Bare script, e.g. `OP_CHECKMULTISIG` in the scriptPubKey (P2MS).
+    Bare,
+    P2pk,
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2shP2wpkh,
+    P2shP2wsh,
+    TaprootKeyPath,
+    TaprootScriptPath,
+}
+
+impl fmt::Display for SpendType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SpendType::Bare => "bare",
+            SpendType::P2pk => "p2pk",
+            SpendType::P2pkh => "p2pkh",
+            SpendType::P2sh => "p2sh",
+            SpendType::P2wpkh => "p2wpkh",
+            SpendType::P2wsh => "p2wsh",
+            SpendType::P2shP2wpkh => "p2sh-p2wpkh",
+            SpendType::P2shP2wsh => "p2sh-p2wsh",
+            SpendType::TaprootKeyPath => "p2tr key path",
+            SpendType::TaprootScriptPath => "p2tr script path",
+        })
+    }
+}
+
+/// One key/signature pair met while interpreting an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SignerReport {
+    pub key: SignerKey,
+    pub signature: SignerSignature,
+    pub sighash_type: SighashType,
+    /// Whether the signature is valid for `key` over the input's sighash.
+    pub verified: bool,
+    /// Position of `key` in [`InputReport::script_keys`].
+    pub key_index: Option<usize>,
+    /// Position of the signature among the scriptSig pushes, or among the
+    /// witness elements for segwit spends.
+    pub sig_index: Option<usize>,
+}
+
+impl SignerReport {
+    pub(crate) fn new(key_sig: &KeySigPair, verified: bool) -> Self {
+        let (key, signature) = match *key_sig {
+            KeySigPair::Ecdsa(pk, sig) => (SignerKey::Full(pk), SignerSignature::Ecdsa(sig)),
+            KeySigPair::Schnorr(pk, sig) => (SignerKey::XOnly(pk), SignerSignature::Schnorr(sig)),
+        };
+        SignerReport {
+            key,
+            signature,
+            sighash_type: signature.sighash_type(),
+            verified,
+            key_index: None,
+            sig_index: None,
+        }
+    }
+}
+
+/// Signers of one transaction input, whatever its script type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InputReport {
+    /// Index of the analyzed input in the spending transaction.
+    pub input_index: usize,
+    pub spend_type: SpendType,
+    /// Descriptor of the spent output as inferred by the interpreter.
+    pub descriptor: String,
+    /// Key/signature pairs in the order the interpreter checked them. A
+    /// signature that verified under some key is not listed again for the
+    /// keys it was tried against before (as `OP_CHECKMULTISIG` does).
+    pub signers: Vec<SignerReport>,
+    /// Every key of the spent script (or leaf), in script order, i.e. the
+    /// order of `OP_CHECKMULTISIG` / `OP_CHECKSIGADD` keys.
+    pub script_keys: Vec<SignerKey>,
+    /// Keys of `script_keys` without a verified signature.
+    pub non_signers: Vec<SignerKey>,
+    /// Internal key and script tree the output key of a taproot key path
+    /// spend commits to, once found by [`InputReport::attribute_key_path`].
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub taproot_commitment: Option<TaprootCommitment>,
+    /// MuSig2 participants whose aggregate is the internal key of
+    /// `taproot_commitment`, when found by [`InputReport::attribute_musig`].
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub musig: Option<MusigAggregation>,
+    /// Semantic policy of the spent script (or leaf) and the branch of it
+    /// the spend satisfied; `None` if the script cannot be lifted.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub policy: Option<PolicyReport>,
+    /// Control block of a taproot script path spend, boxed as most inputs
+    /// have none.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub control_block: Option<Box<ControlBlockReport>>,
+}
+
+impl InputReport {
+    /// Keys whose signature verified.
+    pub fn verified_keys(&self) -> impl Iterator<Item = &SignerKey> {
+        self.signers.iter().filter(|s| s.verified).map(|s| &s.key)
+    }
+
+    /// True if at least one signature verified.
+    pub fn has_verified_signer(&self) -> bool {
+        self.signers.iter().any(|s| s.verified)
+    }
+
+    /// Output key of a taproot key path spend whose signature verified.
+    pub fn key_path_signer(&self) -> Option<XOnlyPublicKey> {
+        if self.spend_type != SpendType::TaprootKeyPath {
+            return None;
+        }
+        self.verified_keys().find_map(|key| match key {
+            SignerKey::XOnly(key) => Some(*key),
+            SignerKey::Full(_) => None,
+        })
+    }
+
+    /// Looks among `internal_keys`, with no script tree or one of
+    /// `merkle_roots`, for what the [`InputReport::key_path_signer`] output
+    /// key commits to, and records it in `taproot_commitment`.
+    pub fn attribute_key_path(
+        &mut self,
+        internal_keys: &[XOnlyPublicKey],
+        merkle_roots: &[TapNodeHash],
+    ) -> Option<TaprootCommitment> {
+        let output_key = self.key_path_signer()?;
+        self.taproot_commitment = find_taproot_commitment(&output_key, internal_keys, merkle_roots);
+        self.musig = None;
+        self.taproot_commitment
+    }
+
+    /// Like [`InputReport::attribute_key_path`], the internal key being the
+    /// MuSig2 aggregate of all of `participants` in an order of `ordering`.
+    /// Records the participants in `musig`; the report is left unchanged
+    /// when no ordering matches.
+    pub fn attribute_musig(
+        &mut self,
+        participants: &[PublicKey],
+        merkle_roots: &[TapNodeHash],
+        ordering: KeyOrdering,
+    ) -> Option<TaprootCommitment> {
+        let output_key = self.key_path_signer()?;
+        let (musig, commitment) =
+            find_musig_commitment(&output_key, participants, merkle_roots, ordering)?;
+        self.musig = Some(musig);
+        self.taproot_commitment = Some(commitment);
+        self.taproot_commitment
+    }
+}
+
+/// Signers of every input of a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TxReport {
+    #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+    pub txid: Txid,
+    /// Coinbase transactions spend nothing and are not analyzed.
+    pub coinbase: bool,
+    /// Reports of the inputs that could be analyzed, in input order.
+    pub inputs: Vec<InputReport>,
+    pub summary: TxSummary,
+}
+
+/// Transaction-wide view of the input reports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TxSummary {
+    /// Distinct keys with a verified signature, in order of first appearance.
+    pub signers: Vec<TxSigner>,
+    /// Keys of `signers` that signed more than one input.
+    pub multi_input_signers: Vec<SignerKey>,
+    /// Inputs that could not be analyzed, and why.
+    pub unanalyzed: Vec<UnanalyzedInput>,
+}
+
+/// A key with a verified signature and the inputs it signed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TxSigner {
+    pub key: SignerKey,
+    pub inputs: Vec<usize>,
+}
+
+/// An input skipped by a transaction-level analysis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UnanalyzedInput {
+    pub input_index: usize,
+    pub error: MinikeysError,
+}
+
+/// Tagged string forms of the signature enums: `{"type": "ecdsa", "hex": ...}`
+/// and `{"type": "taproot", "flag": "SIGHASH_ALL"}`.
+#[cfg(feature = "serde")]
+mod serde_repr {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "type", content = "hex", rename_all = "snake_case")]
+    pub enum Signature {
+        Ecdsa(String),
+        Schnorr(String),
+    }
+
+    impl From<super::SignerSignature> for Signature {
+        fn from(sig: super::SignerSignature) -> Self {
+            match sig {
+                super::SignerSignature::Ecdsa(sig) => Signature::Ecdsa(hex::encode(sig.to_vec())),
+                super::SignerSignature::Schnorr(sig) => {
+                    Signature::Schnorr(hex::encode(sig.to_vec()))
+                }
+            }
+        }
+    }
+
+    impl TryFrom<Signature> for super::SignerSignature {
+        type Error = String;
+
+        fn try_from(sig: Signature) -> Result<Self, String> {
+            match sig {
+                Signature::Ecdsa(h) => {
+                    let bytes = hex::decode(h).map_err(|e| e.to_string())?;
+                    bitcoin::ecdsa::Signature::from_slice(&bytes)
+                        .map(super::SignerSignature::Ecdsa)
+                        .map_err(|e| e.to_string())
+                }
+                Signature::Schnorr(h) => {
+                    let bytes = hex::decode(h).map_err(|e| e.to_string())?;
+                    bitcoin::taproot::Signature::from_slice(&bytes)
+                        .map(super::SignerSignature::Schnorr)
+                        .map_err(|e| e.to_string())
+                }
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "type", content = "flag", rename_all = "snake_case")]
+    pub enum Sighash {
+        Ecdsa(String),
+        Taproot(String),
+    }
+
+    impl From<super::SighashType> for Sighash {
+        fn from(ty: super::SighashType) -> Self {
+            match ty {
+                super::SighashType::Ecdsa(ty) => Sighash::Ecdsa(ty.to_string()),
+                super::SighashType::Taproot(ty) => Sighash::Taproot(ty.to_string()),
+            }
+        }
+    }
+
+    impl TryFrom<Sighash> for super::SighashType {
+        type Error = String;
+
+        fn try_from(ty: Sighash) -> Result<Self, String> {
+            match ty {
+                Sighash::Ecdsa(s) => s
+                    .parse()
+                    .map(super::SighashType::Ecdsa)
+                    .map_err(|e: bitcoin::sighash::SighashTypeParseError| e.to_string()),
+                Sighash::Taproot(s) => s
+                    .parse()
+                    .map(super::SighashType::Taproot)
+                    .map_err(|e: bitcoin::sighash::SighashTypeParseError| e.to_string()),
+            }
+        }
+    }
+}
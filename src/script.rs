@@ -0,0 +1,168 @@
+//! Introspection of the spent script and of the input's scriptSig/witness.
+
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::sighash::TapSighashType;
+use bitcoin::taproot::TAPROOT_ANNEX_PREFIX;
+use bitcoin::{Script, TxIn, Witness};
+use miniscript::{ForEachKey, Interpreter, Miniscript, Tap};
+
+use crate::{MinikeysError, SignerKey, SignerSignature, SpendType};
+
+/// Classifies the spend from the spent scriptPubKey and, for P2SH, the
+/// redeem script pushed last in the scriptSig.
+pub(crate) fn spend_type(
+    interpreter: &Interpreter,
+    script_pubkey: &Script,
+    txin: &TxIn,
+) -> SpendType {
+    if script_pubkey.is_p2pk() {
+        SpendType::P2pk
+    } else if script_pubkey.is_p2pkh() {
+        SpendType::P2pkh
+    } else if script_pubkey.is_v0_p2wpkh() {
+        SpendType::P2wpkh
+    } else if script_pubkey.is_v0_p2wsh() {
+        SpendType::P2wsh
+    } else if script_pubkey.is_v1_p2tr() {
+        if interpreter.is_taproot_v1_key_spend() {
+            SpendType::TaprootKeyPath
+        } else {
+            SpendType::TaprootScriptPath
+        }
+    } else if script_pubkey.is_p2sh() {
+        match redeem_script(txin) {
+            Some(redeem) if redeem.is_v0_p2wpkh() => SpendType::P2shP2wpkh,
+            Some(redeem) if redeem.is_v0_p2wsh() => SpendType::P2shP2wsh,
+            _ => SpendType::P2sh,
+        }
+    } else {
+        SpendType::Bare
+    }
+}
+
+/// The last push of the scriptSig, which is the redeem script of a P2SH spend.
+pub(crate) fn redeem_script(txin: &TxIn) -> Option<&Script> {
+    match txin.script_sig.instructions().last()? {
+        Ok(Instruction::PushBytes(b)) => Some(Script::from_bytes(b.as_bytes())),
+        _ => None,
+    }
+}
+
+/// All keys of the spent script, in script order.
+///
+/// Pre-taproot keys come from the descriptor inferred by the interpreter. A
+/// taproot key spend has the output key as its only key, a script spend the
+/// keys of the revealed leaf.
+pub(crate) fn script_keys(
+    interpreter: &Interpreter,
+    script_pubkey: &Script,
+    txin: &TxIn,
+    input_index: usize,
+) -> Result<Vec<SignerKey>, MinikeysError> {
+    let interpreter_err = |reason: String| MinikeysError::Interpreter {
+        input_index,
+        reason,
+    };
+    let mut keys = vec![];
+    if interpreter.is_taproot_v1_key_spend() {
+        let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..])
+            .map_err(|e| interpreter_err(e.to_string()))?;
+        keys.push(SignerKey::XOnly(output_key));
+    } else if interpreter.is_taproot_v1_script_spend() {
+        let leaf = tapscript(&txin.witness)
+            .ok_or_else(|| interpreter_err("missing tapscript".to_string()))?;
+        let ms = Miniscript::<XOnlyPublicKey, Tap>::parse_insane(leaf)
+            .map_err(|e| interpreter_err(e.to_string()))?;
+        ms.for_each_key(|pk| {
+            keys.push(SignerKey::XOnly(*pk));
+            true
+        });
+    } else {
+        let descriptor = interpreter
+            .inferred_descriptor()
+            .map_err(|e| interpreter_err(e.to_string()))?;
+        descriptor.for_each_key(|pk| {
+            keys.push(SignerKey::Full(*pk));
+            true
+        });
+    }
+    Ok(keys)
+}
+
+/// The tapscript of a taproot script-path spend: the element before the
+/// control block, once a possible annex is dropped.
+pub(crate) fn tapscript(witness: &Witness) -> Option<&Script> {
+    script_path(witness).map(|(leaf, _)| leaf)
+}
+
+/// The serialized control block of a taproot script-path spend.
+pub(crate) fn control_block(witness: &Witness) -> Option<&[u8]> {
+    script_path(witness).map(|(_, control_block)| control_block)
+}
+
+/// The last two witness elements once a possible annex is dropped.
+fn script_path(witness: &Witness) -> Option<(&Script, &[u8])> {
+    match without_annex(witness).as_slice() {
+        [.., leaf, control_block] => Some((Script::from_bytes(leaf), control_block)),
+        _ => None,
+    }
+}
+
+/// The witness elements, without the last one when it is a taproot annex:
+/// there are at least two and it starts with 0x50.
+pub(crate) fn without_annex(witness: &Witness) -> Vec<&[u8]> {
+    let mut elements: Vec<&[u8]> = witness.iter().collect();
+    let last_byte = elements.last().and_then(|element| element.first());
+    if elements.len() >= 2 && last_byte == Some(&TAPROOT_ANNEX_PREFIX) {
+        elements.pop();
+    }
+    elements
+}
+
+/// Whether every signature of a taproot spend commits to its own spent
+/// output only (`SIGHASH_ANYONECANPAY`), so the other inputs' spent outputs
+/// are not needed to check them. Signatures are told by their length, and
+/// a spend without any is not.
+pub(crate) fn taproot_anyonecanpay_only(witness: &Witness) -> bool {
+    let elements = without_annex(witness);
+    // A key path spend is its signature alone, a script path spend ends
+    // with the tapscript and the control block
+    let stack = match elements.as_slice() {
+        [sig] => std::slice::from_ref(sig),
+        [stack @ .., _, _] => stack,
+        [] => return false,
+    };
+    let mut sigs = stack
+        .iter()
+        .filter(|element| element.len() == 64 || element.len() == 65)
+        .peekable();
+    sigs.peek().is_some()
+        && sigs.all(|sig| {
+            sig.len() == 65
+                && matches!(
+                    TapSighashType::from_consensus_u8(sig[64]),
+                    Ok(TapSighashType::AllPlusAnyoneCanPay
+                        | TapSighashType::NonePlusAnyoneCanPay
+                        | TapSighashType::SinglePlusAnyoneCanPay)
+                )
+        })
+}
+
+/// Position of `signature` among the scriptSig pushes or, for segwit spends,
+/// the witness elements.
+pub(crate) fn signature_index(txin: &TxIn, signature: &SignerSignature) -> Option<usize> {
+    let serialized = match signature {
+        SignerSignature::Ecdsa(sig) => sig.to_vec(),
+        SignerSignature::Schnorr(sig) => sig.to_vec(),
+    };
+    if !txin.witness.is_empty() {
+        return txin
+            .witness
+            .iter()
+            .position(|elem| elem == serialized.as_slice());
+    }
+    txin.script_sig.instructions().position(
+        |ins| matches!(ins, Ok(Instruction::PushBytes(b)) if b.as_bytes() == serialized.as_slice()),
+    )
+}
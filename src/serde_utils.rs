@@ -0,0 +1,134 @@
+//! serde helpers for the bitcoin types carried by reports.
+//!
+//! They pin the JSON representation to plain strings (hex, or the type's
+//! display form) instead of relying on rust-bitcoin's own serde formats, so
+//! the documented schema does not move with rust-bitcoin upgrades.
+
+/// Serializes through `Display` and deserializes through `FromStr`.
+pub(crate) mod display_fromstr {
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: Display, S: Serializer>(value: &T, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(d: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(d)?.parse().map_err(de::Error::custom)
+    }
+}
+
+/// [`display_fromstr`] for optional values, `None` being `null`.
+pub(crate) mod option_display_fromstr {
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &Option<T>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => s.collect_str(value),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(d: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(d)?
+            .map(|s| s.parse().map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+/// [`display_fromstr`] for each element of a sequence.
+pub(crate) mod vec_display_fromstr {
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    use serde::ser::SerializeSeq;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: Display, S: Serializer>(values: &[T], s: S) -> Result<S::Ok, S::Error> {
+        let mut seq = s.serialize_seq(Some(values.len()))?;
+        for value in values {
+            seq.serialize_element(&value.to_string())?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, T, D>(d: D) -> Result<Vec<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(d)?
+            .iter()
+            .map(|s| s.parse().map_err(de::Error::custom))
+            .collect()
+    }
+}
+
+/// A key parity as `"even"` or `"odd"`.
+pub(crate) mod parity {
+    use bitcoin::secp256k1::Parity;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(parity: &Parity, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(match parity {
+            Parity::Even => "even",
+            Parity::Odd => "odd",
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Parity, D::Error> {
+        match String::deserialize(d)?.as_str() {
+            "even" => Ok(Parity::Even),
+            "odd" => Ok(Parity::Odd),
+            other => Err(de::Error::custom(format!("invalid parity {:?}", other))),
+        }
+    }
+}
+
+/// A taproot leaf version as its consensus byte.
+pub(crate) mod leaf_version {
+    use bitcoin::taproot::LeafVersion;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(version: &LeafVersion, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u8(version.to_consensus())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<LeafVersion, D::Error> {
+        LeafVersion::from_consensus(u8::deserialize(d)?).map_err(de::Error::custom)
+    }
+}
+
+/// A script as the hex of its bytes.
+pub(crate) mod script_hex {
+    use bitcoin::ScriptBuf;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(script: &ScriptBuf, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&hex::encode(script.as_bytes()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<ScriptBuf, D::Error> {
+        let bytes = hex::decode(String::deserialize(d)?).map_err(de::Error::custom)?;
+        Ok(ScriptBuf::from(bytes))
+    }
+}
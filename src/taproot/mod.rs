@@ -0,0 +1,193 @@
+//! Taproot output key commitments (BIP341).
+//!
+//! A taproot output key is the internal key tweaked with the hash of that key
+//! and of the script tree's merkle root, if any. A key path spend reveals the
+//! output key only; which internal key and tree it commits to can be found
+//! by recomputing the tweak for candidate keys and roots. A script path
+//! spend reveals the internal key and the path from the spent leaf to the
+//! root in its control block, from which [`TapTrees`] rebuilds as much of
+//! the tree as the spends of an output reveal. Internal keys that are MuSig2
+//! aggregates are found from candidate participants by
+//! [`find_musig_commitment`].
+
+mod musig;
+mod tree;
+
+use bitcoin::key::{Secp256k1, TapTweak, XOnlyPublicKey};
+use bitcoin::secp256k1::Parity;
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TapNodeHash};
+use bitcoin::Script;
+
+#[cfg(feature = "serde")]
+use crate::serde_utils::{
+    display_fromstr, leaf_version, option_display_fromstr, parity, vec_display_fromstr,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub use musig::{
+    find_musig_commitment, musig_key_agg, musig_key_sort, KeyOrdering, MusigAggregation,
+    MAX_PERMUTED_PARTICIPANTS,
+};
+pub use tree::{PartialTapTree, TapTreeNode, TapTrees};
+
+/// Internal key and script tree a taproot output key commits to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TaprootCommitment {
+    #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+    pub internal_key: XOnlyPublicKey,
+    /// Merkle root of the script tree, `None` for an output without scripts
+    /// (BIP86).
+    #[cfg_attr(feature = "serde", serde(with = "option_display_fromstr"))]
+    pub merkle_root: Option<TapNodeHash>,
+}
+
+impl TaprootCommitment {
+    pub fn new(internal_key: XOnlyPublicKey, merkle_root: Option<TapNodeHash>) -> Self {
+        TaprootCommitment {
+            internal_key,
+            merkle_root,
+        }
+    }
+
+    /// The output key: the internal key tweaked with the merkle root.
+    pub fn output_key(&self) -> XOnlyPublicKey {
+        let secp = Secp256k1::verification_only();
+        let (output_key, _parity) = self.internal_key.tap_tweak(&secp, self.merkle_root);
+        output_key.to_inner()
+    }
+}
+
+/// Finds which of `internal_keys` `output_key` commits to, with no script
+/// tree or with one of `merkle_roots`. `None` if no combination matches.
+pub fn find_taproot_commitment(
+    output_key: &XOnlyPublicKey,
+    internal_keys: &[XOnlyPublicKey],
+    merkle_roots: &[TapNodeHash],
+) -> Option<TaprootCommitment> {
+    let roots: Vec<Option<TapNodeHash>> = std::iter::once(None)
+        .chain(merkle_roots.iter().copied().map(Some))
+        .collect();
+    internal_keys.iter().find_map(|internal_key| {
+        roots
+            .iter()
+            .map(|root| TaprootCommitment::new(*internal_key, *root))
+            .find(|commitment| commitment.output_key() == *output_key)
+    })
+}
+
+/// x coordinate of the point H of BIP341, whose discrete logarithm nobody
+/// knows: outputs with it as internal key can only be spent by script.
+/// `50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0`.
+pub const NUMS_INTERNAL_KEY: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// Whether `key` is the [`NUMS_INTERNAL_KEY`]. Wallets may also derive
+/// unspendable internal keys from H and a secret tweak, which only they can
+/// recognize.
+pub fn is_nums_key(key: &XOnlyPublicKey) -> bool {
+    key.serialize() == NUMS_INTERNAL_KEY
+}
+
+/// What the control block of a taproot script path spend reveals, and
+/// whether it proves the spent leaf is committed to by the output key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ControlBlockReport {
+    #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+    pub internal_key: XOnlyPublicKey,
+    /// Whether the internal key is the [`NUMS_INTERNAL_KEY`], so that the
+    /// output has no key path.
+    pub nums_internal_key: bool,
+    /// Parity of the output key, as claimed by the control block.
+    #[cfg_attr(feature = "serde", serde(with = "parity"))]
+    pub output_key_parity: Parity,
+    #[cfg_attr(feature = "serde", serde(with = "leaf_version"))]
+    pub leaf_version: LeafVersion,
+    #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+    pub leaf_hash: TapLeafHash,
+    /// Sibling hashes from the leaf up to the root; the leaf is at depth
+    /// `merkle_path.len()`.
+    #[cfg_attr(feature = "serde", serde(with = "vec_display_fromstr"))]
+    pub merkle_path: Vec<TapNodeHash>,
+    /// Root recomputed from the leaf hash and `merkle_path`.
+    #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+    pub merkle_root: TapNodeHash,
+    /// Output key of the spent scriptPubKey.
+    #[cfg_attr(feature = "serde", serde(with = "display_fromstr"))]
+    pub output_key: XOnlyPublicKey,
+    /// Whether the internal key tweaked with `merkle_root` is `output_key`,
+    /// with the claimed parity.
+    pub commitment_verified: bool,
+}
+
+impl ControlBlockReport {
+    /// Reports `control_block`, revealed with `leaf_script` to spend an
+    /// output of key `output_key`.
+    pub fn new(
+        control_block: &ControlBlock,
+        leaf_script: &Script,
+        output_key: XOnlyPublicKey,
+    ) -> Self {
+        let leaf_hash = TapLeafHash::from_script(leaf_script, control_block.leaf_version);
+        let merkle_path = control_block.merkle_branch.as_inner().to_vec();
+        let merkle_root = merkle_root(leaf_hash, &merkle_path);
+        let secp = Secp256k1::verification_only();
+        let (tweaked, parity) = control_block
+            .internal_key
+            .tap_tweak(&secp, Some(merkle_root));
+        ControlBlockReport {
+            internal_key: control_block.internal_key,
+            nums_internal_key: is_nums_key(&control_block.internal_key),
+            output_key_parity: control_block.output_key_parity,
+            leaf_version: control_block.leaf_version,
+            leaf_hash,
+            merkle_path,
+            merkle_root,
+            output_key,
+            commitment_verified: tweaked.to_inner() == output_key
+                && parity == control_block.output_key_parity,
+        }
+    }
+
+    /// Depth of the spent leaf in the script tree, 0 for a lone leaf.
+    pub fn depth(&self) -> usize {
+        self.merkle_path.len()
+    }
+
+    /// The internal key and script tree the control block claims.
+    pub fn commitment(&self) -> TaprootCommitment {
+        TaprootCommitment::new(self.internal_key, Some(self.merkle_root))
+    }
+}
+
+/// The report of the control block of a script path spend through `witness`
+/// of an output with scriptPubKey `script_pubkey`.
+pub(crate) fn control_block_report(
+    witness: &bitcoin::Witness,
+    script_pubkey: &Script,
+) -> Option<ControlBlockReport> {
+    let leaf_script = crate::script::tapscript(witness)?;
+    let control_block = ControlBlock::decode(crate::script::control_block(witness)?).ok()?;
+    if !script_pubkey.is_v1_p2tr() {
+        return None;
+    }
+    let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).ok()?;
+    Some(ControlBlockReport::new(
+        &control_block,
+        leaf_script,
+        output_key,
+    ))
+}
+
+/// Root of the tree holding leaf `leaf_hash` at the end of `merkle_path`.
+pub(crate) fn merkle_root(leaf_hash: TapLeafHash, merkle_path: &[TapNodeHash]) -> TapNodeHash {
+    merkle_path
+        .iter()
+        .fold(TapNodeHash::from(leaf_hash), |node, sibling| {
+            TapNodeHash::from_node_hashes(node, *sibling)
+        })
+}
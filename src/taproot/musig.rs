@@ -0,0 +1,165 @@
+//! MuSig2 key aggregation (BIP327 KeyAgg).
+//!
+//! A key path spend by MuSig2 cosigners shows a single signature under the
+//! output key, the internal key being the aggregate of their individual keys.
+//! The aggregate depends on the order of the keys, which BIP327 leaves to the
+//! wallet; most sort them first (KeySort). Given candidate participants, the
+//! aggregate of each ordering is tweaked like any internal key and compared
+//! to the output key.
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::{Secp256k1, XOnlyPublicKey};
+use bitcoin::secp256k1::constants::CURVE_ORDER;
+use bitcoin::secp256k1::{PublicKey, Scalar};
+use bitcoin::taproot::TapNodeHash;
+
+use super::{find_taproot_commitment, TaprootCommitment};
+#[cfg(feature = "serde")]
+use crate::serde_utils::vec_display_fromstr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Beyond this many participants, [`KeyOrdering::All`] only tries the sorted
+/// order: the orderings of 9 keys already take 362880 aggregations.
+pub const MAX_PERMUTED_PARTICIPANTS: usize = 8;
+
+/// Orderings of the participant keys to aggregate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyOrdering {
+    /// The keys sorted by BIP327 KeySort only.
+    Sorted,
+    /// Every ordering, the sorted one first.
+    All,
+}
+
+/// The participants of a MuSig2 aggregate key, in aggregation order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MusigAggregation {
+    #[cfg_attr(feature = "serde", serde(with = "vec_display_fromstr"))]
+    pub participants: Vec<PublicKey>,
+    /// Whether `participants` are in the order of BIP327 KeySort.
+    pub key_sorted: bool,
+}
+
+impl MusigAggregation {
+    /// The aggregate key, internal key of the taproot output. `None` for no
+    /// participants or an aggregate at infinity, which only a value not
+    /// returned by [`find_musig_commitment`] can have.
+    pub fn aggregate_key(&self) -> Option<XOnlyPublicKey> {
+        musig_key_agg(&self.participants)
+    }
+}
+
+/// BIP327 KeySort: keys sorted by their compressed serialization.
+pub fn musig_key_sort(pubkeys: &[PublicKey]) -> Vec<PublicKey> {
+    let mut sorted = pubkeys.to_vec();
+    sorted.sort_by_key(|pk| pk.serialize());
+    sorted
+}
+
+/// BIP327 KeyAgg of `pubkeys` in the given order, as the x-only key used as
+/// taproot internal key. `None` for no keys or an aggregate at infinity.
+pub fn musig_key_agg(pubkeys: &[PublicKey]) -> Option<XOnlyPublicKey> {
+    let secp = Secp256k1::verification_only();
+    let first = pubkeys.first()?;
+    let mut list = tagged_engine("KeyAgg list");
+    for pk in pubkeys {
+        list.input(&pk.serialize());
+    }
+    let list_hash = sha256::Hash::from_engine(list);
+    let second = pubkeys.iter().find(|pk| *pk != first);
+
+    let mut points = Vec::with_capacity(pubkeys.len());
+    for pk in pubkeys {
+        if Some(pk) == second {
+            points.push(*pk);
+            continue;
+        }
+        let mut coefficient = tagged_engine("KeyAgg coefficient");
+        coefficient.input(list_hash.as_ref());
+        coefficient.input(&pk.serialize());
+        let coefficient = scalar_mod_order(sha256::Hash::from_engine(coefficient).to_byte_array());
+        points.push(pk.mul_tweak(&secp, &coefficient).ok()?);
+    }
+    let points: Vec<&PublicKey> = points.iter().collect();
+    let aggregate = PublicKey::combine_keys(&points).ok()?;
+    Some(aggregate.x_only_public_key().0)
+}
+
+/// Finds the ordering of `participants` whose MuSig2 aggregate `output_key`
+/// commits to, with no script tree or with one of `merkle_roots`. Every
+/// participant is part of the aggregate.
+pub fn find_musig_commitment(
+    output_key: &XOnlyPublicKey,
+    participants: &[PublicKey],
+    merkle_roots: &[TapNodeHash],
+    ordering: KeyOrdering,
+) -> Option<(MusigAggregation, TaprootCommitment)> {
+    let sorted = musig_key_sort(participants);
+    let matches = |keys: &[PublicKey]| {
+        let aggregate = musig_key_agg(keys)?;
+        let commitment = find_taproot_commitment(output_key, &[aggregate], merkle_roots)?;
+        let aggregation = MusigAggregation {
+            participants: keys.to_vec(),
+            key_sorted: keys == sorted.as_slice(),
+        };
+        Some((aggregation, commitment))
+    };
+    if let Some(found) = matches(&sorted) {
+        return Some(found);
+    }
+    if ordering == KeyOrdering::Sorted || participants.len() > MAX_PERMUTED_PARTICIPANTS {
+        return None;
+    }
+    // Heap's algorithm, each ordering once
+    let mut keys = participants.to_vec();
+    let mut counters = vec![0; keys.len()];
+    if keys != sorted {
+        if let Some(found) = matches(&keys) {
+            return Some(found);
+        }
+    }
+    let mut i = 1;
+    while i < keys.len() {
+        if counters[i] < i {
+            keys.swap(if i % 2 == 0 { 0 } else { counters[i] }, i);
+            if keys != sorted {
+                if let Some(found) = matches(&keys) {
+                    return Some(found);
+                }
+            }
+            counters[i] += 1;
+            i = 1;
+        } else {
+            counters[i] = 0;
+            i += 1;
+        }
+    }
+    None
+}
+
+/// A SHA256 engine primed with the BIP340 tag prefix of `tag`.
+fn tagged_engine(tag: &str) -> sha256::HashEngine {
+    let tag_hash = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    engine
+}
+
+/// A 256-bit big-endian integer modulo the curve order. Below twice the
+/// order, so one subtraction is enough.
+fn scalar_mod_order(mut bytes: [u8; 32]) -> Scalar {
+    if let Ok(scalar) = Scalar::from_be_bytes(bytes) {
+        return scalar;
+    }
+    let mut borrow = 0;
+    for i in (0..32).rev() {
+        let (diff, b1) = bytes[i].overflowing_sub(CURVE_ORDER[i]);
+        let (diff, b2) = diff.overflowing_sub(borrow);
+        bytes[i] = diff;
+        borrow = u8::from(b1 || b2);
+    }
+    Scalar::from_be_bytes(bytes).expect("reduced below the order")
+}
@@ -0,0 +1,357 @@
+//! Script trees of taproot outputs, rebuilt from their script path spends.
+//!
+//! Each spend reveals one leaf and the hashes of the siblings on its path to
+//! the root. Spends of outputs sharing an internal key and tree reveal more
+//! leaves of the same tree; what no spend revealed stays known by hash only.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TapNodeHash};
+use bitcoin::{Script, ScriptBuf, Transaction, TxIn};
+use miniscript::{Miniscript, Tap};
+
+use super::{control_block_report, TaprootCommitment};
+use crate::PrevoutMap;
+
+/// A node of a partially known script tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TapTreeNode {
+    /// A subtree known by its hash only: a sibling on the path of a revealed
+    /// leaf.
+    Hidden(TapNodeHash),
+    /// A revealed leaf.
+    Leaf {
+        script: ScriptBuf,
+        version: LeafVersion,
+    },
+    /// A branch, children in the hash order BIP341 combines them in.
+    Branch {
+        hash: TapNodeHash,
+        children: Box<[TapTreeNode; 2]>,
+    },
+}
+
+impl TapTreeNode {
+    pub fn hash(&self) -> TapNodeHash {
+        match self {
+            TapTreeNode::Hidden(hash) => *hash,
+            TapTreeNode::Leaf { script, version } => {
+                TapNodeHash::from(TapLeafHash::from_script(script, *version))
+            }
+            TapTreeNode::Branch { hash, .. } => *hash,
+        }
+    }
+
+    fn branch(a: TapTreeNode, b: TapTreeNode) -> Self {
+        let (a_hash, b_hash) = (a.hash(), b.hash());
+        let children = if a_hash <= b_hash { [a, b] } else { [b, a] };
+        TapTreeNode::Branch {
+            hash: TapNodeHash::from_node_hashes(a_hash, b_hash),
+            children: Box::new(children),
+        }
+    }
+}
+
+/// The part of the script tree of a taproot output revealed by its script
+/// path spends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialTapTree {
+    internal_key: XOnlyPublicKey,
+    root: TapTreeNode,
+}
+
+impl PartialTapTree {
+    fn new(internal_key: XOnlyPublicKey, merkle_root: TapNodeHash) -> Self {
+        PartialTapTree {
+            internal_key,
+            root: TapTreeNode::Hidden(merkle_root),
+        }
+    }
+
+    pub fn internal_key(&self) -> XOnlyPublicKey {
+        self.internal_key
+    }
+
+    pub fn merkle_root(&self) -> TapNodeHash {
+        self.root.hash()
+    }
+
+    pub fn commitment(&self) -> TaprootCommitment {
+        TaprootCommitment::new(self.internal_key, Some(self.merkle_root()))
+    }
+
+    /// The output key of the spent outputs.
+    pub fn output_key(&self) -> XOnlyPublicKey {
+        self.commitment().output_key()
+    }
+
+    pub fn root(&self) -> &TapTreeNode {
+        &self.root
+    }
+
+    /// Revealed leaves with their depth, left to right.
+    pub fn leaves(&self) -> Vec<(usize, &Script, LeafVersion)> {
+        let mut leaves = vec![];
+        walk(&self.root, 0, &mut |node, depth| {
+            if let TapTreeNode::Leaf { script, version } = node {
+                leaves.push((depth, script.as_script(), *version));
+            }
+        });
+        leaves
+    }
+
+    /// Hashes of the subtrees no spend revealed, with their depth.
+    pub fn hidden(&self) -> Vec<(usize, TapNodeHash)> {
+        let mut hidden = vec![];
+        walk(&self.root, 0, &mut |node, depth| {
+            if let TapTreeNode::Hidden(hash) = node {
+                hidden.push((depth, *hash));
+            }
+        });
+        hidden
+    }
+
+    /// True once every leaf was revealed.
+    pub fn is_complete(&self) -> bool {
+        self.hidden().is_empty()
+    }
+
+    /// A `tr()` descriptor of the tree as known. Leaves are miniscript when
+    /// they parse as such and `rawleaf(<hex>)` otherwise (with the leaf
+    /// version if not tapscript), hidden subtrees are `rawnode(<hash>)`.
+    /// Descriptor parsers without these extensions only accept complete
+    /// trees of miniscript leaves. No checksum is appended.
+    pub fn descriptor(&self) -> String {
+        format!("tr({},{})", self.internal_key, node_descriptor(&self.root))
+    }
+
+    /// A Graphviz DOT diagram of the tree, revealed leaves filled, hidden
+    /// subtrees dashed.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph taptree {\n  node [fontname=\"monospace\"];\n");
+        let _ = writeln!(
+            dot,
+            "  key [shape=box, label=\"output key {}\\ninternal key {}\"];",
+            self.output_key(),
+            self.internal_key
+        );
+        let mut next_id = 0;
+        dot_node(&self.root, "key", &mut next_id, &mut dot);
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Adds a leaf and its path, which must lead to the root of the tree.
+    /// Returns whether the leaf was new.
+    fn insert(&mut self, script: &Script, version: LeafVersion, path: &[TapNodeHash]) -> bool {
+        // Node hashes from the leaf up to the root
+        let mut hashes = vec![TapNodeHash::from(TapLeafHash::from_script(script, version))];
+        for sibling in path {
+            let node = TapNodeHash::from_node_hashes(hashes[hashes.len() - 1], *sibling);
+            hashes.push(node);
+        }
+        debug_assert_eq!(hashes.last(), Some(&self.merkle_root()));
+
+        let mut node = &mut self.root;
+        for depth in (0..path.len()).rev() {
+            if let TapTreeNode::Hidden(_) = node {
+                *node = TapTreeNode::branch(
+                    TapTreeNode::Hidden(hashes[depth]),
+                    TapTreeNode::Hidden(path[depth]),
+                );
+            }
+            node = match node {
+                TapTreeNode::Branch { children, .. } => {
+                    let Some(child) = children.iter_mut().find(|c| c.hash() == hashes[depth])
+                    else {
+                        return false;
+                    };
+                    child
+                }
+                // A leaf where the path goes on, which only a hash
+                // collision would produce
+                _ => return false,
+            };
+        }
+        match node {
+            TapTreeNode::Hidden(_) => {
+                *node = TapTreeNode::Leaf {
+                    script: script.to_owned(),
+                    version,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The script trees of taproot outputs, rebuilt from the leaves and merkle
+/// paths revealed by their script path spends. Outputs with the same internal
+/// key and tree, i.e. the same output key, share one tree.
+#[derive(Debug, Clone, Default)]
+pub struct TapTrees {
+    trees: Vec<PartialTapTree>,
+    index: HashMap<TaprootCommitment, usize>,
+}
+
+impl TapTrees {
+    pub fn new() -> Self {
+        TapTrees::default()
+    }
+
+    /// Adds the leaf revealed by `txin` if it is a script path spend of a
+    /// taproot output of scriptPubKey `script_pubkey`, and returns what the
+    /// output commits to. Control blocks not committed to by the output key
+    /// are ignored.
+    pub fn add_spend(&mut self, txin: &TxIn, script_pubkey: &Script) -> Option<TaprootCommitment> {
+        let report = control_block_report(&txin.witness, script_pubkey)?;
+        if !report.commitment_verified {
+            return None;
+        }
+        let script = crate::script::tapscript(&txin.witness)?;
+        self.insert(
+            report.internal_key,
+            report.merkle_root,
+            script,
+            report.leaf_version,
+            &report.merkle_path,
+        );
+        Some(report.commitment())
+    }
+
+    /// Adds the script path spends of `tx` whose spent output is in
+    /// `prevout_map`, returning how many there were.
+    pub fn add_tx(&mut self, tx: &Transaction, prevout_map: &PrevoutMap) -> usize {
+        tx.input
+            .iter()
+            .filter(|txin| {
+                prevout_map
+                    .get(&txin.previous_output)
+                    .and_then(|txout| self.add_spend(txin, &txout.script_pubkey))
+                    .is_some()
+            })
+            .count()
+    }
+
+    /// Adds the leaf `script` proven by `control_block`, without checking it
+    /// against any output key.
+    pub fn add_control_block(
+        &mut self,
+        control_block: &ControlBlock,
+        script: &Script,
+    ) -> TaprootCommitment {
+        let leaf_hash = TapLeafHash::from_script(script, control_block.leaf_version);
+        let path = control_block.merkle_branch.as_inner();
+        let merkle_root = super::merkle_root(leaf_hash, path);
+        self.insert(
+            control_block.internal_key,
+            merkle_root,
+            script,
+            control_block.leaf_version,
+            path,
+        );
+        TaprootCommitment::new(control_block.internal_key, Some(merkle_root))
+    }
+
+    /// The tree of outputs committing to `commitment`.
+    pub fn get(&self, commitment: &TaprootCommitment) -> Option<&PartialTapTree> {
+        self.index.get(commitment).map(|&i| &self.trees[i])
+    }
+
+    /// Trees in order of first spend.
+    pub fn iter(&self) -> impl Iterator<Item = &PartialTapTree> {
+        self.trees.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.trees.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trees.is_empty()
+    }
+
+    fn insert(
+        &mut self,
+        internal_key: XOnlyPublicKey,
+        merkle_root: TapNodeHash,
+        script: &Script,
+        version: LeafVersion,
+        path: &[TapNodeHash],
+    ) {
+        let commitment = TaprootCommitment::new(internal_key, Some(merkle_root));
+        let trees = &mut self.trees;
+        let i = *self.index.entry(commitment).or_insert_with(|| {
+            trees.push(PartialTapTree::new(internal_key, merkle_root));
+            trees.len() - 1
+        });
+        self.trees[i].insert(script, version, path);
+    }
+}
+
+/// Calls `f` on every node, depth first, left to right.
+fn walk<'a>(node: &'a TapTreeNode, depth: usize, f: &mut impl FnMut(&'a TapTreeNode, usize)) {
+    f(node, depth);
+    if let TapTreeNode::Branch { children, .. } = node {
+        for child in children.iter() {
+            walk(child, depth + 1, f);
+        }
+    }
+}
+
+fn node_descriptor(node: &TapTreeNode) -> String {
+    match node {
+        TapTreeNode::Hidden(hash) => format!("rawnode({})", hash),
+        TapTreeNode::Leaf { script, version } => {
+            if *version == LeafVersion::TapScript {
+                if let Ok(ms) = Miniscript::<XOnlyPublicKey, Tap>::parse_insane(script) {
+                    return ms.to_string();
+                }
+                format!("rawleaf({})", hex::encode(script.as_bytes()))
+            } else {
+                format!("rawleaf({},{:#x})", hex::encode(script.as_bytes()), version)
+            }
+        }
+        TapTreeNode::Branch { children, .. } => format!(
+            "{{{},{}}}",
+            node_descriptor(&children[0]),
+            node_descriptor(&children[1])
+        ),
+    }
+}
+
+/// Writes `node` and its subtree as DOT, under the node named `parent`.
+fn dot_node(node: &TapTreeNode, parent: &str, next_id: &mut usize, dot: &mut String) {
+    let id = format!("n{}", next_id);
+    *next_id += 1;
+    let _ = match node {
+        TapTreeNode::Hidden(hash) => writeln!(
+            dot,
+            "  {} [shape=box, style=dashed, label=\"hidden\\n{}\"];",
+            id, hash
+        ),
+        TapTreeNode::Leaf { script, version } => writeln!(
+            dot,
+            "  {} [shape=box, style=filled, fillcolor=palegreen, label=\"leaf {:#x}\\n{}\"];",
+            id,
+            version,
+            dot_escape(&script.to_asm_string())
+        ),
+        TapTreeNode::Branch { hash, .. } => {
+            writeln!(dot, "  {} [shape=ellipse, label=\"{}\"];", id, hash)
+        }
+    };
+    let _ = writeln!(dot, "  {} -> {};", parent, id);
+    if let TapTreeNode::Branch { children, .. } = node {
+        for child in children.iter() {
+            dot_node(child, &id, next_id, dot);
+        }
+    }
+}
+
+fn dot_escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
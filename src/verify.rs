@@ -0,0 +1,411 @@
+//! Signature verification of transaction inputs.
+
+use bitcoin::secp256k1::{self, Message, Secp256k1};
+use bitcoin::sighash::SighashCache;
+use bitcoin::taproot::{ControlBlock, TapLeafHash};
+use bitcoin::{Script, ScriptBuf, Transaction};
+use miniscript::interpreter::KeySigPair;
+use miniscript::Interpreter;
+
+use crate::provider::{self, PrevoutProvider};
+use crate::report::{TxSigner, UnanalyzedInput};
+use crate::{
+    policy, script, taproot, InputReport, MinikeysError, PrevoutMap, SighashPrevouts, SignerKey,
+    SignerReport, SignerSignature, TxReport, TxSummary,
+};
+
+/// Reports the key/signature pairs of input `input_index` of `tx` and which
+/// of them verify. Legacy, segwit v0 and taproot inputs are all handled.
+///
+/// # Arguments
+///
+/// * `tx` - spending transaction
+/// * `input_index` - spending transaction input index
+/// * `prevouts` - funding transactions, in any order; the one spent by
+///   `input_index` is always required, taproot spends need the funding
+///   transactions of every input
+pub fn verify_input_signers(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[Transaction],
+) -> Result<InputReport, MinikeysError> {
+    verify_input_signers_with(tx, input_index, &PrevoutMap::from_txs(prevouts))
+}
+
+/// Same as [`verify_input_signers`], with the spent outputs already resolved.
+pub fn verify_input_signers_with(
+    tx: &Transaction,
+    input_index: usize,
+    prevout_map: &PrevoutMap,
+) -> Result<InputReport, MinikeysError> {
+    TxVerifier::new(tx).verify_input(input_index, prevout_map)
+}
+
+/// Same as [`verify_input_signers`], fetching from `provider` only the spent
+/// outputs the input needs. Provider failures are returned as
+/// [`MinikeysError::Provider`].
+pub fn verify_input_signers_from<P: PrevoutProvider + ?Sized>(
+    tx: &Transaction,
+    input_index: usize,
+    provider: &P,
+) -> Result<InputReport, MinikeysError> {
+    let prevout_map = provider::resolve_prevouts(provider, tx, &[input_index])?;
+    verify_input_signers_with(tx, input_index, &prevout_map)
+}
+
+/// Async version of [`verify_input_signers_from`].
+#[cfg(feature = "async")]
+pub async fn verify_input_signers_from_async<P: provider::AsyncPrevoutProvider + ?Sized>(
+    tx: &Transaction,
+    input_index: usize,
+    provider: &P,
+) -> Result<InputReport, MinikeysError> {
+    let prevout_map = provider::resolve_prevouts_async(provider, tx, &[input_index]).await?;
+    verify_input_signers_with(tx, input_index, &prevout_map)
+}
+
+/// Reports the signers of every input of `tx`.
+///
+/// Inputs that cannot be analyzed (missing funding transaction, non-miniscript
+/// script, ...) are listed in the summary instead of failing the whole
+/// transaction; a coinbase transaction yields an empty report.
+///
+/// # Arguments
+///
+/// * `tx` - spending transaction
+/// * `prevouts` - funding transactions of its inputs, in any order
+pub fn verify_tx_signers(tx: &Transaction, prevouts: &[Transaction]) -> TxReport {
+    verify_tx_signers_with(tx, &PrevoutMap::from_txs(prevouts))
+}
+
+/// Same as [`verify_tx_signers`], fetching the spent outputs from `provider`.
+/// Only a failure of the provider itself is an error; outputs it does not
+/// know leave their inputs unanalyzed.
+pub fn verify_tx_signers_from<P: PrevoutProvider + ?Sized>(
+    tx: &Transaction,
+    provider: &P,
+) -> Result<TxReport, MinikeysError> {
+    let inputs: Vec<usize> = (0..tx.input.len()).collect();
+    let prevout_map = provider::resolve_prevouts(provider, tx, &inputs)?;
+    Ok(verify_tx_signers_with(tx, &prevout_map))
+}
+
+/// Async version of [`verify_tx_signers_from`].
+#[cfg(feature = "async")]
+pub async fn verify_tx_signers_from_async<P: provider::AsyncPrevoutProvider + ?Sized>(
+    tx: &Transaction,
+    provider: &P,
+) -> Result<TxReport, MinikeysError> {
+    let inputs: Vec<usize> = (0..tx.input.len()).collect();
+    let prevout_map = provider::resolve_prevouts_async(provider, tx, &inputs).await?;
+    Ok(verify_tx_signers_with(tx, &prevout_map))
+}
+
+/// Same as [`verify_tx_signers`], with the spent outputs already resolved.
+pub fn verify_tx_signers_with(tx: &Transaction, prevout_map: &PrevoutMap) -> TxReport {
+    let mut report = TxReport {
+        txid: tx.txid(),
+        coinbase: tx.is_coin_base(),
+        inputs: vec![],
+        summary: TxSummary::default(),
+    };
+    if report.coinbase {
+        return report;
+    }
+
+    let mut verifier = TxVerifier::new(tx);
+    for input_index in 0..tx.input.len() {
+        match verifier.verify_input(input_index, prevout_map) {
+            Ok(input) => report.inputs.push(input),
+            Err(error) => report
+                .summary
+                .unanalyzed
+                .push(UnanalyzedInput { input_index, error }),
+        }
+    }
+
+    for input in &report.inputs {
+        for key in input.verified_keys() {
+            match report.summary.signers.iter_mut().find(|s| s.key == *key) {
+                Some(signer) if !signer.inputs.contains(&input.input_index) => {
+                    signer.inputs.push(input.input_index)
+                }
+                Some(_) => {}
+                None => report.summary.signers.push(TxSigner {
+                    key: *key,
+                    inputs: vec![input.input_index],
+                }),
+            }
+        }
+    }
+    report.summary.multi_input_signers = report
+        .summary
+        .signers
+        .iter()
+        .filter(|s| s.inputs.len() > 1)
+        .map(|s| s.key)
+        .collect();
+    report
+}
+
+/// Verifies the inputs of one transaction, sharing the sighash cache (and so
+/// the taproot and segwit v0 midstate hashes) between them.
+struct TxVerifier<'tx> {
+    tx: &'tx Transaction,
+    cache: SighashCache<&'tx Transaction>,
+    secp: Secp256k1<secp256k1::VerifyOnly>,
+}
+
+/// What the signatures of an input commit to, besides the transaction.
+enum SigContext {
+    Legacy { script_code: ScriptBuf },
+    SegwitV0 { script_code: ScriptBuf, value: u64 },
+    TaprootKey,
+    TaprootScript { leaf_hash: TapLeafHash },
+}
+
+impl<'tx> TxVerifier<'tx> {
+    fn new(tx: &'tx Transaction) -> Self {
+        TxVerifier {
+            tx,
+            cache: SighashCache::new(tx),
+            secp: Secp256k1::verification_only(),
+        }
+    }
+
+    fn verify_input(
+        &mut self,
+        input_index: usize,
+        prevout_map: &PrevoutMap,
+    ) -> Result<InputReport, MinikeysError> {
+        let tx = self.tx;
+        let txin = tx
+            .input
+            .get(input_index)
+            .ok_or(MinikeysError::InputIndexOutOfRange {
+                index: input_index,
+                inputs: tx.input.len(),
+            })?;
+        let spent_output = prevout_map.spent_output(tx, input_index)?;
+        let script_pubkey = &spent_output.script_pubkey;
+        if !is_supported(script_pubkey) {
+            return Err(MinikeysError::UnsupportedScript {
+                input_index,
+                script_pubkey: script_pubkey.clone(),
+            });
+        }
+        let interpreter = Interpreter::from_txdata(
+            script_pubkey,
+            &txin.script_sig,
+            &txin.witness,
+            txin.sequence,
+            tx.lock_time,
+        )
+        .map_err(|e| MinikeysError::Interpreter {
+            input_index,
+            reason: e.to_string(),
+        })?;
+        let taproot =
+            interpreter.is_taproot_v1_key_spend() || interpreter.is_taproot_v1_script_spend();
+        let all_inputs = taproot && !script::taproot_anyonecanpay_only(&txin.witness);
+        let spent_outputs = prevout_map.sighash_prevouts(tx, input_index, all_inputs)?;
+        let context = sig_context(&interpreter, txin, spent_output.value, input_index)?;
+
+        let mut attempts = vec![];
+        let mut sig_type_mismatch = false;
+        let iter = interpreter.iter_custom(Box::new(|key_sig: &KeySigPair| {
+            if taproot != matches!(key_sig, KeySigPair::Schnorr(..)) {
+                sig_type_mismatch = true;
+                return false;
+            }
+            let res = self.verify_sig(input_index, &spent_outputs, &context, key_sig);
+            attempts.push(SignerReport::new(key_sig, res));
+            res
+        }));
+        // The iterator stops at the first constraint that fails; a failed
+        // signature check is reported through the signer list, not as an error.
+        for _ in iter {}
+        if sig_type_mismatch {
+            return Err(MinikeysError::SignatureTypeMismatch {
+                input_index,
+                taproot,
+            });
+        }
+
+        // Multisig evaluation tries each signature against keys until one
+        // matches; those failed pairings are not failed signatures.
+        let verified_sigs: Vec<SignerSignature> = attempts
+            .iter()
+            .filter(|a| a.verified)
+            .map(|a| a.signature)
+            .collect();
+        attempts.retain(|a| a.verified || !verified_sigs.contains(&a.signature));
+
+        let script_keys = script::script_keys(&interpreter, script_pubkey, txin, input_index)?;
+        for attempt in attempts.iter_mut() {
+            attempt.key_index = script_keys.iter().position(|k| *k == attempt.key);
+            attempt.sig_index = script::signature_index(txin, &attempt.signature);
+        }
+        let mut control_block = None;
+        if interpreter.is_taproot_v1_script_spend() {
+            let report =
+                taproot::control_block_report(&txin.witness, script_pubkey).ok_or_else(|| {
+                    MinikeysError::Interpreter {
+                        input_index,
+                        reason: "invalid control block".to_string(),
+                    }
+                })?;
+            control_block = Some(Box::new(report));
+        }
+        let non_signers = script_keys
+            .iter()
+            .filter(|k| !attempts.iter().any(|a| a.verified && a.key == **k))
+            .cloned()
+            .collect();
+        let verified_keys: Vec<SignerKey> = attempts
+            .iter()
+            .filter(|a| a.verified)
+            .map(|a| a.key)
+            .collect();
+        let policy =
+            policy::policy_report(&interpreter, tx, input_index, script_pubkey, &verified_keys);
+
+        Ok(InputReport {
+            input_index,
+            spend_type: script::spend_type(&interpreter, script_pubkey, txin),
+            descriptor: interpreter.inferred_descriptor_string(),
+            signers: attempts,
+            script_keys,
+            non_signers,
+            taproot_commitment: None,
+            musig: None,
+            policy,
+            control_block,
+        })
+    }
+
+    /// Checks one key/signature pair against the input's sighash. Unlike
+    /// `Interpreter::verify_sig` this keeps the sighash cache across calls
+    /// and accepts the high-S ECDSA signatures of old transactions.
+    fn verify_sig(
+        &mut self,
+        input_index: usize,
+        spent_outputs: &SighashPrevouts,
+        context: &SigContext,
+        key_sig: &KeySigPair,
+    ) -> bool {
+        let prevouts = spent_outputs.as_prevouts();
+        match (key_sig, context) {
+            (KeySigPair::Ecdsa(pk, sig), SigContext::Legacy { script_code }) => {
+                let sighash = self.cache.legacy_signature_hash(
+                    input_index,
+                    script_code,
+                    sig.hash_ty.to_u32(),
+                );
+                let Ok(sighash) = sighash else { return false };
+                self.verify_ecdsa(&sighash[..], pk, sig)
+            }
+            (KeySigPair::Ecdsa(pk, sig), SigContext::SegwitV0 { script_code, value }) => {
+                let sighash =
+                    self.cache
+                        .segwit_signature_hash(input_index, script_code, *value, sig.hash_ty);
+                let Ok(sighash) = sighash else { return false };
+                self.verify_ecdsa(&sighash[..], pk, sig)
+            }
+            (KeySigPair::Schnorr(pk, sig), SigContext::TaprootKey) => {
+                let sighash = self.cache.taproot_key_spend_signature_hash(
+                    input_index,
+                    &prevouts,
+                    sig.hash_ty,
+                );
+                let Ok(sighash) = sighash else { return false };
+                self.verify_schnorr(&sighash[..], pk, sig)
+            }
+            (KeySigPair::Schnorr(pk, sig), SigContext::TaprootScript { leaf_hash }) => {
+                let sighash = self.cache.taproot_script_spend_signature_hash(
+                    input_index,
+                    &prevouts,
+                    *leaf_hash,
+                    sig.hash_ty,
+                );
+                let Ok(sighash) = sighash else { return false };
+                self.verify_schnorr(&sighash[..], pk, sig)
+            }
+            _ => false,
+        }
+    }
+
+    fn verify_ecdsa(
+        &self,
+        sighash: &[u8],
+        pk: &bitcoin::PublicKey,
+        sig: &bitcoin::ecdsa::Signature,
+    ) -> bool {
+        let Ok(msg) = Message::from_slice(sighash) else {
+            return false;
+        };
+        let mut sig = sig.sig;
+        sig.normalize_s();
+        self.secp.verify_ecdsa(&msg, &sig, &pk.inner).is_ok()
+    }
+
+    fn verify_schnorr(
+        &self,
+        sighash: &[u8],
+        pk: &bitcoin::key::XOnlyPublicKey,
+        sig: &bitcoin::taproot::Signature,
+    ) -> bool {
+        let Ok(msg) = Message::from_slice(sighash) else {
+            return false;
+        };
+        self.secp.verify_schnorr(&sig.sig, &msg, pk).is_ok()
+    }
+}
+
+fn sig_context(
+    interpreter: &Interpreter,
+    txin: &bitcoin::TxIn,
+    value: u64,
+    input_index: usize,
+) -> Result<SigContext, MinikeysError> {
+    let interpreter_err = |reason: String| MinikeysError::Interpreter {
+        input_index,
+        reason,
+    };
+    if interpreter.is_taproot_v1_key_spend() {
+        return Ok(SigContext::TaprootKey);
+    }
+    if interpreter.is_taproot_v1_script_spend() {
+        let leaf = script::tapscript(&txin.witness)
+            .ok_or_else(|| interpreter_err("missing tapscript".to_string()))?;
+        // The leaf hash commits to the leaf version of the control block,
+        // which is not always 0xc0 (TapScript)
+        let control_block = script::control_block(&txin.witness)
+            .and_then(|cb| ControlBlock::decode(cb).ok())
+            .ok_or_else(|| interpreter_err("invalid control block".to_string()))?;
+        return Ok(SigContext::TaprootScript {
+            leaf_hash: TapLeafHash::from_script(leaf, control_block.leaf_version),
+        });
+    }
+    let script_code = interpreter
+        .inferred_descriptor()
+        .and_then(|d| d.script_code())
+        .map_err(|e| interpreter_err(e.to_string()))?;
+    if interpreter.is_segwit_v0() {
+        Ok(SigContext::SegwitV0 { script_code, value })
+    } else {
+        Ok(SigContext::Legacy { script_code })
+    }
+}
+
+/// False for scriptPubKeys no interpreter can spend: OP_RETURN outputs and
+/// witness programs of unknown version or length.
+fn is_supported(script_pubkey: &Script) -> bool {
+    if script_pubkey.is_op_return() {
+        return false;
+    }
+    !script_pubkey.is_witness_program()
+        || script_pubkey.is_v0_p2wpkh()
+        || script_pubkey.is_v0_p2wsh()
+        || script_pubkey.is_v1_p2tr()
+}
@@ -0,0 +1,227 @@
+use bitcoin::{OutPoint, Transaction};
+use minikeys::provider::{BitcoindAuth, BitcoindRest, BitcoindRpc};
+use minikeys::{verify_tx_signers_from, PrevoutProvider};
+use serde_json::{json, Value};
+
+mod common;
+use common::{p2wsh_txs, HttpRequest, MockHttpServer};
+
+//Checks the bitcoind providers against a local server mimicking bitcoind's
+//JSON-RPC and REST interfaces.
+
+//run with: cargo test --test bitcoind_provider --features bitcoind
+
+/// A JSON-RPC server knowing `txs` through getrawtransaction and `utxos`
+/// through gettxout. Requests without `auth` as Authorization get a 401.
+fn rpc_server(
+    txs: Vec<Transaction>,
+    utxos: Vec<(OutPoint, Value)>,
+    auth: &'static str,
+) -> MockHttpServer {
+    MockHttpServer::start(move |req: &HttpRequest| {
+        if req.header("Authorization") != Some(auth) {
+            return (401, "text/plain", vec![]);
+        }
+        let calls: Vec<Value> = serde_json::from_slice(&req.body).unwrap();
+        let replies: Vec<Value> = calls
+            .iter()
+            .map(|call| {
+                let params = &call["params"];
+                let result = match call["method"].as_str().unwrap() {
+                    "getrawtransaction" => txs
+                        .iter()
+                        .find(|tx| tx.txid().to_string() == params[0])
+                        .map(|tx| json!(bitcoin::consensus::encode::serialize_hex(tx))),
+                    "gettxout" => Some(
+                        utxos
+                            .iter()
+                            .find(|(o, _)| o.txid.to_string() == params[0] && o.vout == params[1])
+                            .map_or(Value::Null, |(_, utxo)| utxo.clone()),
+                    ),
+                    _ => None,
+                };
+                match result {
+                    Some(result) => json!({"result": result, "error": null, "id": call["id"]}),
+                    None => json!({
+                        "result": null,
+                        "error": {"code": -5, "message": "No such mempool or blockchain transaction"},
+                        "id": call["id"],
+                    }),
+                }
+            })
+            .collect();
+        // Replies in reverse order: ids, not positions, identify the calls
+        let replies: Vec<Value> = replies.into_iter().rev().collect();
+        (
+            200,
+            "application/json",
+            serde_json::to_vec(&replies).unwrap(),
+        )
+    })
+}
+
+#[test]
+fn rpc_batch_and_userpass() {
+    let (tx, reftx) = p2wsh_txs();
+    //base64("user:pass")
+    let server = rpc_server(vec![reftx.clone()], vec![], "Basic dXNlcjpwYXNz");
+    let rpc = BitcoindRpc::new(
+        &server.url(),
+        BitcoindAuth::UserPass {
+            user: "user".to_string(),
+            password: "pass".to_string(),
+        },
+    );
+
+    let report = verify_tx_signers_from(&tx, &rpc).unwrap();
+    assert!(report.summary.unanalyzed.is_empty());
+    assert_eq!(report.inputs[0].verified_keys().count(), 2);
+
+    //Both outputs of the funding tx and an unknown one: one batch request
+    //for the transactions, one for the UTXO set lookup of the unknown tx
+    let unknown = OutPoint::new(tx.txid(), 0);
+    let outpoints = [
+        OutPoint::new(reftx.txid(), 0),
+        OutPoint::new(reftx.txid(), 1),
+        unknown,
+    ];
+    let before = server.requests().len();
+    let txouts = rpc.prevouts(&outpoints).unwrap();
+    assert_eq!(txouts[0].as_ref(), Some(&reftx.output[0]));
+    assert_eq!(txouts[1].as_ref(), Some(&reftx.output[1]));
+    assert_eq!(txouts[2], None);
+    let requests = server.requests();
+    assert_eq!(requests.len() - before, 2);
+    let batch: Vec<Value> = serde_json::from_slice(&requests[before].body).unwrap();
+    assert_eq!(batch.len(), 2);
+    let batch: Vec<Value> = serde_json::from_slice(&requests[before + 1].body).unwrap();
+    assert_eq!(batch[0]["method"], "gettxout");
+
+    //Smaller batches split the calls over several requests
+    let rpc = rpc.batch_size(1);
+    let before = server.requests().len();
+    rpc.get_raw_transactions(&[reftx.txid(), tx.txid()])
+        .unwrap();
+    assert_eq!(server.requests().len() - before, 2);
+}
+
+#[test]
+fn rpc_cookie_and_gettxout() {
+    let (tx, reftx) = p2wsh_txs();
+    let cookie = std::env::temp_dir().join(format!("minikeys-cookie-{}", std::process::id()));
+    std::fs::write(&cookie, "__cookie__:secret\n").unwrap();
+    //Node without -txindex: the funding tx is only known through its UTXO
+    let spent = tx.input[0].previous_output;
+    let utxo = json!({
+        "bestblock": "00000000000000000002a7c4c1e48d76c5a37902165a270156b7a8d72728a054",
+        "confirmations": 10,
+        "value": reftx.output[0].value as f64 / 1e8,
+        "scriptPubKey": {"hex": hex::encode(reftx.output[0].script_pubkey.as_bytes())},
+        "coinbase": false,
+    });
+    //base64("__cookie__:secret")
+    let server = rpc_server(
+        vec![],
+        vec![(spent, utxo)],
+        "Basic X19jb29raWVfXzpzZWNyZXQ=",
+    );
+    let rpc = BitcoindRpc::new(&server.url(), BitcoindAuth::CookieFile(cookie.clone()));
+
+    assert_eq!(
+        rpc.prevout(&spent).unwrap().as_ref(),
+        Some(&reftx.output[0])
+    );
+    let report = verify_tx_signers_from(&tx, &rpc).unwrap();
+    assert_eq!(report.inputs[0].verified_keys().count(), 2);
+    std::fs::remove_file(cookie).unwrap();
+}
+
+#[test]
+fn rpc_errors() {
+    let (tx, _) = p2wsh_txs();
+    let server = rpc_server(vec![], vec![], "Basic dXNlcjpwYXNz");
+    let rpc = BitcoindRpc::new(&server.url(), BitcoindAuth::None);
+    let err = verify_tx_signers_from(&tx, &rpc).unwrap_err();
+    assert!(
+        matches!(err, minikeys::MinikeysError::Provider(_)),
+        "{}",
+        err
+    );
+
+    //Missing cookie file
+    let rpc = BitcoindRpc::new(
+        &server.url(),
+        BitcoindAuth::CookieFile("/nonexistent/.cookie".into()),
+    );
+    assert!(rpc.prevout(&tx.input[0].previous_output).is_err());
+}
+
+#[test]
+fn rest() {
+    let (tx, reftx) = p2wsh_txs();
+    let raw_reftx = bitcoin::consensus::serialize(&reftx);
+    let path = format!("/rest/tx/{}.bin", reftx.txid());
+    let server = MockHttpServer::start(move |req: &HttpRequest| {
+        if req.method == "GET" && req.path == path {
+            (200, "application/octet-stream", raw_reftx.clone())
+        } else if req.path.starts_with("/rest/getutxos/checkmempool/") {
+            let body = json!({"chainHeight": 800000, "bitmap": "0", "utxos": []});
+            (200, "application/json", serde_json::to_vec(&body).unwrap())
+        } else {
+            (404, "text/plain", vec![])
+        }
+    });
+    let rest = BitcoindRest::new(&server.url());
+
+    let report = verify_tx_signers_from(&tx, &rest).unwrap();
+    assert_eq!(report.inputs[0].verified_keys().count(), 2);
+    assert_eq!(rest.get_tx(&tx.txid()).unwrap(), None);
+    assert_eq!(rest.prevout(&OutPoint::new(tx.txid(), 0)).unwrap(), None);
+}
+
+#[test]
+fn mismatched_tx() {
+    let (tx, reftx) = p2wsh_txs();
+    //A node or proxy answering every transaction lookup with the funding tx
+    let raw_reftx = bitcoin::consensus::serialize(&reftx);
+    let server = MockHttpServer::start(move |req: &HttpRequest| {
+        if req.path.starts_with("/rest/tx/") {
+            return (200, "application/octet-stream", raw_reftx.clone());
+        }
+        let calls: Vec<Value> = serde_json::from_slice(&req.body).unwrap();
+        let replies: Vec<Value> = calls
+            .iter()
+            .map(|call| {
+                json!({
+                    "result": hex::encode(&raw_reftx),
+                    "error": null,
+                    "id": call["id"],
+                })
+            })
+            .collect();
+        (
+            200,
+            "application/json",
+            serde_json::to_vec(&replies).unwrap(),
+        )
+    });
+    let expected = format!("got transaction {} for {}", reftx.txid(), tx.txid());
+    let spent_from_tx = OutPoint::new(tx.txid(), 0);
+
+    let rpc = BitcoindRpc::new(&server.url(), BitcoindAuth::None);
+    assert_eq!(
+        rpc.get_raw_transaction(&reftx.txid(), None).unwrap(),
+        Some(reftx.clone())
+    );
+    let err = rpc.get_raw_transaction(&tx.txid(), None).unwrap_err();
+    assert!(err.to_string().contains(&expected), "{}", err);
+    let err = rpc.prevouts(&[spent_from_tx]).unwrap_err();
+    assert!(err.to_string().contains(&expected), "{}", err);
+
+    let rest = BitcoindRest::new(&server.url());
+    assert_eq!(rest.get_tx(&reftx.txid()).unwrap(), Some(reftx.clone()));
+    let err = rest.get_tx(&tx.txid()).unwrap_err();
+    assert!(err.to_string().contains(&expected), "{}", err);
+    let err = rest.prevout(&spent_from_tx).unwrap_err();
+    assert!(err.to_string().contains(&expected), "{}", err);
+}
@@ -0,0 +1,307 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::consensus::encode::VarInt;
+use bitcoin::consensus::serialize;
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::{Block, Network, PublicKey, ScriptBuf, TxOut};
+use hex_lit::hex;
+use minikeys::blockfile::{scan_block, BlockFileReader, BlockUndo, BlocksDir, ScanResult};
+use minikeys::{MinikeysError, PrevoutMap, SpendType};
+
+mod common;
+use common::{compress_amount, p2ms_txs, p2wsh_txs, varint, write_compressed_txout};
+
+//Checks reading Bitcoin Core block and undo files, obfuscated or not, and
+//scanning their blocks for multisig spends.
+
+//run with: cargo test --test block_scan
+
+const XOR_KEY: [u8; 8] = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+
+//Blocks of the genesis header holding the spends of validkeys.rs
+fn test_blocks() -> Vec<Block> {
+    let header = genesis_block(Network::Bitcoin).header;
+    let (p2wsh_tx, p2wsh_reftx) = p2wsh_txs();
+    let (p2ms_tx, _) = p2ms_txs();
+    vec![
+        Block {
+            header,
+            txdata: vec![p2wsh_reftx, p2wsh_tx],
+        },
+        Block {
+            header,
+            txdata: vec![p2ms_tx],
+        },
+    ]
+}
+
+/// Writes `blocks` as a block file, followed by `padding` zeroed bytes, all
+/// XORed with `key`.
+fn write_block_file(path: &Path, blocks: &[Block], padding: usize, key: [u8; 8]) {
+    let mut data = vec![];
+    for block in blocks {
+        let raw = serialize(block);
+        data.extend(Network::Bitcoin.magic().to_bytes());
+        data.extend((raw.len() as u32).to_le_bytes());
+        data.extend(raw);
+    }
+    data.extend(vec![0; padding]);
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[i % 8];
+    }
+    std::fs::write(path, data).unwrap();
+}
+
+/// Serializes an undo record, the spent coins being at height 100.
+fn encode_undo(spent_outputs: &[Vec<TxOut>]) -> Vec<u8> {
+    let mut raw = serialize(&VarInt(spent_outputs.len() as u64));
+    for txouts in spent_outputs {
+        raw.extend(serialize(&VarInt(txouts.len() as u64)));
+        for txout in txouts {
+            raw.extend(varint(100 * 2));
+            raw.extend(varint(0));
+            write_compressed_txout(&mut raw, txout);
+        }
+    }
+    raw
+}
+
+/// Writes undo records for the blocks following the given previous block
+/// hashes, with their checksums, XORed with `key`.
+fn write_rev_file(path: &Path, records: &[(bitcoin::BlockHash, Vec<u8>)], key: [u8; 8]) {
+    let mut data = vec![];
+    for (prev_blockhash, raw) in records {
+        data.extend(Network::Bitcoin.magic().to_bytes());
+        data.extend((raw.len() as u32).to_le_bytes());
+        data.extend(raw);
+        let mut preimage = prev_blockhash.to_byte_array().to_vec();
+        preimage.extend(raw);
+        data.extend(sha256d::Hash::hash(&preimage).to_byte_array());
+    }
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[i % 8];
+    }
+    std::fs::write(path, data).unwrap();
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("minikeys-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn blocks_dir() {
+    let blocks = test_blocks();
+    for key in [None, Some(XOR_KEY)] {
+        let dir = temp_dir("blocks");
+        if let Some(key) = key {
+            std::fs::write(dir.join("xor.dat"), key).unwrap();
+        }
+        let key = key.unwrap_or_default();
+        write_block_file(&dir.join("blk00000.dat"), &blocks[..1], 4096, key);
+        write_block_file(&dir.join("blk00001.dat"), &blocks[1..], 0, key);
+        //Not a block file
+        std::fs::write(dir.join("rev00000.dat"), [0xff; 16]).unwrap();
+
+        let blocks_dir = BlocksDir::open(&dir, Network::Bitcoin).unwrap();
+        assert_eq!(blocks_dir.xor_key(), key);
+        assert_eq!(blocks_dir.block_files().unwrap().len(), 2);
+        let read: Vec<Block> = blocks_dir.blocks().unwrap().map(Result::unwrap).collect();
+        assert_eq!(read, blocks);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn bad_records() {
+    let blocks = test_blocks();
+    let dir = temp_dir("bad-blocks");
+    let path = dir.join("blk00000.dat");
+    write_block_file(&path, &blocks, 0, XOR_KEY);
+
+    //Wrong key or network: the first magic does not match
+    let mut reader = BlockFileReader::open(&path, Network::Bitcoin, [0; 8]).unwrap();
+    assert!(matches!(
+        reader.next(),
+        Some(Err(MinikeysError::BlockFile(_)))
+    ));
+    assert!(reader.next().is_none());
+    let mut reader = BlockFileReader::open(&path, Network::Testnet, XOR_KEY).unwrap();
+    assert!(matches!(
+        reader.next(),
+        Some(Err(MinikeysError::BlockFile(_)))
+    ));
+
+    //Truncated last block
+    let data = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &data[..data.len() - 10]).unwrap();
+    let read: Vec<_> = BlockFileReader::open(&path, Network::Bitcoin, XOR_KEY)
+        .unwrap()
+        .collect();
+    assert_eq!(read.len(), 2);
+    assert!(read[0].is_ok() && read[1].is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn scan_block_signers() {
+    let blocks = test_blocks();
+    //The P2WSH spend finds its prevout in the block, the P2WPKH input of
+    //the funding tx is skipped
+    let records = scan_block(&blocks[0], &PrevoutMap::new());
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].block_hash, blocks[0].block_hash());
+    assert_eq!(records[0].txid, blocks[0].txdata[1].txid());
+    let ScanResult::Report(report) = &records[0].result else {
+        panic!("{:?}", records[0].result)
+    };
+    assert_eq!(report.spend_type, SpendType::P2wsh);
+    assert_eq!(report.verified_keys().count(), 2);
+
+    //The bare multisig spend needs its prevout from the provider
+    let records = scan_block(&blocks[1], &PrevoutMap::new());
+    assert_eq!(records.len(), 1);
+    assert!(matches!(
+        records[0].result,
+        ScanResult::Error(MinikeysError::MissingPrevout { .. })
+    ));
+    let (_, p2ms_reftx) = p2ms_txs();
+    let records = scan_block(&blocks[1], &PrevoutMap::from_txs([&p2ms_reftx]));
+    let ScanResult::Report(report) = &records[0].result else {
+        panic!("{:?}", records[0].result)
+    };
+    assert_eq!(report.spend_type, SpendType::Bare);
+    assert_eq!(report.verified_keys().count(), 2);
+}
+
+#[test]
+fn undo_decoding() {
+    //Vectors of Core's compress_tests
+    assert_eq!(compress_amount(100_000_000), 0x9);
+    assert_eq!(compress_amount(2_100_000_000_000_000), 0x1406f40);
+    //Core's serialize_tests VARINTs
+    assert_eq!(varint(0x80), [0x80, 0x00]);
+    assert_eq!(varint(0xffffffff), [0x8e, 0xfe, 0xfe, 0xfe, 0x7f]);
+
+    //One tx spending a P2PK output of each kind and a P2PKH one
+    let g = hex!("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+    let mut raw = vec![1, 4];
+    for (size, value) in [(2u8, 0x1u64), (4, 0x9), (5, 0x32)] {
+        //Coinbase coin at height 0: no dummy version
+        raw.extend([0x01]);
+        raw.extend(varint(value));
+        raw.push(size);
+        raw.extend(g);
+    }
+    raw.extend(varint(2 * 7));
+    raw.extend(varint(0));
+    raw.extend(varint(0x1406f40));
+    raw.push(0);
+    raw.extend([0xab; 20]);
+    let undo = BlockUndo::decode(&raw).unwrap();
+
+    let key =
+        PublicKey::from_str("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap();
+    let uncompressed = PublicKey {
+        compressed: false,
+        inner: key.inner,
+    };
+    let spent = &undo.spent_outputs[0];
+    assert_eq!(spent[0].value, 1);
+    assert_eq!(spent[0].script_pubkey, ScriptBuf::new_p2pk(&key));
+    assert_eq!(spent[1].value, 100_000_000);
+    assert_eq!(spent[1].script_pubkey, ScriptBuf::new_p2pk(&uncompressed));
+    assert_eq!(spent[1].script_pubkey.len(), 67);
+    //Odd y
+    assert_ne!(spent[2].script_pubkey, spent[1].script_pubkey);
+    assert_eq!(spent[2].value, 50 * 100_000_000);
+    assert_eq!(spent[3].value, 21_000_000 * 100_000_000);
+    assert!(spent[3].script_pubkey.is_p2pkh());
+
+    assert!(BlockUndo::decode(&raw[..raw.len() - 1]).is_err());
+    let mut trailing = raw.clone();
+    trailing.push(0);
+    assert!(BlockUndo::decode(&trailing).is_err());
+}
+
+#[test]
+fn blocks_with_undo() {
+    let genesis = genesis_block(Network::Bitcoin);
+    let (p2wsh_tx, p2wsh_reftx) = p2wsh_txs();
+    let (p2ms_tx, p2ms_reftx) = p2ms_txs();
+    let prevouts = PrevoutMap::from_txs([&p2wsh_reftx, &p2ms_reftx]);
+    let spent = vec![
+        prevouts.spent_outputs(&p2ms_tx).unwrap(),
+        prevouts.spent_outputs(&p2wsh_tx).unwrap(),
+    ];
+    //Two blocks of the same shape, told apart by their previous block
+    let mut first = Block {
+        header: genesis.header,
+        txdata: vec![genesis.txdata[0].clone(), p2ms_tx, p2wsh_tx],
+    };
+    first.header.prev_blockhash = genesis.block_hash();
+    let mut second = first.clone();
+    second.header.prev_blockhash = first.block_hash();
+    let mut other_spent = spent.clone();
+    other_spent[1][0].value += 1;
+
+    let dir = temp_dir("undo");
+    std::fs::write(dir.join("xor.dat"), XOR_KEY).unwrap();
+    write_block_file(
+        &dir.join("blk00000.dat"),
+        &[genesis.clone(), first.clone(), second.clone()],
+        0,
+        XOR_KEY,
+    );
+    //Undo records in connection order, not block file order
+    write_rev_file(
+        &dir.join("rev00000.dat"),
+        &[
+            (second.header.prev_blockhash, encode_undo(&other_spent)),
+            (first.header.prev_blockhash, encode_undo(&spent)),
+        ],
+        XOR_KEY,
+    );
+
+    let blocks_dir = BlocksDir::open(&dir, Network::Bitcoin).unwrap();
+    let read: Vec<(Block, Option<BlockUndo>)> = blocks_dir
+        .blocks_with_undo()
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(read.len(), 3);
+    assert_eq!(read[0], (genesis, None));
+    assert_eq!(read[1].1.as_ref().unwrap().spent_outputs, spent);
+    assert_eq!(read[2].1.as_ref().unwrap().spent_outputs, other_spent);
+    let prevout_map = read[1].1.as_ref().unwrap().prevout_map(&first).unwrap();
+    assert_eq!(
+        prevout_map.spent_outputs(&first.txdata[2]).unwrap(),
+        spent[1]
+    );
+    assert!(read[1]
+        .1
+        .as_ref()
+        .unwrap()
+        .prevout_map(&genesis_block(Network::Bitcoin))
+        .is_err());
+
+    //The genesis block has no undo data and is not scanned
+    let records: Vec<_> = blocks_dir.scan().unwrap().map(Result::unwrap).collect();
+    assert_eq!(records.len(), 2);
+    for record in &records[0] {
+        let ScanResult::Report(report) = &record.result else {
+            panic!("{:?}", record.result)
+        };
+        assert_eq!(report.verified_keys().count(), 2);
+    }
+    //A wrong amount invalidates the segwit signatures only
+    let ScanResult::Report(report) = &records[1][1].result else {
+        panic!("{:?}", records[1][1].result)
+    };
+    assert_eq!(report.verified_keys().count(), 0);
+    std::fs::remove_dir_all(dir).unwrap();
+}
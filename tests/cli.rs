@@ -0,0 +1,76 @@
+use std::process::Command;
+
+use bitcoin::hashes::Hash;
+use bitcoin::key::{Secp256k1, TapTweak};
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::secp256k1::Message;
+use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoin::{OutPoint, ScriptBuf, TxIn, TxOut, Txid, Witness};
+use serde_json::Value;
+
+mod common;
+use common::{keypair, unsigned_spend, xonly};
+
+//Checks the minikeys binary on inputs the library alone does not cover.
+
+//run with: cargo test --test cli
+
+fn minikeys(args: &[&str]) -> Value {
+    let output = Command::new(env!("CARGO_BIN_EXE_minikeys"))
+        .args(["--format", "json"])
+        .args(args)
+        .output()
+        .unwrap();
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn psbt_inputs_after_unanalyzed_one() {
+    // Input 0 spends an OP_RETURN output, which is left unanalyzed; input 1
+    // is a key path spend of xonly(1), given as the PSBT's tap_internal_key
+    let secp = Secp256k1::new();
+    let script_pubkey = ScriptBuf::new_v1_p2tr(&secp, xonly(1), None);
+    let (mut tx, funding_tx) = unsigned_spend(script_pubkey, 7);
+    tx.input.insert(
+        0,
+        TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([8; 32]), 0),
+            ..TxIn::default()
+        },
+    );
+    let unsupported = TxOut {
+        value: 1000,
+        script_pubkey: ScriptBuf::new_op_return(&[]),
+    };
+    let prevouts = [unsupported.clone(), funding_tx.output[0].clone()];
+    let sighash = SighashCache::new(&tx)
+        .taproot_key_spend_signature_hash(1, &Prevouts::All(&prevouts), TapSighashType::Default)
+        .unwrap();
+    let msg = Message::from_slice(&sighash[..]).unwrap();
+    let tweaked = keypair(1).tap_tweak(&secp, None).to_inner();
+    let sig = bitcoin::taproot::Signature {
+        sig: secp.sign_schnorr_no_aux_rand(&msg, &tweaked),
+        hash_ty: TapSighashType::Default,
+    };
+
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+    psbt.inputs[0].witness_utxo = Some(unsupported);
+    psbt.inputs[1].witness_utxo = Some(funding_tx.output[0].clone());
+    psbt.inputs[1].final_script_witness = Some(Witness::from_slice(&[sig.to_vec()]));
+    psbt.inputs[1].tap_internal_key = Some(xonly(1));
+
+    let doc = minikeys(&["psbt", &psbt.to_string()]);
+    let result = &doc["result"];
+    let unanalyzed = &result["summary"]["unanalyzed"];
+    assert_eq!(unanalyzed.as_array().unwrap().len(), 1);
+    assert_eq!(unanalyzed[0]["input_index"], 0);
+    assert_eq!(unanalyzed[0]["error"]["kind"], "unsupported_script");
+    let inputs = result["inputs"].as_array().unwrap();
+    assert_eq!(inputs.len(), 1);
+    assert_eq!(inputs[0]["input_index"], 1);
+    assert_eq!(
+        inputs[0]["taproot_commitment"]["internal_key"],
+        xonly(1).to_string()
+    );
+    assert_eq!(inputs[0]["taproot_commitment"]["merkle_root"], Value::Null);
+}
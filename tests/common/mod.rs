@@ -0,0 +1,347 @@
+//! Local stand-in servers for the provider tests, encoders of the Bitcoin
+//! Core files read by the offline ones, and the transactions and synthetic
+//! keys the tests share.
+
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::key::{KeyPair, Secp256k1, TapTweak, XOnlyPublicKey};
+use bitcoin::opcodes::all::OP_CHECKSIG;
+use bitcoin::secp256k1::{Message, SecretKey};
+use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoin::taproot::{LeafVersion, TapLeafHash, TapNodeHash, TaprootSpendInfo};
+use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use hex_lit::hex;
+use minikeys::decode_tx;
+
+/// One HTTP request as received by [`MockHttpServer`].
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Serves every request with `handler`, which returns a status code, a
+/// content type and a body. Requests are recorded for inspection.
+pub struct MockHttpServer {
+    pub addr: SocketAddr,
+    requests: Arc<Mutex<Vec<HttpRequest>>>,
+}
+
+type Handler = dyn Fn(&HttpRequest) -> (u16, &'static str, Vec<u8>) + Send + Sync;
+
+impl MockHttpServer {
+    pub fn start(
+        handler: impl Fn(&HttpRequest) -> (u16, &'static str, Vec<u8>) + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let recorded = requests.clone();
+        let handler: Arc<Handler> = Arc::new(handler);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let recorded = recorded.clone();
+                let handler = handler.clone();
+                thread::spawn(move || serve(stream, &*handler, &recorded));
+            }
+        });
+        MockHttpServer { addr, requests }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Answers the requests of one keep-alive connection.
+fn serve(stream: TcpStream, handler: &Handler, recorded: &Mutex<Vec<HttpRequest>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+    while let Some(request) = read_request(&mut reader) {
+        let (status, content_type, body) = handler(&request);
+        recorded.lock().unwrap().push(request);
+        let head = format!(
+            "HTTP/1.1 {} X\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            status,
+            content_type,
+            body.len()
+        );
+        if stream.write_all(head.as_bytes()).is_err() || stream.write_all(&body).is_err() {
+            return;
+        }
+    }
+}
+
+fn read_request(reader: &mut BufReader<TcpStream>) -> Option<HttpRequest> {
+    let mut line = String::new();
+    reader.read_line(&mut line).ok().filter(|n| *n > 0)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    let mut request = HttpRequest {
+        method,
+        path,
+        headers,
+        body: vec![],
+    };
+    let len: usize = request
+        .header("Content-Length")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0);
+    request.body = vec![0; len];
+    reader.read_exact(&mut request.body).ok()?;
+    Some(request)
+}
+
+/// Bitcoin Core's `VARINT`.
+pub fn varint(mut n: u64) -> Vec<u8> {
+    let mut out = vec![(n & 0x7f) as u8];
+    while n > 0x7f {
+        n = (n >> 7) - 1;
+        out.push((n & 0x7f) as u8 | 0x80);
+    }
+    out.reverse();
+    out
+}
+
+/// Bitcoin Core's `CompressAmount`.
+// `u64::is_multiple_of` needs Rust 1.87
+#[allow(unknown_lints, clippy::manual_is_multiple_of)]
+pub fn compress_amount(mut n: u64) -> u64 {
+    if n == 0 {
+        return 0;
+    }
+    let mut e = 0;
+    while n % 10 == 0 && e < 9 {
+        n /= 10;
+        e += 1;
+    }
+    if e < 9 {
+        let d = n % 10;
+        n /= 10;
+        1 + (n * 9 + d - 1) * 10 + e
+    } else {
+        1 + (n - 1) * 10 + 9
+    }
+}
+
+/// Bitcoin Core's `TxOutCompression`. P2PK outputs are written as raw
+/// scripts, which Core never does but decodes all the same.
+pub fn write_compressed_txout(out: &mut Vec<u8>, txout: &TxOut) {
+    out.extend(varint(compress_amount(txout.value)));
+    let script = txout.script_pubkey.as_bytes();
+    if txout.script_pubkey.is_p2pkh() {
+        out.push(0);
+        out.extend(&script[3..23]);
+    } else if txout.script_pubkey.is_p2sh() {
+        out.push(1);
+        out.extend(&script[2..22]);
+    } else {
+        out.extend(varint(script.len() as u64 + 6));
+        out.extend(script);
+    }
+}
+
+//The P2SH 2of2 multisig spend of validkeys.rs and its funding transaction
+pub fn p2sh_txs() -> (Transaction, Transaction) {
+    let tx = decode_tx(&hex!("0100000001d611ad58b2f5bc0db7d15dfde4f497d6482d1b4a1e8c462ef077d4d32b3dae7901000000da0047304402203b17b4f64fa7299e8a85a688bda3cb1394b80262598bbdffd71dab1d7f266098022019cc20dc20eae417374609cb9ca22b28261511150ed69d39664b9d3b1bcb3d1201483045022100cfff9c400abb4ce5f247bd1c582cf54ec841719b0d39550b714c3c793fb4347b02201427a961a7f32aba4eeb1b71b080ea8712705e77323b747c03c8f5dbdda1025a01475221032d7306898e980c66aefdfb6b377eaf71597c449bf9ce741a3380c5646354f6de2103e8c742e1f283ef810c1cd0c8875e5c2998a05fc5b23c30160d3d33add7af565752aeffffffff020ed000000000000016001477800cff52bd58133b895622fd1220d9e2b47a79cd0902000000000017a914da55145ca5c56ba01f1b0b98d896425aa4b0f4468700000000")).unwrap();
+    let reftx = decode_tx(&hex!("0100000001ade1ff3b7e2da73914f831ef20ab26bb861f60b7659ff7de3af07137d973a17402000000da00473044022039c919ba99640e2256b8b56c22278997bfdc24a78b72e9dd7e232d0a5650c01702204a15618469c4f0978e123d391f833f7bc6dc9e3b7d22f79d492e0c70a6aa704701483045022100983973c309c05ab8a6002e1ea663fb44227b1b8eaaaeb2d63e6c39a595f02a78022020fd7cc11660bf10cef660a0256276a9b378d236b30fe3a9f900448a7d5de0b201475221032d7306898e980c66aefdfb6b377eaf71597c449bf9ce741a3380c5646354f6de2103e8c742e1f283ef810c1cd0c8875e5c2998a05fc5b23c30160d3d33add7af565752aeffffffff02b5ba0000000000001976a914c91852f5780f02a7f8884f05037547594ac3cb8988ac5ce002000000000017a914da55145ca5c56ba01f1b0b98d896425aa4b0f4468700000000")).unwrap();
+    (tx, reftx)
+}
+
+//The P2WSH 2of2 multisig spend of validkeys.rs and its funding transaction
+pub fn p2wsh_txs() -> (Transaction, Transaction) {
+    let tx = decode_tx(&hex!("010000000001011b9eb4122976fad8f809ee4cea8ac8d1c5b6b8e0d0f9f93327a5d78c9a3945280000000000ffffffff02ba3e0d00000000002200201c3b09401aaa7c9709d118a75d301bdb2180fb68b2e9b3ade8ad4ff7281780cfa586010000000000220020a41d0d894799879ca1bd88c1c3f1c2fd4b1592821cc3c5bfd5be5238b904b09f040047304402201c7563e876d67b5702aea5726cd202bf92d0b1dc52c4acd03435d6073e630bac022032b64b70d7fba0cb8be30b882ea06c5f8ec7288d113459dd5d3e294214e2c96201483045022100f532f7e3b8fd01a0edc86de4870db4e04858964d0a609df81deb99d9581e6c2e02206d9e9b6ab661176be8194faded62f518cdc6ee74dba919e0f35d77cff81f38e5014752210289da5da9d3700156db2d01e6362491733f6c886971791deda74b4e9d707190b2210323c437f30384498be79df2990ce5a8de00844e768c0ccce914335b6c26adea7352ae00000000")).unwrap();
+    let reftx = decode_tx(&hex!("02000000000101828b350cf855b1a52b23a17dc89ed4e99e6d70503e1e1519945f75e19e7617c50000000000fdffffff0230c60e0000000000220020781ada670a98cfb276c6d2a78bbf21eb8f3617f4c2288cb16f5ad8741b5d83dd809698000000000016001449176d383a51877682b0f80e24776c015b2fa6d502473044022064b1047b43707baef72e69796c18340cfe74ff3fdb720bd8d6d814df2c61224802204c2c00fe218461eb84f01a885b90f73b90caae7b25e906e43618916cd7bd3a270121033d0f4a852ee6b3cec7bea4296bf6e4a88510fb5ac6da80b95fa8bb3bfc9fd1bb5fae0a00")).unwrap();
+    (tx, reftx)
+}
+
+//The bare 2of3 multisig spend of validkeys.rs and its funding transaction
+pub fn p2ms_txs() -> (Transaction, Transaction) {
+    let tx = decode_tx(&hex!("010000000110a5fee9786a9d2d72c25525e52dd70cbd9035d5152fac83b62d3aa7e2301d58000000009300483045022100af204ef91b8dba5884df50f87219ccef22014c21dd05aa44470d4ed800b7f6e40220428fe058684db1bb2bfb6061bff67048592c574effc217f0d150daedcf36787601483045022100e8547aa2c2a2761a5a28806d3ae0d1bbf0aeff782f9081dfea67b86cacb321340220771a166929469c34959daf726a2ac0c253f9aff391e58a3c7cb46d8b7e0fdc4801ffffffff0180a21900000000001976a914971802edf585cdbc4e57017d6e5142515c1e502888ac00000000")).unwrap();
+    let reftx = decode_tx(&hex!("01000000014563f26698c0ea3ebd85d4767457370d7e2ebbe922a7736dbf70e1d0f8a9aa9c000000008a473044022039294d5c8843a6776d4a2032cf03549f41c634ba5e65898c7816973919e485b902205af1f61f6d7d6a5f32cbe46676303c141fe499288b1be0d8f0c4e80d4c0ecb5701410454ffbc96ef3c26acffa431066915308865d990e044c507e0ab3d26af34a8ba5b4cb3028fe7c91926bb8be47d652dc70ab300e3022f8259db5f79306b601fc66effffffff0190c9190000000000c9524104d81fd577272bbe73308c93009eec5dc9fc319fc1ee2e7066e17220a5d47a18314578be2faea34b9f1f8ca078f8621acd4bc22897b03daa422b9bf56646b342a24104ec3afff0b2b66e8152e9018fe3be3fc92b30bf886b3487a525997d00fd9da2d012dce5d5275854adc3106572a5d1e12d4211b228429f5a7b2f7ba92eb0475bb14104b49b496684b02855bc32f5daefa2e2e406db4418f3b86bca5195600951c7d918cdbe5e6d3736ec2abf2dd7610995c3086976b2c0c7b4e459d10b34a316d5a5e753ae00000000")).unwrap();
+    (tx, reftx)
+}
+
+//The P2TR 2of2 script path spend of validkeys.rs and its funding transaction
+pub fn p2tr_txs() -> (Transaction, Transaction) {
+    let tx = decode_tx(&hex!("02000000000101b41b20295ac85fd2ae3e3d02900f1a1e7ddd6139b12e341386189c03d6f5795b0000000000fdffffff0100000000000000003c6a3a546878205361746f7368692120e2889e2f32316d696c20466972737420546170726f6f74206d756c7469736967207370656e64202d426974476f044123b1d4ff27b16af4b0fcb9672df671701a1a7f5a6bb7352b051f461edbc614aa6068b3e5313a174f90f3d95dc4e06f69bebd9cf5a3098fde034b01e69e8e788901400fd4a0d3f36a1f1074cb15838a48f572dc18d412d0f0f0fc1eeda9fa4820c942abb77e4d1a3c2b99ccf4ad29d9189e6e04a017fe611748464449f681bc38cf394420febe583fa77e49089f89b78fa8c116710715d6e40cc5f5a075ef1681550dd3c4ad20d0fa46cb883e940ac3dc5421f05b03859972639f51ed2eccbf3dc5a62e2e1b15ac41c02e44c9e47eaeb4bb313adecd11012dfad435cd72ce71f525329f24d75c5b9432774e148e9209baf3f1656a46986d5f38ddf4e20912c6ac28f48d6bf747469fb100000000")).unwrap();
+    let reftx = decode_tx(&hex!("0200000000010140b84131c5c582290126bbd8b8e2e5bbd7c2681a4b01314f1b874ea1b5fdf81c0000000000ffffffff014c1d0000000000002251202fcad7470279652cc5f88b8908678d6f4d57af5627183b03fc8404cb4e16d88902473044022066d6939ea701db5d306fb948aea64af196ae52fc34d62c2e7992f62cdabc791402200abdac6766105457ceabcbe55a2d33f064d515210085f7af1248d273442e2b2a012103476f0d6a85ced4a85b08cbabbff28564a1ba31091b38f10b167f4fe1e1c9c4f900d40a00")).unwrap();
+    (tx, reftx)
+}
+
+//The P2TR 1of2 script path spend of validkeys.rs, with two inputs, and the
+//funding transactions of both
+pub fn p2tr_two_input_txs() -> (Transaction, [Transaction; 2]) {
+    let tx = decode_tx(&hex!("010000000001022373cf02ce7df6500ae46a4a0fbbb1b636d2debed8f2df91e2415627397a34090000000000fdffffff88c23d928893cd3509845516cf8411b7cab2738c054cc5ce7e4bde9586997c770000000000fdffffff0200000000000000002b6a29676d20746170726f6f7420f09fa5952068747470733a2f2f626974636f696e6465766b69742e6f72676e9e1100000000001976a91405070d0290da457409a37db2e294c1ffbc52738088ac04410adf90fd381d4a13c3e73740b337b230701189ed94abcb4030781635f035e6d3b50b8506470a68292a2bc74745b7a5732a28254b5f766f09e495929ec308090b01004620c13e6d193f5d04506723bd67abcc5d31b610395c445ac6744cb0a1846b3aabaeac20b0e2e48ad7c3d776cf6f2395c504dc19551268ea7429496726c5d5bf72f9333cba519c21c0000000000000000000000000000000000000000000000000000000000000000104414636070d21adc8280735383102f7a0f5978cea257777a23934dd3b458b79bf388aca218e39e23533a059da173e402c4fc5e3375e1f839efb22e9a5c2a815b07301004620c13e6d193f5d04506723bd67abcc5d31b610395c445ac6744cb0a1846b3aabaeac20b0e2e48ad7c3d776cf6f2395c504dc19551268ea7429496726c5d5bf72f9333cba519c21c0000000000000000000000000000000000000000000000000000000000000000100000000")).unwrap();
+    let reftx_0 = decode_tx(&hex!("010000000409cc8928f1d3ea4855dedbff8b783e3379735817b072df569776b5c5187d09ca010000006b483045022100a885cea8709cbb93b8311bf2fd5a30ff3e9fc02459652ebb040f47efc70cf51e02202194d53c2fe26cafcdf5748722949a275faf8575d15e2967d1bd3010d652c21b012102682e5ebd58a7d62a87b0490572776e4bb87dc868fb06419d8f33fe41c21160f9ffffffff0fbd54556226c210849929c0c50c00fc472ab4448be0333aa59f335c4e5a088b010000006b483045022100c4c368a8696a200e2d815c0d7cba690e415e3af6f6a0472b28c292ab85ffaa7002207911811c71ac927c48c47797fe8790a0d7ba172a7005ee8036e70e481909a375012102682e5ebd58a7d62a87b0490572776e4bb87dc868fb06419d8f33fe41c21160f9ffffffffa5091f20a2f91e56811e0d979b2dd7126c58dcd2d767d379e25c0a09c3c526fb010000006a473044022046bf081055f3409cee71cedb396a28060f1166195130cb8bedd6a13ecd1f6beb0220602ebd6e0a7b2c39bcfb59b42035dc246ec2a87e2e9f4b71ffa13feb15167615012102682e5ebd58a7d62a87b0490572776e4bb87dc868fb06419d8f33fe41c21160f9ffffffffb2c3b6434a7bda252db8aeb975ea5ca58da36a461545bb634dddadf5e35c6607010000006b483045022100a0466b24f77b68c54748d1c9ac43559eb91f952928b3fe28e452e619f814f23d022003853b255707400301cedd7922256d623ba2fc60d2734f19f79b2c6f0f61c3d4012102682e5ebd58a7d62a87b0490572776e4bb87dc868fb06419d8f33fe41c21160f9ffffffff01273f110000000000225120667bdd93c7c029767fd516d2ea292624b938fefefa175ac9f1220cf508963ff300000000")).unwrap();
+    let reftx_1 = decode_tx(&hex!("02000000000101fe9d111c806dbf9fa4f03869a42ff81972691b86db5c3ef89381456b4422d3be0000000000ffffffff023075000000000000225120667bdd93c7c029767fd516d2ea292624b938fefefa175ac9f1220cf508963ff30000000000000000116a0f676d20746170726f6f7420f09fa59502473044022001ce176bf7357e12a873b4e439d53eb02f1a642a043a6b7e9e5ae46d0d152f8c02204d603e93f49205624eb56c686fc759cc8d11000f4df76c24bda62d790f13d1ff012102e484e53bcce92e801a29454dae07812d6999bf1133aca94c8b03c65b56bdd08d00000000")).unwrap();
+    (tx, [reftx_0, reftx_1])
+}
+
+/// The throwaway secret key `[byte; 32]`.
+pub fn secret(byte: u8) -> SecretKey {
+    SecretKey::from_slice(&[byte; 32]).unwrap()
+}
+
+pub fn keypair(byte: u8) -> KeyPair {
+    KeyPair::from_secret_key(&Secp256k1::new(), &secret(byte))
+}
+
+pub fn xonly(byte: u8) -> XOnlyPublicKey {
+    keypair(byte).x_only_public_key().0
+}
+
+/// The tapleaf `<xonly(byte)> OP_CHECKSIG`.
+pub fn leaf(byte: u8) -> ScriptBuf {
+    ScriptBuf::builder()
+        .push_x_only_key(&xonly(byte))
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+pub fn leaf_hash(byte: u8) -> TapNodeHash {
+    TapNodeHash::from_script(&leaf(byte), LeafVersion::TapScript)
+}
+
+/// The merkle root of a tree holding only `leaf(byte)`.
+pub fn merkle_root(byte: u8) -> TapNodeHash {
+    leaf_hash(byte)
+}
+
+/// The BIP341 output key, tweaked by bitcoin's own implementation.
+pub fn output_key(
+    internal_key: XOnlyPublicKey,
+    merkle_root: Option<TapNodeHash>,
+) -> XOnlyPublicKey {
+    let secp = Secp256k1::verification_only();
+    internal_key.tap_tweak(&secp, merkle_root).0.to_inner()
+}
+
+/// A transaction paying 50000 sats to `script_pubkey`, and an unsigned one
+/// spending it to `xonly(9)`. `nonce` fills the made up txid the funding
+/// transaction spends, to tell several of them apart.
+pub fn unsigned_spend(script_pubkey: ScriptBuf, nonce: u8) -> (Transaction, Transaction) {
+    let secp = Secp256k1::verification_only();
+    let funding_tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([nonce; 32]), 0),
+            ..TxIn::default()
+        }],
+        output: vec![TxOut {
+            value: 50_000,
+            script_pubkey,
+        }],
+    };
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(funding_tx.txid(), 0),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            ..TxIn::default()
+        }],
+        output: vec![TxOut {
+            value: 49_000,
+            script_pubkey: ScriptBuf::new_v1_p2tr(&secp, xonly(9), None),
+        }],
+    };
+    (tx, funding_tx)
+}
+
+/// A key path spend of the output `keypair` commits to with `merkle_root`.
+pub fn key_path_spend(
+    keypair: &KeyPair,
+    merkle_root: Option<TapNodeHash>,
+) -> (Transaction, Transaction) {
+    let secp = Secp256k1::new();
+    let internal_key = keypair.x_only_public_key().0;
+    let script_pubkey = ScriptBuf::new_v1_p2tr(&secp, internal_key, merkle_root);
+    let (mut tx, funding_tx) = unsigned_spend(script_pubkey, 7);
+    let sighash = SighashCache::new(&tx)
+        .taproot_key_spend_signature_hash(
+            0,
+            &Prevouts::All(&funding_tx.output),
+            TapSighashType::Default,
+        )
+        .unwrap();
+    let msg = Message::from_slice(&sighash[..]).unwrap();
+    let tweaked = keypair.tap_tweak(&secp, merkle_root).to_inner();
+    let sig = bitcoin::taproot::Signature {
+        sig: secp.sign_schnorr_no_aux_rand(&msg, &tweaked),
+        hash_ty: TapSighashType::Default,
+    };
+    tx.input[0].witness = Witness::from_slice(&[sig.to_vec()]);
+    (tx, funding_tx)
+}
+
+/// A spend of `leaf(byte)` in `spend_info`, signed by `keypair(byte)`.
+pub fn script_path_spend(spend_info: &TaprootSpendInfo, byte: u8) -> (Transaction, Transaction) {
+    leaf_version_spend(spend_info, byte, LeafVersion::TapScript)
+}
+
+/// Same as [`script_path_spend`] for a leaf of version `leaf_version`.
+pub fn leaf_version_spend(
+    spend_info: &TaprootSpendInfo,
+    byte: u8,
+    leaf_version: LeafVersion,
+) -> (Transaction, Transaction) {
+    let secp = Secp256k1::new();
+    let script_pubkey = ScriptBuf::new_v1_p2tr_tweaked(spend_info.output_key());
+    let (mut tx, funding_tx) = unsigned_spend(script_pubkey, 7);
+    let script = leaf(byte);
+    let sighash = SighashCache::new(&tx)
+        .taproot_script_spend_signature_hash(
+            0,
+            &Prevouts::All(&funding_tx.output),
+            TapLeafHash::from_script(&script, leaf_version),
+            TapSighashType::Default,
+        )
+        .unwrap();
+    let msg = Message::from_slice(&sighash[..]).unwrap();
+    let sig = bitcoin::taproot::Signature {
+        sig: secp.sign_schnorr_no_aux_rand(&msg, &keypair(byte)),
+        hash_ty: TapSighashType::Default,
+    };
+    let control_block = spend_info
+        .control_block(&(script.clone(), leaf_version))
+        .unwrap();
+    tx.input[0].witness =
+        Witness::from_slice(&[sig.to_vec(), script.into_bytes(), control_block.serialize()]);
+    (tx, funding_tx)
+}
@@ -0,0 +1,163 @@
+use std::cell::Cell;
+use std::path::PathBuf;
+
+use bitcoin::hashes::Hash;
+use bitcoin::{OutPoint, ScriptBuf, TxOut, Txid};
+use minikeys::provider::DiskCachedProvider;
+use minikeys::{MinikeysError, PrevoutMap, PrevoutProvider};
+
+//Checks the disk-backed prevout cache: answers survive reopening the
+//database without the inner provider, and limits evict the least recently
+//used outputs.
+
+//run with: cargo test --test disk_cache --features disk-cache
+
+/// Answers from a map, counting the outpoints asked for.
+struct CountingProvider {
+    map: PrevoutMap,
+    asked: Cell<usize>,
+}
+
+impl CountingProvider {
+    fn new(map: PrevoutMap) -> Self {
+        CountingProvider {
+            map,
+            asked: Cell::new(0),
+        }
+    }
+}
+
+impl PrevoutProvider for CountingProvider {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        self.asked.set(self.asked.get() + 1);
+        self.map.prevout(outpoint)
+    }
+}
+
+fn outpoint(i: u8) -> OutPoint {
+    OutPoint::new(Txid::from_byte_array([i; 32]), u32::from(i))
+}
+
+fn txout(i: u8) -> TxOut {
+    TxOut {
+        value: 1000 * u64::from(i),
+        script_pubkey: ScriptBuf::from(vec![0x51; usize::from(i)]),
+    }
+}
+
+fn map(range: std::ops::Range<u8>) -> PrevoutMap {
+    let mut map = PrevoutMap::new();
+    for i in range {
+        map.insert(outpoint(i), txout(i));
+    }
+    map
+}
+
+/// A fresh database path, removed again when dropped.
+struct TempDb(PathBuf);
+
+impl TempDb {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "minikeys-disk-cache-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        TempDb(path)
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn persistence() {
+    let db = TempDb::new("persistence");
+    let path = &db.0;
+    let outpoints: Vec<OutPoint> = (0..4).map(outpoint).collect();
+    {
+        let cache = DiskCachedProvider::open(path, CountingProvider::new(map(0..3))).unwrap();
+        assert!(cache.is_empty());
+        let txouts = cache.prevouts(&outpoints).unwrap();
+        assert_eq!(
+            txouts,
+            vec![Some(txout(0)), Some(txout(1)), Some(txout(2)), None]
+        );
+        assert_eq!(cache.inner().asked.get(), 4);
+        // Known outputs are answered from the cache, the unknown one asked again
+        assert_eq!(cache.prevouts(&outpoints).unwrap(), txouts);
+        assert_eq!(cache.inner().asked.get(), 5);
+        assert_eq!(cache.len(), 3);
+    }
+
+    // Reopened with an empty provider, as offline
+    let cache = DiskCachedProvider::open(path, CountingProvider::new(PrevoutMap::new())).unwrap();
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.prevout(&outpoint(1)).unwrap(), Some(txout(1)));
+    assert_eq!(cache.prevout(&outpoint(3)).unwrap(), None);
+    assert_eq!(cache.inner().asked.get(), 1);
+
+    cache.clear().unwrap();
+    assert!(cache.is_empty());
+    assert_eq!(cache.size_bytes(), 0);
+    assert_eq!(cache.prevout(&outpoint(1)).unwrap(), None);
+}
+
+#[test]
+fn max_entries() {
+    let db = TempDb::new("max_entries");
+    let path = &db.0;
+    {
+        let cache = DiskCachedProvider::open(path, map(0..10))
+            .unwrap()
+            .max_entries(3);
+        for i in 0..3 {
+            cache.prevout(&outpoint(i)).unwrap();
+        }
+        // 0 becomes the most recently used, 1 the least
+        cache.prevout(&outpoint(0)).unwrap();
+        cache.prevout(&outpoint(3)).unwrap();
+        assert_eq!(cache.len(), 3);
+        cache.prevout(&outpoint(4)).unwrap();
+        assert_eq!(cache.len(), 3);
+    }
+
+    // Access order is kept across reopening
+    let cache = DiskCachedProvider::open(path, CountingProvider::new(PrevoutMap::new()))
+        .unwrap()
+        .max_entries(3);
+    let cached = |cache: &DiskCachedProvider<CountingProvider>, i| {
+        cache.prevout(&outpoint(i)).unwrap().is_some()
+    };
+    assert!(!cached(&cache, 1));
+    assert!(!cached(&cache, 2));
+    assert!(cached(&cache, 0));
+    assert!(cached(&cache, 3));
+    assert!(cached(&cache, 4));
+    assert_eq!(cache.inner().asked.get(), 2);
+}
+
+#[test]
+fn max_bytes() {
+    let db = TempDb::new("max_bytes");
+    let path = &db.0;
+    let cache = DiskCachedProvider::open(path, map(1..30)).unwrap();
+    cache.prevout(&outpoint(10)).unwrap();
+    let entry_size = cache.size_bytes();
+    drop(cache);
+
+    // Room for four outputs like outpoint 10's
+    let cache = DiskCachedProvider::open(path, map(1..30))
+        .unwrap()
+        .max_bytes(4 * entry_size);
+    for i in 11..20 {
+        cache.prevout(&outpoint(i)).unwrap();
+        assert!(cache.size_bytes() <= 4 * entry_size);
+    }
+    assert!(cache.len() < 4);
+    assert_eq!(cache.prevout(&outpoint(19)).unwrap(), Some(txout(19)));
+}
@@ -0,0 +1,219 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use bitcoin::{OutPoint, Transaction, Txid};
+use minikeys::provider::{ElectrumProvider, ElectrumTls};
+use minikeys::{verify_tx_signers_from, MinikeysError, PrevoutProvider};
+use rustls::pki_types::PrivateKeyDer;
+use serde_json::{json, Value};
+
+mod common;
+use common::p2wsh_txs;
+
+//Checks the Electrum provider against a local fake Electrum server, over
+//plain TCP and over TLS with a self-signed certificate.
+
+//run with: cargo test --test electrum_provider --features electrum
+
+/// A fake Electrum server knowing `txs`, and failing to look up those of
+/// `failing` as if its bitcoind were unavailable. Each batch of calls (the
+/// lines received together) is answered in reverse order, after a
+/// notification, and recorded as a list of methods.
+struct FakeElectrumServer {
+    addr: SocketAddr,
+    batches: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+impl FakeElectrumServer {
+    fn start(
+        txs: Vec<Transaction>,
+        failing: Vec<Txid>,
+        tls: Option<Arc<rustls::ServerConfig>>,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let batches = Arc::new(Mutex::new(vec![]));
+        let recorded = batches.clone();
+        thread::spawn(move || {
+            for tcp in listener.incoming().flatten() {
+                let (txs, failing, recorded) = (txs.clone(), failing.clone(), recorded.clone());
+                let stream: Box<dyn ReadWrite> = match &tls {
+                    Some(config) => {
+                        let conn = rustls::ServerConnection::new(config.clone()).unwrap();
+                        Box::new(rustls::StreamOwned::new(conn, tcp))
+                    }
+                    None => Box::new(tcp),
+                };
+                thread::spawn(move || serve(stream, &txs, &failing, &recorded));
+            }
+        });
+        FakeElectrumServer { addr, batches }
+    }
+
+    fn batches(&self) -> Vec<Vec<String>> {
+        self.batches.lock().unwrap().clone()
+    }
+}
+
+trait ReadWrite: Read + Write + Send {}
+impl<T: Read + Write + Send> ReadWrite for T {}
+
+fn serve(
+    stream: Box<dyn ReadWrite>,
+    txs: &[Transaction],
+    failing: &[Txid],
+    recorded: &Mutex<Vec<Vec<String>>>,
+) {
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut calls: Vec<Value> = vec![];
+        loop {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => calls.push(serde_json::from_str(&line).unwrap()),
+            }
+            if reader.buffer().is_empty() {
+                break;
+            }
+        }
+        recorded.lock().unwrap().push(
+            calls
+                .iter()
+                .map(|c| c["method"].as_str().unwrap().to_string())
+                .collect(),
+        );
+        let mut out = b"{\"jsonrpc\": \"2.0\", \"method\": \"blockchain.headers.subscribe\", \"params\": [{\"height\": 800000}]}\n".to_vec();
+        for call in calls.iter().rev() {
+            let params = &call["params"];
+            let result = match call["method"].as_str().unwrap() {
+                "server.version" => Ok(json!(["FakeElectrum 1.0", "1.4"])),
+                "blockchain.transaction.get"
+                    if failing.iter().any(|t| t.to_string() == params[0]) =>
+                {
+                    Err(json!({"code": 2, "message": "daemon error: DaemonError({'code': -28, 'message': 'Loading block index...'})"}))
+                }
+                "blockchain.transaction.get" => txs
+                    .iter()
+                    .find(|tx| tx.txid().to_string() == params[0])
+                    .map(|tx| json!(bitcoin::consensus::encode::serialize_hex(tx)))
+                    .ok_or_else(|| json!({"code": 2, "message": "daemon error: No such mempool or blockchain transaction"})),
+                method => Err(json!({"code": -32601, "message": format!("unknown method {}", method)})),
+            };
+            let reply = match result {
+                Ok(result) => json!({"jsonrpc": "2.0", "id": call["id"], "result": result}),
+                Err(error) => json!({"jsonrpc": "2.0", "id": call["id"], "error": error}),
+            };
+            serde_json::to_writer(&mut out, &reply).unwrap();
+            out.push(b'\n');
+        }
+        let stream = reader.get_mut();
+        if stream
+            .write_all(&out)
+            .and_then(|()| stream.flush())
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+#[test]
+fn electrum_tcp() {
+    let (tx, reftx) = p2wsh_txs();
+    let server = FakeElectrumServer::start(vec![tx.clone(), reftx.clone()], vec![], None);
+    let electrum = ElectrumProvider::connect_tcp(server.addr).unwrap();
+
+    let report = verify_tx_signers_from(&tx, &electrum).unwrap();
+    assert!(report.summary.unanalyzed.is_empty());
+    assert_eq!(report.inputs[0].verified_keys().count(), 2);
+
+    //Both outputs of the funding tx and one of an unknown tx in one batch
+    let unknown = OutPoint::new(reftx.input[0].previous_output.txid, 0);
+    let txouts = electrum
+        .prevouts(&[
+            OutPoint::new(reftx.txid(), 0),
+            unknown,
+            OutPoint::new(reftx.txid(), 1),
+        ])
+        .unwrap();
+    assert_eq!(txouts[0].as_ref(), Some(&reftx.output[0]));
+    assert_eq!(txouts[1], None);
+    assert_eq!(txouts[2].as_ref(), Some(&reftx.output[1]));
+    let batches = server.batches();
+    assert_eq!(batches[0], vec!["server.version"]);
+    assert_eq!(
+        batches.last().unwrap(),
+        &vec!["blockchain.transaction.get"; 2]
+    );
+
+    //Smaller batches are sent one after the other on the same connection
+    let electrum = electrum.batch_size(1);
+    let before = server.batches().len();
+    let txs = electrum
+        .get_transactions(&[reftx.txid(), tx.txid()])
+        .unwrap();
+    assert_eq!(txs, vec![Some(reftx), Some(tx)]);
+    assert_eq!(server.batches().len() - before, 2);
+}
+
+#[test]
+fn electrum_tls() {
+    let (tx, reftx) = p2wsh_txs();
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_no_client_auth()
+    .with_single_cert(
+        vec![cert.der().clone()],
+        PrivateKeyDer::Pkcs8(key_pair.serialize_der().into()),
+    )
+    .unwrap();
+    let server = FakeElectrumServer::start(vec![tx.clone(), reftx], vec![], Some(Arc::new(config)));
+
+    //Self-signed certificate given as root
+    let roots = ElectrumTls::Roots(vec![cert.der().to_vec()]);
+    let electrum = ElectrumProvider::connect_tls(server.addr, "localhost", roots).unwrap();
+    let report = verify_tx_signers_from(&tx, &electrum).unwrap();
+    assert_eq!(report.inputs[0].verified_keys().count(), 2);
+
+    //Or accepted without verification
+    let electrum =
+        ElectrumProvider::connect_tls(server.addr, "localhost", ElectrumTls::AcceptInvalidCerts)
+            .unwrap();
+    assert!(electrum
+        .prevout(&tx.input[0].previous_output)
+        .unwrap()
+        .is_some());
+
+    //But rejected against the public roots
+    assert!(
+        ElectrumProvider::connect_tls(server.addr, "localhost", ElectrumTls::WebpkiRoots).is_err()
+    );
+}
+
+#[test]
+fn electrum_errors() {
+    let (tx, reftx) = p2wsh_txs();
+    let server = FakeElectrumServer::start(vec![], vec![reftx.txid()], None);
+    let electrum = ElectrumProvider::connect_tcp(server.addr).unwrap();
+
+    //An unknown transaction is not an error
+    assert_eq!(electrum.get_transaction(&tx.txid()).unwrap(), None);
+    assert_eq!(
+        electrum.prevout(&OutPoint::new(tx.txid(), 0)).unwrap(),
+        None
+    );
+
+    //A server failure is, not to be taken for a missing prevout
+    let err = electrum.get_transaction(&reftx.txid()).unwrap_err();
+    assert!(err.to_string().contains("Loading block index"), "{}", err);
+    let err = verify_tx_signers_from(&tx, &electrum).unwrap_err();
+    assert!(matches!(err, MinikeysError::Provider(_)), "{}", err);
+}
@@ -0,0 +1,126 @@
+use bitcoin::{OutPoint, Transaction};
+use minikeys::provider::{CachedProvider, EsploraProvider, Outspend};
+use minikeys::{verify_tx_signers_from, PrevoutProvider};
+use serde_json::json;
+
+mod common;
+use common::{p2wsh_txs, HttpRequest, MockHttpServer};
+
+//Checks the Esplora provider against a local stand-in for an Esplora
+//instance (electrs / mempool.space HTTP API).
+
+//run with: cargo test --test esplora_provider --features esplora
+
+/// Serves `/api/tx/:txid/hex` for `txs`, and `/api/tx/:txid/outspend/:vout`
+/// for the outputs they spend.
+fn esplora_server(txs: Vec<Transaction>) -> MockHttpServer {
+    MockHttpServer::start(move |req: &HttpRequest| {
+        let parts: Vec<&str> = req.path.trim_start_matches("/api/tx/").split('/').collect();
+        match parts.as_slice() {
+            [txid, "hex"] => match txs.iter().find(|tx| tx.txid().to_string() == *txid) {
+                Some(tx) => (
+                    200,
+                    "text/plain",
+                    bitcoin::consensus::encode::serialize_hex(tx).into_bytes(),
+                ),
+                None => (404, "text/plain", b"Transaction not found".to_vec()),
+            },
+            [txid, "outspend", vout] => {
+                let spender = txs.iter().find_map(|tx| {
+                    let vin = tx.input.iter().position(|txin| {
+                        txin.previous_output.txid.to_string() == *txid
+                            && txin.previous_output.vout.to_string() == *vout
+                    })?;
+                    Some((tx.txid(), vin))
+                });
+                let body = match spender {
+                    Some((txid, vin)) => json!({
+                        "spent": true,
+                        "txid": txid.to_string(),
+                        "vin": vin,
+                        "status": {"confirmed": true, "block_height": 700000},
+                    }),
+                    None => json!({"spent": false}),
+                };
+                (200, "application/json", serde_json::to_vec(&body).unwrap())
+            }
+            _ => (400, "text/plain", b"Invalid request".to_vec()),
+        }
+    })
+}
+
+#[test]
+fn esplora_prevouts() {
+    let (tx, reftx) = p2wsh_txs();
+    let server = esplora_server(vec![tx.clone(), reftx.clone()]);
+    let esplora = CachedProvider::new(EsploraProvider::new(&format!("{}/api/", server.url())));
+
+    let report = verify_tx_signers_from(&tx, &esplora).unwrap();
+    assert!(report.summary.unanalyzed.is_empty());
+    assert_eq!(report.inputs[0].verified_keys().count(), 2);
+
+    //Both outputs of one tx: a single request, then none once cached
+    let outpoints = [
+        OutPoint::new(reftx.txid(), 0),
+        OutPoint::new(reftx.txid(), 1),
+        OutPoint::new(reftx.txid(), 7),
+    ];
+    let before = server.requests().len();
+    let txouts = esplora.prevouts(&outpoints).unwrap();
+    assert_eq!(txouts[0].as_ref(), Some(&reftx.output[0]));
+    assert_eq!(txouts[1].as_ref(), Some(&reftx.output[1]));
+    assert_eq!(txouts[2], None);
+    esplora.prevouts(&outpoints[..2]).unwrap();
+    let requests = server.requests();
+    assert_eq!(requests.len() - before, 1);
+    assert!(requests.iter().all(|r| r.method == "GET"));
+}
+
+#[test]
+fn esplora_outspend() {
+    let (tx, reftx) = p2wsh_txs();
+    let server = esplora_server(vec![tx.clone(), reftx.clone()]);
+    let esplora = EsploraProvider::new(&format!("{}/api", server.url()));
+
+    //Find the spend of the funding output, then verify it
+    let funding = tx.input[0].previous_output;
+    assert_eq!(
+        esplora.get_outspend(&funding).unwrap(),
+        Some(Outspend {
+            txid: tx.txid(),
+            vin: 0
+        })
+    );
+    let (spending_tx, vin) = esplora.spending_tx(&funding).unwrap().unwrap();
+    assert_eq!((spending_tx.txid(), vin), (tx.txid(), 0));
+    let report = minikeys::verify_input_signers_from(&spending_tx, vin, &esplora).unwrap();
+    assert_eq!(report.verified_keys().count(), 2);
+
+    assert_eq!(
+        esplora.get_outspend(&OutPoint::new(tx.txid(), 0)).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn esplora_errors() {
+    let (tx, _) = p2wsh_txs();
+    //Funding tx unknown to the indexer: the input is left unanalyzed
+    let server = esplora_server(vec![tx.clone()]);
+    let esplora = EsploraProvider::new(&format!("{}/api", server.url()));
+    let report = verify_tx_signers_from(&tx, &esplora).unwrap();
+    assert!(report.inputs.is_empty());
+    assert!(matches!(
+        report.summary.unanalyzed[0].error,
+        minikeys::MinikeysError::MissingPrevout { .. }
+    ));
+
+    //Server errors are provider errors
+    let esplora = EsploraProvider::new(&server.url());
+    let err = esplora.prevout(&tx.input[0].previous_output).unwrap_err();
+    assert!(
+        matches!(err, minikeys::MinikeysError::Provider(_)),
+        "{}",
+        err
+    );
+}
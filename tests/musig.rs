@@ -0,0 +1,205 @@
+use std::str::FromStr;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::{KeyPair, Secp256k1, XOnlyPublicKey};
+use bitcoin::secp256k1::{PublicKey, Scalar, SecretKey};
+use minikeys::{
+    find_musig_commitment, musig_key_agg, musig_key_sort, verify_input_signers, KeyOrdering,
+    MusigAggregation, TaprootCommitment,
+};
+
+mod common;
+use common::{key_path_spend, merkle_root, output_key, secret};
+
+//Checks MuSig2 key aggregation against the BIP327 KeyAgg test vectors, and
+//the attribution of a key path spend to its MuSig2 participants on a spend
+//signed here with throwaway keys.
+
+//run with: cargo test --test musig
+
+fn pubkey(hex: &str) -> PublicKey {
+    PublicKey::from_str(hex).unwrap()
+}
+
+fn xonly_hex(hex: &str) -> XOnlyPublicKey {
+    XOnlyPublicKey::from_str(hex).unwrap()
+}
+
+/// key_agg_vectors.json of BIP327
+#[test]
+fn key_agg_vectors() {
+    let x = [
+        pubkey("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+        pubkey("03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659"),
+        pubkey("023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66"),
+    ];
+    let vectors = [
+        (
+            vec![x[0], x[1], x[2]],
+            "90539EEDE565F5D054F32CC0C220126889ED1E5D193BAF15AEF344FE59D4610C",
+        ),
+        (
+            vec![x[2], x[1], x[0]],
+            "6204DE8B083426DC6EAF9502D27024D53FC826BF7D2012148A0575435DF54B2B",
+        ),
+        (
+            vec![x[0], x[0], x[0]],
+            "B436E3BAD62B8CD409969A224731C193D051162D8C5AE8B109306127DA3AA935",
+        ),
+        (
+            vec![x[0], x[0], x[1], x[1]],
+            "69BC22BFA5D106306E48A20679DE1D7389386124D07571D0D872686028C26A3E",
+        ),
+    ];
+    for (keys, aggregate) in vectors {
+        assert_eq!(musig_key_agg(&keys), Some(xonly_hex(aggregate)));
+    }
+    assert_eq!(musig_key_agg(&[]), None);
+    assert_eq!(musig_key_sort(&x), [x[2], x[0], x[1]]);
+}
+
+fn participant(byte: u8) -> PublicKey {
+    secret(byte).public_key(&Secp256k1::new())
+}
+
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    for d in data {
+        engine.input(d);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// The secret of the aggregate of `secrets`' keys in that order, computed
+/// independently of minikeys: the sum of the secrets weighted by their
+/// KeyAgg coefficient. A MuSig2 session gives the same signatures without
+/// anyone knowing it.
+fn aggregate_secret(secrets: &[SecretKey]) -> SecretKey {
+    let secp = Secp256k1::new();
+    let keys: Vec<[u8; 33]> = secrets
+        .iter()
+        .map(|sk| sk.public_key(&secp).serialize())
+        .collect();
+    let list: Vec<&[u8]> = keys.iter().map(|k| &k[..]).collect();
+    let list_hash = tagged_hash("KeyAgg list", &list);
+    let second = keys.iter().find(|k| **k != keys[0]);
+    let mut aggregate: Option<SecretKey> = None;
+    for (sk, key) in secrets.iter().zip(&keys) {
+        let weighted = if Some(key) == second {
+            *sk
+        } else {
+            let coefficient = tagged_hash("KeyAgg coefficient", &[&list_hash, key]);
+            sk.mul_tweak(&Scalar::from_be_bytes(coefficient).unwrap())
+                .unwrap()
+        };
+        aggregate = Some(match aggregate {
+            Some(sum) => sum.add_tweak(&Scalar::from(weighted)).unwrap(),
+            None => weighted,
+        });
+    }
+    aggregate.unwrap()
+}
+
+#[test]
+fn find_musig() {
+    let participants = [participant(1), participant(2), participant(3)];
+    let sorted = musig_key_sort(&participants);
+    let unsorted = if sorted[0] == participants[2] {
+        [participants[0], participants[2], participants[1]]
+    } else {
+        [participants[2], participants[0], participants[1]]
+    };
+    assert_ne!(sorted, unsorted);
+    let roots = [merkle_root(4), merkle_root(5)];
+
+    // Sorted aggregate, no script tree
+    let aggregate = musig_key_agg(&sorted).unwrap();
+    let (musig, commitment) = find_musig_commitment(
+        &output_key(aggregate, None),
+        &participants,
+        &roots,
+        KeyOrdering::Sorted,
+    )
+    .unwrap();
+    assert_eq!(musig.participants, sorted);
+    assert!(musig.key_sorted);
+    assert_eq!(musig.aggregate_key(), Some(aggregate));
+    //Not from a search, e.g. deserialized
+    let empty = MusigAggregation {
+        participants: vec![],
+        key_sorted: true,
+    };
+    assert_eq!(empty.aggregate_key(), None);
+    let mut secrets = [secret(1), secret(2), secret(3)];
+    secrets.sort_by_key(|sk| sk.public_key(&Secp256k1::new()).serialize());
+    let (secret_key, _) = aggregate_secret(&secrets).x_only_public_key(&Secp256k1::new());
+    assert_eq!(secret_key, aggregate);
+    assert_eq!(commitment, TaprootCommitment::new(aggregate, None));
+
+    // Unsorted aggregate with a script tree, only found trying every order
+    let aggregate = musig_key_agg(&unsorted).unwrap();
+    let output = output_key(aggregate, Some(roots[1]));
+    assert_eq!(
+        find_musig_commitment(&output, &participants, &roots, KeyOrdering::Sorted),
+        None
+    );
+    let (musig, commitment) =
+        find_musig_commitment(&output, &participants, &roots, KeyOrdering::All).unwrap();
+    assert_eq!(musig.participants, unsorted);
+    assert!(!musig.key_sorted);
+    assert_eq!(
+        commitment,
+        TaprootCommitment::new(aggregate, Some(roots[1]))
+    );
+
+    // A subset of the participants is not their aggregate
+    let pair = musig_key_agg(&musig_key_sort(&participants[..2])).unwrap();
+    assert_eq!(
+        find_musig_commitment(
+            &output_key(pair, None),
+            &participants,
+            &[],
+            KeyOrdering::All
+        ),
+        None
+    );
+}
+
+#[test]
+fn musig_report() {
+    let participants = [participant(1), participant(2), participant(3)];
+    let mut secrets = [secret(1), secret(2), secret(3)];
+    secrets.sort_by_key(|sk| sk.public_key(&Secp256k1::new()).serialize());
+    let root = merkle_root(4);
+    let keypair = KeyPair::from_secret_key(&Secp256k1::new(), &aggregate_secret(&secrets));
+    let (tx, funding_tx) = key_path_spend(&keypair, Some(root));
+    let mut report = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    assert!(report.has_verified_signer());
+    assert_eq!(report.musig, None);
+
+    // Not a plain internal key
+    let xonly: Vec<_> = participants
+        .iter()
+        .map(|pk| pk.x_only_public_key().0)
+        .collect();
+    assert_eq!(report.attribute_key_path(&xonly, &[root]), None);
+    // Missing the tree
+    assert_eq!(
+        report.attribute_musig(&participants, &[], KeyOrdering::All),
+        None
+    );
+    let found = report.attribute_musig(&participants, &[root], KeyOrdering::Sorted);
+    let aggregate = musig_key_agg(&musig_key_sort(&participants)).unwrap();
+    assert_eq!(found, Some(TaprootCommitment::new(aggregate, Some(root))));
+    assert_eq!(report.taproot_commitment, found);
+    let musig = report.musig.as_ref().unwrap();
+    assert!(musig.key_sorted);
+    assert_eq!(musig.participants, musig_key_sort(&participants));
+
+    // Attributing to a plain key clears the participants
+    report.attribute_key_path(&xonly, &[]);
+    assert_eq!(report.musig, None);
+}
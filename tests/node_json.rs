@@ -0,0 +1,106 @@
+use minikeys::{parse_verbose, parse_verbose_block, parse_verbose_tx};
+
+//Verifies signers straight from bitcoin-cli verbosity 3 JSON, which carries
+//the prevout of every input, so no funding transaction is needed.
+
+//run with: cargo test --test node_json --features serde
+
+//bitcoin-cli getrawtransaction 2bb157363e7a62d70b92082a9b2c9bb6f329154f816b8d239bd58c35c789a96a 3
+//(the P2WSH 2of2 multisig spend of validkeys.rs, vout list elided)
+const GETRAWTRANSACTION_3: &str = r#"{
+  "txid": "2bb157363e7a62d70b92082a9b2c9bb6f329154f816b8d239bd58c35c789a96a",
+  "hash": "0726d619cae9212af8a557a8c062e4cb9d0479c80502420e0b8c74c63ccbc542",
+  "version": 1,
+  "locktime": 0,
+  "vin": [
+    {
+      "txid": "2845399a8cd7a52733f9f9d0e0b8b6c5d1c88aea4cee09f8d8fa762912b49e1b",
+      "vout": 0,
+      "scriptSig": {
+        "asm": "",
+        "hex": ""
+      },
+      "txinwitness": [
+        "",
+        "304402201c7563e876d67b5702aea5726cd202bf92d0b1dc52c4acd03435d6073e630bac022032b64b70d7fba0cb8be30b882ea06c5f8ec7288d113459dd5d3e294214e2c96201",
+        "3045022100f532f7e3b8fd01a0edc86de4870db4e04858964d0a609df81deb99d9581e6c2e02206d9e9b6ab661176be8194faded62f518cdc6ee74dba919e0f35d77cff81f38e501",
+        "52210289da5da9d3700156db2d01e6362491733f6c886971791deda74b4e9d707190b2210323c437f30384498be79df2990ce5a8de00844e768c0ccce914335b6c26adea7352ae"
+      ],
+      "prevout": {
+        "generated": false,
+        "value": 0.00968240,
+        "scriptPubKey": {
+          "asm": "0 781ada670a98cfb276c6d2a78bbf21eb8f3617f4c2288cb16f5ad8741b5d83dd",
+          "hex": "0020781ada670a98cfb276c6d2a78bbf21eb8f3617f4c2288cb16f5ad8741b5d83dd",
+          "type": "witness_v0_scripthash"
+        }
+      },
+      "sequence": 4294967295
+    }
+  ],
+  "vout": [],
+  "hex": "010000000001011b9eb4122976fad8f809ee4cea8ac8d1c5b6b8e0d0f9f93327a5d78c9a3945280000000000ffffffff02ba3e0d00000000002200201c3b09401aaa7c9709d118a75d301bdb2180fb68b2e9b3ade8ad4ff7281780cfa586010000000000220020a41d0d894799879ca1bd88c1c3f1c2fd4b1592821cc3c5bfd5be5238b904b09f040047304402201c7563e876d67b5702aea5726cd202bf92d0b1dc52c4acd03435d6073e630bac022032b64b70d7fba0cb8be30b882ea06c5f8ec7288d113459dd5d3e294214e2c96201483045022100f532f7e3b8fd01a0edc86de4870db4e04858964d0a609df81deb99d9581e6c2e02206d9e9b6ab661176be8194faded62f518cdc6ee74dba919e0f35d77cff81f38e5014752210289da5da9d3700156db2d01e6362491733f6c886971791deda74b4e9d707190b2210323c437f30384498be79df2990ce5a8de00844e768c0ccce914335b6c26adea7352ae00000000"
+}"#;
+
+#[test]
+fn getrawtransaction_3() {
+    let parsed = parse_verbose_tx(GETRAWTRANSACTION_3).unwrap();
+    assert!(parsed.missing_prevouts().is_empty());
+    let spent = parsed
+        .prevouts
+        .get(&parsed.tx.input[0].previous_output)
+        .unwrap();
+    assert_eq!(spent.value, 968240);
+
+    let report = minikeys::verify_tx_signers_with(&parsed.tx, &parsed.prevouts);
+    assert!(report.summary.unanalyzed.is_empty());
+    assert_eq!(report.inputs[0].verified_keys().count(), 2);
+    println!("{}: {} signers", report.txid, report.summary.signers.len());
+
+    //The same JSON with one prevout stripped, as from verbosity 1 or 2
+    let stripped = GETRAWTRANSACTION_3.replace("\"prevout\"", "\"prevout_\"");
+    let parsed = parse_verbose_tx(&stripped).unwrap();
+    assert_eq!(
+        parsed.missing_prevouts(),
+        vec![parsed.tx.input[0].previous_output]
+    );
+
+    //hex and txid must agree
+    let tampered = GETRAWTRANSACTION_3.replace("\"txid\": \"2bb1", "\"txid\": \"2bb2");
+    assert!(matches!(
+        parse_verbose_tx(&tampered),
+        Err(minikeys::MinikeysError::NodeJson(_))
+    ));
+}
+
+#[test]
+fn getblock_3() {
+    //A block (other fields elided) made of a coinbase, without prevout, and the tx above
+    let block = format!(r#"{{"tx": [{}, {}]}}"#, COINBASE_3, GETRAWTRANSACTION_3);
+    let txs = parse_verbose_block(&block).unwrap();
+    assert_eq!(txs.len(), 2);
+    assert!(txs[0].tx.is_coin_base());
+    assert!(txs[0].missing_prevouts().is_empty());
+    assert_eq!(parse_verbose(&block).unwrap(), txs);
+    assert_eq!(parse_verbose(GETRAWTRANSACTION_3).unwrap().len(), 1);
+
+    for parsed in &txs {
+        let report = minikeys::verify_tx_signers_with(&parsed.tx, &parsed.prevouts);
+        assert_eq!(report.coinbase, parsed.tx.is_coin_base());
+        assert!(report.summary.unanalyzed.is_empty());
+    }
+}
+
+const COINBASE_3: &str = r#"{
+  "txid": "a3d4301d10d3ce1e6fc32e1af2d9901a6cd66c6c81e88d07fabded81a456391a",
+  "hash": "a3d4301d10d3ce1e6fc32e1af2d9901a6cd66c6c81e88d07fabded81a456391a",
+  "version": 1,
+  "vin": [
+    {
+      "coinbase": "03a0bb0d",
+      "sequence": 4294967295
+    }
+  ],
+  "vout": [],
+  "hex": "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0403a0bb0dffffffff0140be40250000000016001477800cff52bd58133b895622fd1220d9e2b47a7900000000"
+}"#;
@@ -0,0 +1,193 @@
+use std::str::FromStr;
+
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{PublicKey, ScriptBuf, Sequence, Transaction, Witness};
+use minikeys::verify_input_signers;
+use miniscript::{Miniscript, Segwitv0};
+
+mod common;
+use common::{secret, unsigned_spend};
+
+//Checks the semantic policy report and the satisfied branch, on spends of a
+//"2-of-3, or a recovery key after 52560 blocks" P2WSH output and of a "2 of
+//two keys and 52560 blocks" one, signed here with throwaway keys.
+
+//run with: cargo test --test policy
+
+const RECOVERY_DELAY: u32 = 52560;
+
+fn key(byte: u8) -> PublicKey {
+    PublicKey::new(secret(byte).public_key(&Secp256k1::new()))
+}
+
+/// Keys 1, 2 and 3 2-of-3, or key 4 after `RECOVERY_DELAY` blocks.
+fn witness_script() -> ScriptBuf {
+    let ms = Miniscript::<PublicKey, Segwitv0>::from_str(&format!(
+        "or_d(multi(2,{},{},{}),and_v(v:pk({}),older({})))",
+        key(1),
+        key(2),
+        key(3),
+        key(4),
+        RECOVERY_DELAY
+    ))
+    .unwrap();
+    ms.encode()
+}
+
+/// 2 of key 1, key 2 and `RECOVERY_DELAY` blocks.
+fn keys_or_delay_script() -> ScriptBuf {
+    let ms = Miniscript::<PublicKey, Segwitv0>::from_str(&format!(
+        "thresh(2,pk({}),s:pk({}),sln:older({}))",
+        key(1),
+        key(2),
+        RECOVERY_DELAY
+    ))
+    .unwrap();
+    ms.encode()
+}
+
+/// A transaction spending output 0 of `funding_tx`, locked by `script`,
+/// with input sequence `sequence`, and its funding transaction. The witness
+/// is left to `witness`, given the signatures of `signers` in that order.
+fn spend(
+    script: ScriptBuf,
+    sequence: Sequence,
+    signers: &[u8],
+    witness: impl Fn(Vec<Vec<u8>>) -> Vec<Vec<u8>>,
+) -> (Transaction, Transaction) {
+    let secp = Secp256k1::new();
+    let script_pubkey = ScriptBuf::new_v0_p2wsh(&script.wscript_hash());
+    let (mut tx, funding_tx) = unsigned_spend(script_pubkey, 7);
+    tx.input[0].sequence = sequence;
+    let sighash = SighashCache::new(&tx)
+        .segwit_signature_hash(0, &script, 50_000, EcdsaSighashType::All)
+        .unwrap();
+    let msg = Message::from_slice(&sighash[..]).unwrap();
+    let sigs = signers
+        .iter()
+        .map(|byte| {
+            bitcoin::ecdsa::Signature {
+                sig: secp.sign_ecdsa(&msg, &secret(*byte)),
+                hash_ty: EcdsaSighashType::All,
+            }
+            .to_vec()
+        })
+        .collect();
+    let mut elements = witness(sigs);
+    elements.push(script.into_bytes());
+    tx.input[0].witness = Witness::from_slice(&elements);
+    (tx, funding_tx)
+}
+
+#[test]
+fn primary_branch() {
+    // OP_CHECKMULTISIG dummy, then the signatures of keys 1 and 3
+    let (tx, funding_tx) = spend(
+        witness_script(),
+        Sequence::ENABLE_RBF_NO_LOCKTIME,
+        &[1, 3],
+        |sigs| [vec![vec![]], sigs].concat(),
+    );
+    let report = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    assert_eq!(report.verified_keys().count(), 2);
+    let policy = report.policy.unwrap();
+    assert!(policy.policy.starts_with("or("));
+    assert!(policy
+        .policy
+        .contains(&format!("older({})", RECOVERY_DELAY)));
+    assert!(policy.policy.contains("thresh(2,"));
+    let satisfied = policy.satisfied.unwrap();
+    assert!(satisfied.starts_with("and("));
+    assert!(satisfied.contains(&format!("pk({})", key(1))));
+    assert!(satisfied.contains(&format!("pk({})", key(3))));
+    assert!(!satisfied.contains(&format!("pk({})", key(2))));
+    let description = policy.description.unwrap();
+    assert!(description.starts_with("2-of-3 keys: "));
+    assert!(description.contains(&key(1).to_string()));
+    assert!(description.contains(&key(3).to_string()));
+    assert!(policy.branch.is_some());
+}
+
+#[test]
+fn recovery_branch() {
+    // The signature of key 4 under the dissatisfied 2-of-3
+    let (tx, funding_tx) = spend(
+        witness_script(),
+        Sequence::from_consensus(RECOVERY_DELAY),
+        &[4],
+        |sigs| [sigs, vec![vec![], vec![], vec![]]].concat(),
+    );
+    let report = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    let policy = report.policy.unwrap();
+    let satisfied = policy.satisfied.unwrap();
+    assert!(satisfied.starts_with("and("));
+    assert!(satisfied.contains(&format!("pk({})", key(4))));
+    assert!(satisfied.contains(&format!("older({})", RECOVERY_DELAY)));
+    let description = policy.description.unwrap();
+    assert!(description.contains(&format!("key {}", key(4))));
+    assert!(description.contains(&format!("after {} blocks", RECOVERY_DELAY)));
+    assert!(description.contains(" and "));
+
+    // The other alternative than the primary branch
+    let (tx, funding_tx) = spend(
+        witness_script(),
+        Sequence::ENABLE_RBF_NO_LOCKTIME,
+        &[1, 2],
+        |sigs| [vec![vec![]], sigs].concat(),
+    );
+    let primary = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    assert_ne!(policy.branch, primary.policy.unwrap().branch);
+}
+
+#[test]
+fn primary_branch_after_delay() {
+    // The 2-of-3 with a sequence meeting the recovery delay as well
+    let (tx, funding_tx) = spend(
+        witness_script(),
+        Sequence::from_consensus(RECOVERY_DELAY),
+        &[1, 3],
+        |sigs| [vec![vec![]], sigs].concat(),
+    );
+    let report = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    let policy = report.policy.unwrap();
+    let satisfied = policy.satisfied.unwrap();
+    assert!(satisfied.starts_with("and("));
+    assert!(!satisfied.contains("older("));
+    assert!(!satisfied.contains(&format!("pk({})", key(4))));
+    let description = policy.description.unwrap();
+    assert!(description.starts_with("2-of-3 keys: "));
+    assert!(!description.contains("blocks"));
+
+    let (tx, funding_tx) = spend(
+        witness_script(),
+        Sequence::ENABLE_RBF_NO_LOCKTIME,
+        &[1, 3],
+        |sigs| [vec![vec![]], sigs].concat(),
+    );
+    let primary = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    assert_eq!(policy.branch, primary.policy.unwrap().branch);
+}
+
+#[test]
+fn signatures_over_timelock() {
+    // Both signatures, the timelock dissatisfied (IF branch pushing 0) but
+    // met by the sequence anyway
+    let (tx, funding_tx) = spend(
+        keys_or_delay_script(),
+        Sequence::from_consensus(RECOVERY_DELAY),
+        &[1, 2],
+        |sigs| vec![vec![1], sigs[1].clone(), sigs[0].clone()],
+    );
+    let report = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    assert_eq!(report.verified_keys().count(), 2);
+    let policy = report.policy.unwrap();
+    assert!(policy.policy.starts_with("thresh(2,"));
+    assert_eq!(policy.branch, None);
+    let satisfied = policy.satisfied.unwrap();
+    assert_eq!(satisfied, format!("and(pk({}),pk({}))", key(1), key(2)));
+    let description = policy.description.unwrap();
+    assert!(description.contains(&format!("key {}", key(1))));
+    assert!(description.contains(&format!("key {}", key(2))));
+    assert!(!description.contains("blocks"));
+}
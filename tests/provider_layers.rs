@@ -0,0 +1,179 @@
+use bitcoin::hashes::Hash;
+use bitcoin::{OutPoint, ScriptBuf, TxOut, Txid};
+use minikeys::provider::{CachedProvider, FallbackProvider};
+use minikeys::{MinikeysError, PrevoutMap, PrevoutProvider};
+
+//Checks the providers wrapping other providers, and that they turn a
+//wrapped provider answering for too few or too many outpoints into an error.
+
+//run with: cargo test --test provider_layers [--features async]
+
+fn outpoint(i: u8) -> OutPoint {
+    OutPoint::new(Txid::from_byte_array([i; 32]), u32::from(i))
+}
+
+fn txout(i: u8) -> TxOut {
+    TxOut {
+        value: 1000 * u64::from(i),
+        script_pubkey: ScriptBuf::from(vec![0x51; usize::from(i)]),
+    }
+}
+
+fn map(range: std::ops::Range<u8>) -> PrevoutMap {
+    let mut map = PrevoutMap::new();
+    for i in range {
+        map.insert(outpoint(i), txout(i));
+    }
+    map
+}
+
+/// Answers every batch with `extra` more outputs than asked for, or fewer
+/// when negative.
+struct MiscountingProvider {
+    extra: isize,
+}
+
+impl MiscountingProvider {
+    fn answers(&self, outpoints: &[OutPoint]) -> Vec<Option<TxOut>> {
+        let len = outpoints.len().saturating_add_signed(self.extra);
+        vec![Some(txout(9)); len]
+    }
+}
+
+impl PrevoutProvider for MiscountingProvider {
+    fn prevout(&self, _outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        Ok(Some(txout(9)))
+    }
+
+    fn prevouts(&self, outpoints: &[OutPoint]) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+        Ok(self.answers(outpoints))
+    }
+}
+
+fn is_provider_error<T>(result: Result<T, MinikeysError>) -> bool {
+    matches!(result, Err(MinikeysError::Provider(_)))
+}
+
+#[test]
+fn cached() {
+    let cache = CachedProvider::new(map(0..3));
+    let outpoints: Vec<OutPoint> = (0..4).map(outpoint).collect();
+    let txouts = cache.prevouts(&outpoints).unwrap();
+    assert_eq!(
+        txouts,
+        vec![Some(txout(0)), Some(txout(1)), Some(txout(2)), None]
+    );
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.prevout(&outpoint(1)).unwrap(), Some(txout(1)));
+    assert_eq!(cache.prevout(&outpoint(3)).unwrap(), None);
+
+    for extra in [-1, 1] {
+        let cache = CachedProvider::new(MiscountingProvider { extra });
+        assert!(is_provider_error(cache.prevout(&outpoint(1))));
+        assert!(is_provider_error(cache.prevouts(&outpoints)));
+        assert!(cache.is_empty());
+    }
+}
+
+#[test]
+fn fallback() {
+    let outpoints: Vec<OutPoint> = (0..4).map(outpoint).collect();
+    let provider = FallbackProvider::new(map(0..2), map(1..3));
+    assert_eq!(
+        provider.prevouts(&outpoints).unwrap(),
+        vec![Some(txout(0)), Some(txout(1)), Some(txout(2)), None]
+    );
+
+    for extra in [-1, 1] {
+        // A miscounting primary is a failed one, the fallback answers
+        let provider = FallbackProvider::new(MiscountingProvider { extra }, map(0..4));
+        assert_eq!(provider.prevout(&outpoint(2)).unwrap(), Some(txout(2)));
+        let txouts: Vec<_> = (0..4).map(|i| Some(txout(i))).collect();
+        assert_eq!(provider.prevouts(&outpoints).unwrap(), txouts);
+        // Unless it does not know everything either
+        let provider = FallbackProvider::new(MiscountingProvider { extra }, map(0..2));
+        assert!(is_provider_error(provider.prevouts(&outpoints)));
+        assert!(is_provider_error(provider.prevout(&outpoint(3))));
+
+        let provider = FallbackProvider::new(map(0..2), MiscountingProvider { extra });
+        assert!(is_provider_error(provider.prevouts(&outpoints)));
+        assert!(is_provider_error(provider.prevout(&outpoint(3))));
+        assert_eq!(provider.prevout(&outpoint(1)).unwrap(), Some(txout(1)));
+    }
+}
+
+#[cfg(feature = "async")]
+mod async_layers {
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    use minikeys::AsyncPrevoutProvider;
+
+    use super::*;
+
+    #[async_trait::async_trait]
+    impl AsyncPrevoutProvider for MiscountingProvider {
+        async fn prevout(&self, _outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+            Ok(Some(txout(9)))
+        }
+
+        async fn prevouts(
+            &self,
+            outpoints: &[OutPoint],
+        ) -> Result<Vec<Option<TxOut>>, MinikeysError> {
+            Ok(self.answers(outpoints))
+        }
+    }
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Runs a future that never waits, as those of in-memory providers.
+    fn ready<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut future = std::pin::pin!(future);
+        match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("in-memory provider future is pending"),
+        }
+    }
+
+    #[test]
+    fn cached_async() {
+        let cache = CachedProvider::new(map(0..3));
+        let outpoints: Vec<OutPoint> = (0..4).map(outpoint).collect();
+        let txouts = ready(AsyncPrevoutProvider::prevouts(&cache, &outpoints)).unwrap();
+        assert_eq!(
+            txouts,
+            vec![Some(txout(0)), Some(txout(1)), Some(txout(2)), None]
+        );
+        assert_eq!(cache.len(), 3);
+
+        for extra in [-1, 1] {
+            let cache = CachedProvider::new(MiscountingProvider { extra });
+            let answer = ready(AsyncPrevoutProvider::prevout(&cache, &outpoint(1)));
+            assert!(is_provider_error(answer));
+            assert!(cache.is_empty());
+        }
+    }
+
+    #[test]
+    fn fallback_async() {
+        let outpoints: Vec<OutPoint> = (0..4).map(outpoint).collect();
+        for extra in [-1, 1] {
+            let provider = FallbackProvider::new(MiscountingProvider { extra }, map(0..4));
+            let answer = ready(AsyncPrevoutProvider::prevout(&provider, &outpoint(2)));
+            assert_eq!(answer.unwrap(), Some(txout(2)));
+
+            let provider = FallbackProvider::new(map(0..2), MiscountingProvider { extra });
+            let txouts = ready(AsyncPrevoutProvider::prevouts(&provider, &outpoints));
+            assert!(is_provider_error(txouts));
+            let answer = ready(AsyncPrevoutProvider::prevout(&provider, &outpoint(3)));
+            assert!(is_provider_error(answer));
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use bitcoin::hashes::Hash;
+use bitcoin::key::Secp256k1;
+use bitcoin::opcodes::OP_TRUE;
+use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::taproot::{LeafVersion, TaprootBuilder};
+use bitcoin::{Amount, OutPoint, ScriptBuf, TxIn, Txid, Witness};
+use minikeys::{derive_script_pubkey, verify_input_signers_with, MinikeysError, PrevoutMap};
+
+mod common;
+use common::{p2ms_txs, p2sh_txs, p2tr_txs, p2wsh_txs};
+
+//Checks spent outputs given as scriptPubKey and amount, or as amount alone
+//with the scriptPubKey derived from the input, instead of funding
+//transactions, and the errors for missing or wrong funding transactions.
+
+//run with: cargo test --test spent_outputs
+
+#[test]
+fn derives_script_pubkey() {
+    for (tx, reftx) in [p2sh_txs(), p2wsh_txs(), p2tr_txs()] {
+        let spent = PrevoutMap::from_txs([&reftx])
+            .spent_output(&tx, 0)
+            .unwrap()
+            .clone();
+        assert_eq!(
+            derive_script_pubkey(&tx.input[0]),
+            Some(spent.script_pubkey)
+        );
+    }
+    //The funding tx of the P2WSH spend itself spends a P2WPKH output
+    let (_, reftx) = p2wsh_txs();
+    let derived = derive_script_pubkey(&reftx.input[0]).unwrap();
+    assert!(derived.is_v0_p2wpkh());
+    //A single leaf script path spend has a 33-byte control block, like the
+    //key of a P2WPKH spend
+    let (txin, script_pubkey) = single_leaf_spend();
+    assert_eq!(txin.witness.len(), 2);
+    assert_eq!(txin.witness.last().unwrap().len(), 33);
+    assert_eq!(derive_script_pubkey(&txin), Some(script_pubkey.clone()));
+    //An annex is ignored, so a key path spend with one reveals nothing
+    let annex = vec![0x50, 1, 2, 3];
+    let mut with_annex = txin.clone();
+    with_annex.witness.push(&annex);
+    assert_eq!(derive_script_pubkey(&with_annex), Some(script_pubkey));
+    let key_path = TxIn {
+        witness: Witness::from_slice(&[vec![1; 64], annex]),
+        ..txin
+    };
+    assert_eq!(derive_script_pubkey(&key_path), None);
+    //A bare multisig spend only reveals signatures
+    let (tx, _) = p2ms_txs();
+    assert_eq!(derive_script_pubkey(&tx.input[0]), None);
+}
+
+#[test]
+fn amount_only() {
+    let (tx, reftx) = p2wsh_txs();
+    let mut prevout_map = PrevoutMap::new();
+    let amount = Amount::from_sat(reftx.output[0].value);
+    prevout_map.insert_amount(&tx, 0, amount).unwrap();
+    assert_eq!(prevout_map.spent_output(&tx, 0).unwrap(), &reftx.output[0]);
+    let report = verify_input_signers_with(&tx, 0, &prevout_map).unwrap();
+    assert_eq!(report.verified_keys().count(), 2);
+
+    let (tx, _) = p2ms_txs();
+    let err = PrevoutMap::new().insert_amount(&tx, 0, amount).unwrap_err();
+    assert_eq!(
+        err,
+        MinikeysError::UnderivableScriptPubKey { input_index: 0 }
+    );
+    let err = PrevoutMap::new().insert_amount(&tx, 1, amount).unwrap_err();
+    assert!(matches!(err, MinikeysError::InputIndexOutOfRange { .. }));
+}
+
+#[test]
+fn script_and_amount() {
+    let (tx, reftx) = p2ms_txs();
+    let spent = PrevoutMap::from_txs([&reftx])
+        .spent_output(&tx, 0)
+        .unwrap()
+        .clone();
+    let mut prevout_map = PrevoutMap::new();
+    prevout_map.insert_spent(&tx, 0, spent).unwrap();
+    let report = verify_input_signers_with(&tx, 0, &prevout_map).unwrap();
+    assert_eq!(report.verified_keys().count(), 2);
+}
+
+#[test]
+fn wrong_funding_tx() {
+    let (tx, reftx) = p2wsh_txs();
+    let (_, wrong_reftx) = p2sh_txs();
+    let missing = MinikeysError::MissingPrevout {
+        input_index: 0,
+        outpoint: tx.input[0].previous_output,
+    };
+    let err = PrevoutMap::new().spent_output(&tx, 0).unwrap_err();
+    assert_eq!(err, missing);
+    //A loose funding transaction is not paired with any input
+    let err = PrevoutMap::from_txs([&wrong_reftx])
+        .spent_output(&tx, 0)
+        .unwrap_err();
+    assert_eq!(err, missing);
+    //A PSBT input's non_witness_utxo is
+    let mut unsigned_tx = tx.clone();
+    unsigned_tx.input[0].witness = Witness::new();
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).unwrap();
+    psbt.inputs[0].non_witness_utxo = Some(wrong_reftx.clone());
+    let err = PrevoutMap::from_psbt(&psbt)
+        .spent_output(&tx, 0)
+        .unwrap_err();
+    assert_eq!(
+        err,
+        MinikeysError::PrevoutTxidMismatch {
+            input_index: 0,
+            expected: reftx.txid(),
+            found: wrong_reftx.txid(),
+        }
+    );
+    psbt.inputs[0].non_witness_utxo = Some(reftx.clone());
+    let spent = PrevoutMap::from_psbt(&psbt).spent_output(&tx, 0).cloned();
+    assert_eq!(spent, Ok(reftx.output[0].clone()));
+    //Funding transactions of other inputs are not mistaken for wrong ones
+    let (p2ms_tx, p2ms_reftx) = p2ms_txs();
+    let mut two_inputs = tx.clone();
+    two_inputs.input.push(p2ms_tx.input[0].clone());
+    let err = PrevoutMap::from_txs([&p2ms_reftx])
+        .spent_output(&two_inputs, 0)
+        .unwrap_err();
+    assert!(matches!(err, MinikeysError::MissingPrevout { .. }));
+    let spent = PrevoutMap::from_txs([&p2ms_reftx, &reftx])
+        .spent_outputs(&two_inputs)
+        .unwrap();
+    assert_eq!(
+        spent,
+        [reftx.output[0].clone(), p2ms_reftx.output[0].clone()]
+    );
+}
+
+//A script path spend of an OP_TRUE leaf, alone in the tree of a throwaway
+//internal key, and the spent scriptPubKey
+fn single_leaf_spend() -> (TxIn, ScriptBuf) {
+    let secp = Secp256k1::new();
+    let internal_key = SecretKey::from_slice(&[1; 32])
+        .unwrap()
+        .x_only_public_key(&secp)
+        .0;
+    let leaf = ScriptBuf::builder().push_opcode(OP_TRUE).into_script();
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(0, leaf.clone())
+        .unwrap()
+        .finalize(&secp, internal_key)
+        .unwrap();
+    let control_block = spend_info
+        .control_block(&(leaf.clone(), LeafVersion::TapScript))
+        .unwrap();
+    let txin = TxIn {
+        previous_output: OutPoint::new(Txid::from_byte_array([7; 32]), 0),
+        witness: Witness::from_slice(&[leaf.into_bytes(), control_block.serialize()]),
+        ..TxIn::default()
+    };
+    let script_pubkey = ScriptBuf::new_v1_p2tr(&secp, internal_key, spend_info.merkle_root());
+    (txin, script_pubkey)
+}
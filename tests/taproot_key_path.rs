@@ -0,0 +1,158 @@
+use std::cell::RefCell;
+
+use bitcoin::hashes::Hash;
+use bitcoin::key::{Secp256k1, TapTweak};
+use bitcoin::secp256k1::Message;
+use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoin::{OutPoint, TxIn, TxOut, Txid, Witness};
+use minikeys::provider::resolve_prevouts;
+use minikeys::{
+    find_taproot_commitment, verify_input_signers, verify_input_signers_with, MinikeysError,
+    PrevoutMap, PrevoutProvider, SignerKey, SpendType, TaprootCommitment,
+};
+
+mod common;
+use common::{key_path_spend, keypair, merkle_root, output_key, xonly};
+
+//Checks the attribution of taproot key path spends to an internal key and
+//script tree, on a spend signed here with throwaway keys.
+
+//run with: cargo test --test taproot_key_path
+
+#[test]
+fn find_commitment() {
+    let internal_keys = [xonly(1), xonly(2)];
+    let roots = [merkle_root(3), merkle_root(4)];
+
+    // BIP86 output, no script tree
+    let found = find_taproot_commitment(&output_key(xonly(2), None), &internal_keys, &roots);
+    assert_eq!(found, Some(TaprootCommitment::new(xonly(2), None)));
+
+    let found = find_taproot_commitment(
+        &output_key(xonly(1), Some(roots[1])),
+        &internal_keys,
+        &roots,
+    );
+    assert_eq!(
+        found,
+        Some(TaprootCommitment::new(xonly(1), Some(roots[1])))
+    );
+    assert_eq!(
+        found.unwrap().output_key(),
+        output_key(xonly(1), Some(roots[1]))
+    );
+
+    // Unknown tree, unknown internal key
+    let unknown_root = output_key(xonly(1), Some(merkle_root(5)));
+    assert_eq!(
+        find_taproot_commitment(&unknown_root, &internal_keys, &roots),
+        None
+    );
+    assert_eq!(
+        find_taproot_commitment(&output_key(xonly(5), None), &internal_keys, &roots),
+        None
+    );
+    // The untweaked internal key is not its own output key
+    assert_eq!(
+        find_taproot_commitment(&xonly(1), &internal_keys, &[]),
+        None
+    );
+}
+
+#[test]
+fn key_path_report() {
+    let root = merkle_root(3);
+    let (tx, funding_tx) = key_path_spend(&keypair(1), Some(root));
+    let mut report = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    assert_eq!(report.spend_type, SpendType::TaprootKeyPath);
+    assert_eq!(
+        report.key_path_signer(),
+        Some(output_key(xonly(1), Some(root)))
+    );
+    assert_eq!(
+        report.script_keys,
+        [SignerKey::XOnly(output_key(xonly(1), Some(root)))]
+    );
+    assert_eq!(report.taproot_commitment, None);
+    assert_eq!(report.control_block, None);
+
+    // Right key, tree not among the candidates
+    assert_eq!(report.attribute_key_path(&[xonly(1), xonly(2)], &[]), None);
+    let found = report.attribute_key_path(&[xonly(2), xonly(1)], &[merkle_root(4), root]);
+    assert_eq!(found, Some(TaprootCommitment::new(xonly(1), Some(root))));
+    assert_eq!(report.taproot_commitment, found);
+
+    // A signature under another key does not verify, so nothing is attributed
+    let (mut forged, funding_tx) = key_path_spend(&keypair(1), None);
+    let (other, _) = key_path_spend(&keypair(2), None);
+    forged.input[0].witness = other.input[0].witness.clone();
+    let mut report = verify_input_signers(&forged, 0, &[funding_tx]).unwrap();
+    assert!(!report.has_verified_signer());
+    assert_eq!(report.key_path_signer(), None);
+    assert_eq!(report.attribute_key_path(&[xonly(1)], &[]), None);
+}
+
+/// Answers from a map, recording the outpoints asked for.
+struct RecordingProvider {
+    map: PrevoutMap,
+    asked: RefCell<Vec<OutPoint>>,
+}
+
+impl PrevoutProvider for RecordingProvider {
+    fn prevout(&self, outpoint: &OutPoint) -> Result<Option<TxOut>, MinikeysError> {
+        self.asked.borrow_mut().push(*outpoint);
+        self.map.prevout(outpoint)
+    }
+}
+
+#[test]
+fn anyonecanpay_prevouts() {
+    // Input 1 is a key path spend of xonly(1), input 0 spends an output
+    // nobody knows
+    let secp = Secp256k1::new();
+    let (mut tx, funding_tx) = key_path_spend(&keypair(1), None);
+    let unknown = OutPoint::new(Txid::from_byte_array([8; 32]), 0);
+    tx.input.insert(
+        0,
+        TxIn {
+            previous_output: unknown,
+            ..TxIn::default()
+        },
+    );
+    let hash_ty = TapSighashType::AllPlusAnyoneCanPay;
+    let sighash = SighashCache::new(&tx)
+        .taproot_key_spend_signature_hash(1, &Prevouts::One(1, &funding_tx.output[0]), hash_ty)
+        .unwrap();
+    let msg = Message::from_slice(&sighash[..]).unwrap();
+    let tweaked = keypair(1).tap_tweak(&secp, None).to_inner();
+    let sig = bitcoin::taproot::Signature {
+        sig: secp.sign_schnorr_no_aux_rand(&msg, &tweaked),
+        hash_ty,
+    };
+    tx.input[1].witness = Witness::from_slice(&[sig.to_vec()]);
+    let provider = RecordingProvider {
+        map: PrevoutMap::from_txs([&funding_tx]),
+        asked: RefCell::new(vec![]),
+    };
+
+    // Its own spent output is all a SIGHASH_ANYONECANPAY signature needs
+    let prevout_map = resolve_prevouts(&provider, &tx, &[1]).unwrap();
+    assert_eq!(*provider.asked.borrow(), [tx.input[1].previous_output]);
+    let report = verify_input_signers_with(&tx, 1, &prevout_map).unwrap();
+    assert!(report.has_verified_signer());
+    assert_eq!(report.key_path_signer(), Some(output_key(xonly(1), None)));
+
+    // A SIGHASH_DEFAULT signature commits to every spent output
+    tx.input[1].witness = Witness::from_slice(&[sig.sig.as_ref()]);
+    provider.asked.borrow_mut().clear();
+    let prevout_map = resolve_prevouts(&provider, &tx, &[1]).unwrap();
+    assert_eq!(
+        *provider.asked.borrow(),
+        [tx.input[1].previous_output, unknown]
+    );
+    let err = verify_input_signers_with(&tx, 1, &prevout_map).unwrap_err();
+    assert!(matches!(
+        err,
+        MinikeysError::MissingPrevout { input_index: 0, .. }
+    ));
+}
@@ -0,0 +1,128 @@
+use bitcoin::key::{Secp256k1, XOnlyPublicKey};
+use bitcoin::secp256k1::Parity;
+use bitcoin::taproot::{LeafVersion, TapLeafHash, TaprootBuilder, TaprootSpendInfo};
+use minikeys::{
+    is_nums_key, verify_input_signers, ControlBlockReport, SignerKey, SpendType, NUMS_INTERNAL_KEY,
+};
+
+mod common;
+use common::{leaf, leaf_version_spend, script_path_spend, xonly};
+
+//Checks the control block reports of taproot script path spends, on a tree
+//and spends built here with throwaway keys.
+
+//run with: cargo test --test taproot_script_path
+
+fn nums_key() -> XOnlyPublicKey {
+    XOnlyPublicKey::from_slice(&NUMS_INTERNAL_KEY).unwrap()
+}
+
+/// Leaf 3 at depth 1, leaves 4 and 5 at depth 2.
+fn tree(internal_key: XOnlyPublicKey) -> TaprootSpendInfo {
+    TaprootBuilder::new()
+        .add_leaf(1, leaf(3))
+        .unwrap()
+        .add_leaf(2, leaf(4))
+        .unwrap()
+        .add_leaf(2, leaf(5))
+        .unwrap()
+        .finalize(&Secp256k1::verification_only(), internal_key)
+        .unwrap()
+}
+
+#[test]
+fn control_block_report() {
+    for internal_key in [xonly(1), nums_key()] {
+        let spend_info = tree(internal_key);
+        let output_key = spend_info.output_key().to_inner();
+        for (byte, depth) in [(3, 1), (4, 2), (5, 2)] {
+            let script = leaf(byte);
+            let control_block = spend_info
+                .control_block(&(script.clone(), LeafVersion::TapScript))
+                .unwrap();
+            let report = ControlBlockReport::new(&control_block, &script, output_key);
+            assert_eq!(report.internal_key, internal_key);
+            assert_eq!(report.nums_internal_key, internal_key == nums_key());
+            assert_eq!(report.output_key_parity, spend_info.output_key_parity());
+            assert_eq!(report.leaf_version, LeafVersion::TapScript);
+            assert_eq!(
+                report.leaf_hash,
+                TapLeafHash::from_script(&script, LeafVersion::TapScript)
+            );
+            assert_eq!(report.depth(), depth);
+            assert_eq!(Some(report.merkle_root), spend_info.merkle_root());
+            assert_eq!(report.output_key, output_key);
+            assert!(report.commitment_verified);
+            assert_eq!(report.commitment().output_key(), output_key);
+
+            // Another output, or the other parity, is not committed to
+            let other = tree(xonly(2)).output_key().to_inner();
+            assert!(!ControlBlockReport::new(&control_block, &script, other).commitment_verified);
+            let mut flipped = control_block.clone();
+            flipped.output_key_parity = match flipped.output_key_parity {
+                Parity::Even => Parity::Odd,
+                Parity::Odd => Parity::Even,
+            };
+            assert!(!ControlBlockReport::new(&flipped, &script, output_key).commitment_verified);
+            // Nor is a leaf not in the tree
+            assert!(
+                !ControlBlockReport::new(&control_block, &leaf(6), output_key).commitment_verified
+            );
+        }
+    }
+    assert!(is_nums_key(&nums_key()));
+    assert!(!is_nums_key(&xonly(1)));
+}
+
+#[test]
+fn script_path_report() {
+    let spend_info = tree(nums_key());
+    let (tx, funding_tx) = script_path_spend(&spend_info, 4);
+    let report = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    assert_eq!(report.spend_type, SpendType::TaprootScriptPath);
+    assert_eq!(
+        report.verified_keys().collect::<Vec<_>>(),
+        [&SignerKey::XOnly(xonly(4))]
+    );
+    let control_block = report.control_block.unwrap();
+    assert!(control_block.nums_internal_key);
+    assert_eq!(control_block.depth(), 2);
+    assert_eq!(Some(control_block.merkle_root), spend_info.merkle_root());
+    assert_eq!(control_block.output_key, spend_info.output_key().to_inner());
+    assert!(control_block.commitment_verified);
+
+    // Leaf 3 of a tree with a key path
+    let spend_info = tree(xonly(1));
+    let (tx, funding_tx) = script_path_spend(&spend_info, 3);
+    let report = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    let control_block = report.control_block.unwrap();
+    assert!(!control_block.nums_internal_key);
+    assert_eq!(control_block.internal_key, xonly(1));
+    assert_eq!(control_block.depth(), 1);
+}
+
+#[test]
+fn other_leaf_version() {
+    // Signatures commit to the leaf hash of the control block's version
+    let version = LeafVersion::from_consensus(0xc2).unwrap();
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(1, leaf(3))
+        .unwrap()
+        .add_leaf_with_ver(1, leaf(4), version)
+        .unwrap()
+        .finalize(&Secp256k1::verification_only(), nums_key())
+        .unwrap();
+    let (tx, funding_tx) = leaf_version_spend(&spend_info, 4, version);
+    let report = verify_input_signers(&tx, 0, &[funding_tx]).unwrap();
+    assert_eq!(
+        report.verified_keys().collect::<Vec<_>>(),
+        [&SignerKey::XOnly(xonly(4))]
+    );
+    let control_block = report.control_block.unwrap();
+    assert_eq!(control_block.leaf_version, version);
+    assert_eq!(
+        control_block.leaf_hash,
+        TapLeafHash::from_script(&leaf(4), version)
+    );
+    assert!(control_block.commitment_verified);
+}
@@ -0,0 +1,150 @@
+use std::str::FromStr;
+
+use bitcoin::key::{Secp256k1, XOnlyPublicKey};
+use bitcoin::taproot::{LeafVersion, TapNodeHash, TaprootBuilder, TaprootSpendInfo};
+use bitcoin::ScriptBuf;
+use minikeys::{PrevoutMap, TapTreeNode, TapTrees, TaprootCommitment};
+use miniscript::Descriptor;
+
+mod common;
+use common::{leaf, leaf_hash, script_path_spend, xonly};
+
+//Checks the rebuilding of taproot script trees from script path spends, on
+//trees and spends built here with throwaway keys.
+
+//run with: cargo test --test taptree
+
+/// Leaf 3 at depth 1, leaf 4 at depth 2, leaves 5 and 6 at depth 3.
+fn tree(internal: u8) -> TaprootSpendInfo {
+    TaprootBuilder::new()
+        .add_leaf(1, leaf(3))
+        .unwrap()
+        .add_leaf(2, leaf(4))
+        .unwrap()
+        .add_leaf(3, leaf(5))
+        .unwrap()
+        .add_leaf(3, leaf(6))
+        .unwrap()
+        .finalize(&Secp256k1::verification_only(), xonly(internal))
+        .unwrap()
+}
+
+/// Feeds the spends of leaves `bytes` of `spend_info` to `trees`.
+fn add_spends(trees: &mut TapTrees, spend_info: &TaprootSpendInfo, bytes: &[u8]) {
+    for &byte in bytes {
+        let (tx, funding_tx) = script_path_spend(spend_info, byte);
+        let mut prevout_map = PrevoutMap::new();
+        prevout_map.insert_tx(&funding_tx);
+        assert_eq!(trees.add_tx(&tx, &prevout_map), 1);
+    }
+}
+
+#[test]
+fn rebuild() {
+    let spend_info = tree(1);
+    let commitment = TaprootCommitment::new(xonly(1), spend_info.merkle_root());
+    let mut trees = TapTrees::new();
+
+    // Leaf 4 reveals leaf 3 and the branch of leaves 5 and 6 by hash
+    add_spends(&mut trees, &spend_info, &[4]);
+    assert_eq!(trees.len(), 1);
+    let partial = trees.get(&commitment).unwrap();
+    assert_eq!(Some(partial.merkle_root()), spend_info.merkle_root());
+    assert_eq!(partial.output_key(), spend_info.output_key().to_inner());
+    assert_eq!(partial.internal_key(), xonly(1));
+    assert_eq!(
+        partial.leaves(),
+        [(2, leaf(4).as_script(), LeafVersion::TapScript)]
+    );
+    let branch_56 = TapNodeHash::from_node_hashes(leaf_hash(5), leaf_hash(6));
+    let mut hidden = partial.hidden();
+    hidden.sort();
+    let mut expected = vec![(1, leaf_hash(3)), (2, branch_56)];
+    expected.sort();
+    assert_eq!(hidden, expected);
+    assert!(!partial.is_complete());
+    let dot = partial.to_dot();
+    assert!(dot.starts_with("digraph taptree {"));
+    assert_eq!(dot.matches("style=dashed").count(), 2);
+    assert_eq!(dot.matches("style=filled").count(), 1);
+    assert!(dot.contains(&format!("OP_PUSHBYTES_32 {} OP_CHECKSIG", xonly(4))));
+
+    // Leaf 5 splits the hidden branch, leaf 6 staying hidden. Spending a
+    // leaf again reveals nothing new
+    add_spends(&mut trees, &spend_info, &[5, 4]);
+    assert_eq!(trees.len(), 1);
+    let partial = trees.get(&commitment).unwrap();
+    assert_eq!(partial.leaves().len(), 2);
+    assert_eq!(partial.hidden().len(), 2);
+    assert!(partial.hidden().contains(&(3, leaf_hash(6))));
+
+    add_spends(&mut trees, &spend_info, &[3, 6]);
+    let partial = trees.get(&commitment).unwrap();
+    assert!(partial.is_complete());
+    let mut leaves: Vec<_> = partial
+        .leaves()
+        .into_iter()
+        .map(|(depth, script, _)| (depth, script.to_owned()))
+        .collect();
+    leaves.sort();
+    assert_eq!(
+        leaves,
+        [(1, leaf(3)), (2, leaf(4)), (3, leaf(5)), (3, leaf(6))]
+    );
+    assert!(!partial.to_dot().contains("style=dashed"));
+    match partial.root() {
+        TapTreeNode::Branch { hash, children } => {
+            assert_eq!(Some(*hash), spend_info.merkle_root());
+            assert!(children[0].hash() <= children[1].hash());
+        }
+        node => panic!("root is not a branch: {:?}", node),
+    }
+}
+
+#[test]
+fn separate_trees() {
+    let mut trees = TapTrees::new();
+    add_spends(&mut trees, &tree(1), &[3]);
+    add_spends(&mut trees, &tree(2), &[3]);
+    assert_eq!(trees.len(), 2);
+    let internal_keys: Vec<_> = trees.iter().map(|t| t.internal_key()).collect();
+    assert_eq!(internal_keys, [xonly(1), xonly(2)]);
+
+    // A control block not committed to by the spent output is left out
+    let (tx, _) = script_path_spend(&tree(1), 4);
+    let (_, other_funding_tx) = script_path_spend(&tree(2), 4);
+    let txin = &tx.input[0];
+    assert_eq!(
+        trees.add_spend(txin, &other_funding_tx.output[0].script_pubkey),
+        None
+    );
+    // Nor is an input whose spent output is unknown
+    assert_eq!(trees.add_tx(&tx, &PrevoutMap::new()), 0);
+    let partial = trees
+        .get(&TaprootCommitment::new(xonly(1), tree(1).merkle_root()))
+        .unwrap();
+    assert_eq!(partial.leaves().len(), 1);
+}
+
+#[test]
+fn descriptor() {
+    let spend_info = tree(1);
+    let mut trees = TapTrees::new();
+    add_spends(&mut trees, &spend_info, &[4]);
+    let partial = trees.iter().next().unwrap();
+    let descriptor = partial.descriptor();
+    assert!(descriptor.starts_with(&format!("tr({},{{", xonly(1))));
+    assert!(descriptor.contains(&format!("pk({})", xonly(4))));
+    assert!(descriptor.contains(&format!("rawnode({})", leaf_hash(3))));
+    assert_eq!(descriptor.matches("rawnode(").count(), 2);
+
+    // Once complete, a descriptor of the spent output
+    add_spends(&mut trees, &spend_info, &[3, 5, 6]);
+    let descriptor = trees.iter().next().unwrap().descriptor();
+    assert!(!descriptor.contains("rawnode("));
+    let parsed = Descriptor::<XOnlyPublicKey>::from_str(&descriptor).unwrap();
+    assert_eq!(
+        parsed.script_pubkey(),
+        ScriptBuf::new_v1_p2tr_tweaked(spend_info.output_key())
+    );
+}
@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+
+use bitcoin::consensus::encode::VarInt;
+use bitcoin::consensus::serialize;
+use bitcoin::hashes::Hash;
+use bitcoin::{BlockHash, Network, OutPoint, TxOut};
+use minikeys::provider::SnapshotProvider;
+use minikeys::{verify_tx_signers_from, MinikeysError, PrevoutProvider};
+
+mod common;
+use common::{p2ms_txs, p2wsh_txs, varint, write_compressed_txout};
+
+//Checks loading the coins of dumptxoutset UTXO snapshots, in the formats of
+//Bitcoin Core 28 and of Core 25 to 27.
+
+//run with: cargo test --test utxo_snapshot
+
+//The outputs of the funding transactions of validkeys.rs, as if unspent
+fn coins() -> Vec<(OutPoint, TxOut)> {
+    let (_, p2wsh_reftx) = p2wsh_txs();
+    let (_, p2ms_reftx) = p2ms_txs();
+    let mut coins = vec![];
+    for tx in [p2wsh_reftx, p2ms_reftx] {
+        for (vout, txout) in tx.output.iter().enumerate() {
+            coins.push((OutPoint::new(tx.txid(), vout as u32), txout.clone()));
+        }
+    }
+    coins
+}
+
+fn base_blockhash() -> BlockHash {
+    BlockHash::from_byte_array([0x11; 32])
+}
+
+/// A snapshot of `coins`, grouped by txid in the format of Core 28 or with
+/// one outpoint per coin as before.
+fn snapshot(coins: &[(OutPoint, TxOut)], grouped: bool, network: Network) -> Vec<u8> {
+    let mut data = vec![];
+    if grouped {
+        data.extend(b"utxo\xff");
+        data.extend(2u16.to_le_bytes());
+        data.extend(network.magic().to_bytes());
+    }
+    data.extend(base_blockhash().to_byte_array());
+    data.extend((coins.len() as u64).to_le_bytes());
+    let mut i = 0;
+    while i < coins.len() {
+        let txid = coins[i].0.txid;
+        let group: Vec<_> = coins[i..]
+            .iter()
+            .take_while(|(o, _)| o.txid == txid)
+            .collect();
+        if grouped {
+            data.extend(txid.to_byte_array());
+            data.extend(serialize(&VarInt(group.len() as u64)));
+        }
+        for (outpoint, txout) in &group {
+            if grouped {
+                data.extend(serialize(&VarInt(u64::from(outpoint.vout))));
+            } else {
+                data.extend(txid.to_byte_array());
+                data.extend(outpoint.vout.to_le_bytes());
+            }
+            //Height 800000, not coinbase
+            data.extend(varint(800_000 * 2));
+            write_compressed_txout(&mut data, txout);
+        }
+        i += group.len();
+    }
+    data
+}
+
+#[test]
+fn snapshot_formats() {
+    let coins = coins();
+    let (p2wsh_tx, _) = p2wsh_txs();
+    for grouped in [true, false] {
+        let data = snapshot(&coins, grouped, Network::Bitcoin);
+        let path = std::env::temp_dir().join(format!(
+            "minikeys-utxo-{}-{}.dat",
+            grouped,
+            std::process::id()
+        ));
+        std::fs::write(&path, data).unwrap();
+        let provider = SnapshotProvider::open(&path, Network::Bitcoin).unwrap();
+        assert_eq!(provider.base_blockhash(), base_blockhash());
+        assert_eq!(provider.coins_count(), coins.len() as u64);
+        assert_eq!(provider.len(), coins.len());
+        for (outpoint, txout) in &coins {
+            assert_eq!(provider.prevout(outpoint).unwrap().as_ref(), Some(txout));
+        }
+        let unknown = OutPoint::new(p2wsh_tx.txid(), 0);
+        assert_eq!(provider.prevout(&unknown).unwrap(), None);
+
+        let report = verify_tx_signers_from(&p2wsh_tx, &provider).unwrap();
+        assert_eq!(report.inputs[0].verified_keys().count(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn filtered() {
+    let coins = coins();
+    let (p2ms_tx, _) = p2ms_txs();
+    let wanted: HashSet<OutPoint> = p2ms_tx.input.iter().map(|i| i.previous_output).collect();
+    let data = snapshot(&coins, true, Network::Bitcoin);
+    let provider =
+        SnapshotProvider::read(&data[..], Network::Bitcoin, |o| wanted.contains(o)).unwrap();
+    assert_eq!(provider.coins_count(), coins.len() as u64);
+    assert_eq!(provider.len(), wanted.len());
+    let report = verify_tx_signers_from(&p2ms_tx, &provider).unwrap();
+    assert_eq!(report.inputs[0].verified_keys().count(), 2);
+}
+
+#[test]
+fn bad_snapshots() {
+    let coins = coins();
+    let is_provider_err =
+        |r: Result<SnapshotProvider, MinikeysError>| matches!(r, Err(MinikeysError::Provider(_)));
+    let data = snapshot(&coins, true, Network::Signet);
+    assert!(is_provider_err(SnapshotProvider::read(
+        &data[..],
+        Network::Bitcoin,
+        |_| true
+    )));
+    assert!(SnapshotProvider::read(&data[..], Network::Signet, |_| true).is_ok());
+
+    //Unknown version
+    let mut data = snapshot(&coins, true, Network::Bitcoin);
+    data[5] = 3;
+    assert!(is_provider_err(SnapshotProvider::read(
+        &data[..],
+        Network::Bitcoin,
+        |_| true
+    )));
+
+    //Fewer coins than announced
+    let data = snapshot(&coins, false, Network::Bitcoin);
+    assert!(is_provider_err(SnapshotProvider::read(
+        &data[..data.len() - 1],
+        Network::Bitcoin,
+        |_| true
+    )));
+}
@@ -0,0 +1,280 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
+use bitcoin::{OutPoint, ScriptBuf, Transaction, TxIn, TxOut, Witness};
+use minikeys::{
+    decode_tx, verify_input_signers, verify_input_signers_with, verify_tx_signers, InputReport,
+    MinikeysError, PrevoutMap, SighashPrevouts, SighashType, SignerKey, SignerSignature, SpendType,
+    TxSigner, TxSummary, UnanalyzedInput,
+};
+
+mod common;
+use common::{p2ms_txs, p2sh_txs, p2tr_two_input_txs, p2tr_txs, p2wsh_txs};
+
+//Checks the signers found in the spends of validkeys.rs, the errors for bad
+//transactions and spent outputs, and the summary of a whole transaction.
+//The other errors are checked along with what reports them: spent_outputs.rs
+//for PSBTs and derived scriptPubKeys, provider_layers.rs, node_json.rs and
+//block_scan.rs.
+
+//run with: cargo test --test validkeys
+
+fn xonly_key(hex: &str) -> SignerKey {
+    SignerKey::XOnly(hex.parse::<XOnlyPublicKey>().unwrap())
+}
+
+/// The scriptSig push or witness element at `index`.
+fn element(txin: &TxIn, index: usize) -> Vec<u8> {
+    if !txin.witness.is_empty() {
+        return txin.witness.nth(index).unwrap().to_vec();
+    }
+    match txin.script_sig.instructions().nth(index) {
+        Some(Ok(Instruction::PushBytes(bytes))) => bytes.as_bytes().to_vec(),
+        other => panic!("no push at {}: {:?}", index, other),
+    }
+}
+
+/// Checks that the indexes of every signer point at its key and signature,
+/// and that the keys without a verified signer are the non-signers.
+fn check_indexes(txin: &TxIn, report: &InputReport) {
+    for signer in &report.signers {
+        let key_index = signer.key_index.unwrap();
+        assert_eq!(report.script_keys[key_index], signer.key);
+        let signature = match signer.signature {
+            SignerSignature::Ecdsa(sig) => sig.to_vec(),
+            SignerSignature::Schnorr(sig) => sig.to_vec(),
+        };
+        assert_eq!(element(txin, signer.sig_index.unwrap()), signature);
+        assert_eq!(signer.sighash_type, signer.signature.sighash_type());
+    }
+    let non_signers: Vec<SignerKey> = report
+        .script_keys
+        .iter()
+        .filter(|key| !report.verified_keys().any(|k| k == *key))
+        .cloned()
+        .collect();
+    assert_eq!(report.non_signers, non_signers);
+}
+
+#[test]
+fn multisig_signers() {
+    let cases = [
+        (p2ms_txs(), SpendType::Bare, 3),
+        (p2sh_txs(), SpendType::P2sh, 2),
+        (p2wsh_txs(), SpendType::P2wsh, 2),
+    ];
+    for ((tx, reftx), spend_type, keys) in cases {
+        let report = verify_input_signers(&tx, 0, &[reftx]).unwrap();
+        assert_eq!(report.input_index, 0);
+        assert_eq!(report.spend_type, spend_type);
+        assert_eq!(report.script_keys.len(), keys);
+        assert_eq!(report.signers.len(), 2);
+        for signer in &report.signers {
+            assert!(signer.verified);
+            let all = SighashType::Ecdsa(EcdsaSighashType::All);
+            assert_eq!(signer.sighash_type, all);
+        }
+        assert_eq!(report.non_signers.len(), keys - 2);
+        check_indexes(&tx.input[0], &report);
+        // OP_CHECKMULTISIG takes the signatures in the order of the keys
+        let mut signers = report.signers.clone();
+        signers.sort_by_key(|s| s.sig_index);
+        assert!(signers[0].key_index < signers[1].key_index);
+    }
+}
+
+#[test]
+fn tapscript_signers() {
+    //febe.. OP_CHECKSIGVERIFY d0fa.. OP_CHECKSIG, the signature of the first
+    //key on top of the witness stack
+    let (tx, reftx) = p2tr_txs();
+    let report = verify_input_signers(&tx, 0, &[reftx]).unwrap();
+    assert_eq!(report.spend_type, SpendType::TaprootScriptPath);
+    let first = xonly_key("febe583fa77e49089f89b78fa8c116710715d6e40cc5f5a075ef1681550dd3c4");
+    let second = xonly_key("d0fa46cb883e940ac3dc5421f05b03859972639f51ed2eccbf3dc5a62e2e1b15");
+    assert_eq!(report.script_keys, [first, second]);
+    assert!(report.non_signers.is_empty());
+    assert_eq!(report.signers.len(), 2);
+    let signer = |key| report.signers.iter().find(|s| s.key == key).unwrap();
+    assert!(signer(first).verified && signer(second).verified);
+    assert_eq!(
+        (signer(first).key_index, signer(first).sig_index),
+        (Some(0), Some(1))
+    );
+    assert_eq!(
+        (signer(second).key_index, signer(second).sig_index),
+        (Some(1), Some(0))
+    );
+    let default = SighashType::Taproot(TapSighashType::Default);
+    assert_eq!(signer(first).sighash_type, default);
+    let all = SighashType::Taproot(TapSighashType::All);
+    assert_eq!(signer(second).sighash_type, all);
+    check_indexes(&tx.input[0], &report);
+}
+
+#[test]
+fn prevouts_in_any_order() {
+    //c13e.. OP_CHECKSIG b0e2.. OP_CHECKSIGADD 1 OP_NUMEQUAL in both inputs,
+    //the second key signing
+    let (tx, [reftx_0, reftx_1]) = p2tr_two_input_txs();
+    let ordered = [reftx_0.clone(), reftx_1.clone()];
+    let report = verify_input_signers(&tx, 0, &ordered).unwrap();
+    let signer = xonly_key("b0e2e48ad7c3d776cf6f2395c504dc19551268ea7429496726c5d5bf72f9333c");
+    let other = xonly_key("c13e6d193f5d04506723bd67abcc5d31b610395c445ac6744cb0a1846b3aabae");
+    assert_eq!(report.verified_keys().collect::<Vec<_>>(), [&signer]);
+    assert_eq!(report.signers[0].key_index, Some(1));
+    assert_eq!(report.signers[0].sig_index, Some(0));
+    assert_eq!(report.non_signers, [other]);
+    check_indexes(&tx.input[0], &report);
+
+    let tx_report = verify_tx_signers(&tx, &ordered);
+    assert_eq!(tx_report.inputs[0], report);
+    let prevout_map = PrevoutMap::from_txs(&ordered);
+    assert_eq!(
+        verify_input_signers_with(&tx, 0, &prevout_map),
+        Ok(report.clone())
+    );
+    let reversed = [reftx_1.clone(), reftx_0.clone()];
+    let repeated = [reftx_1.clone(), reftx_0.clone(), reftx_1, reftx_0];
+    for prevouts in [&reversed[..], &repeated[..]] {
+        assert_eq!(verify_input_signers(&tx, 0, prevouts), Ok(report.clone()));
+        assert_eq!(verify_tx_signers(&tx, prevouts), tx_report);
+    }
+}
+
+#[test]
+fn sighash_prevouts() {
+    let (tx, [reftx_0, reftx_1]) = p2tr_two_input_txs();
+    let spent_0 = reftx_0.output[0].clone();
+    let spent_1 = reftx_1.output[0].clone();
+    let prevout_map = PrevoutMap::from_txs([&reftx_1]);
+    // Only the input's own spent output, when that is all the sighash needs
+    let one = prevout_map.sighash_prevouts(&tx, 1, false);
+    assert_eq!(one, Ok(SighashPrevouts::One(1, spent_1.clone())));
+    // All of them, none made up for the unknown ones
+    let missing = MinikeysError::MissingPrevout {
+        input_index: 0,
+        outpoint: tx.input[0].previous_output,
+    };
+    assert_eq!(
+        prevout_map.sighash_prevouts(&tx, 1, true),
+        Err(missing.clone())
+    );
+    assert_eq!(
+        verify_input_signers(&tx, 1, std::slice::from_ref(&reftx_1)),
+        Err(missing)
+    );
+    let prevout_map = PrevoutMap::from_txs([&reftx_1, &reftx_0]);
+    let all = prevout_map.sighash_prevouts(&tx, 1, true);
+    assert_eq!(all, Ok(SighashPrevouts::All(vec![spent_0, spent_1])));
+}
+
+#[test]
+fn errors() {
+    let (tx, reftx) = p2wsh_txs();
+    let raw_tx = bitcoin::consensus::serialize(&tx);
+    let err = decode_tx(&raw_tx[..raw_tx.len() - 1]).unwrap_err();
+    assert!(matches!(err, MinikeysError::TxDecode(_)));
+
+    let out_of_range = MinikeysError::InputIndexOutOfRange {
+        index: 1,
+        inputs: 1,
+    };
+    let report = verify_input_signers(&tx, 1, std::slice::from_ref(&reftx));
+    assert_eq!(report, Err(out_of_range));
+
+    let outpoint = tx.input[0].previous_output;
+    let missing = MinikeysError::MissingPrevout {
+        input_index: 0,
+        outpoint,
+    };
+    assert_eq!(verify_input_signers(&tx, 0, &[]), Err(missing));
+
+    // The funding transaction is known, but has no such output
+    let mut bad_vout = tx.clone();
+    bad_vout.input[0].previous_output.vout = 5;
+    let mismatch = MinikeysError::PrevoutMismatch {
+        input_index: 0,
+        outpoint: bad_vout.input[0].previous_output,
+    };
+    let report = verify_input_signers(&bad_vout, 0, std::slice::from_ref(&reftx));
+    assert_eq!(report, Err(mismatch));
+
+    let mut prevout_map = PrevoutMap::new();
+    let op_return = ScriptBuf::new_op_return(&[]);
+    let txout = TxOut {
+        value: 1000,
+        script_pubkey: op_return.clone(),
+    };
+    prevout_map.insert(outpoint, txout);
+    let unsupported = MinikeysError::UnsupportedScript {
+        input_index: 0,
+        script_pubkey: op_return,
+    };
+    let report = verify_input_signers_with(&tx, 0, &prevout_map);
+    assert_eq!(report, Err(unsupported));
+
+    // A P2WSH spend without its witness script
+    let mut unsigned = tx;
+    unsigned.input[0].witness = Witness::new();
+    let err = verify_input_signers(&unsigned, 0, &[reftx]).unwrap_err();
+    assert!(matches!(
+        err,
+        MinikeysError::Interpreter { input_index: 0, .. }
+    ));
+}
+
+#[test]
+fn tx_summary() {
+    let (tx, [reftx_0, reftx_1]) = p2tr_two_input_txs();
+    let report = verify_tx_signers(&tx, &[reftx_0.clone(), reftx_1]);
+    assert_eq!(report.txid, tx.txid());
+    assert!(!report.coinbase);
+    assert_eq!(report.inputs.len(), 2);
+    let signer = xonly_key("b0e2e48ad7c3d776cf6f2395c504dc19551268ea7429496726c5d5bf72f9333c");
+    let expected = TxSigner {
+        key: signer,
+        inputs: vec![0, 1],
+    };
+    assert_eq!(report.summary.signers, [expected]);
+    assert_eq!(report.summary.multi_input_signers, [signer]);
+    assert!(report.summary.unanalyzed.is_empty());
+
+    // Either taproot input needs the output spent by the other one
+    let report = verify_tx_signers(&tx, &[reftx_0]);
+    assert!(report.inputs.is_empty());
+    assert!(report.summary.signers.is_empty());
+    let error = MinikeysError::MissingPrevout {
+        input_index: 1,
+        outpoint: tx.input[1].previous_output,
+    };
+    let unanalyzed: Vec<UnanalyzedInput> = (0..2)
+        .map(|input_index| UnanalyzedInput {
+            input_index,
+            error: error.clone(),
+        })
+        .collect();
+    assert_eq!(report.summary.unanalyzed, unanalyzed);
+}
+
+#[test]
+fn coinbase() {
+    let coinbase = Transaction {
+        version: 1,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::from(vec![3, 1, 2, 3]),
+            ..TxIn::default()
+        }],
+        output: vec![TxOut {
+            value: 50_000,
+            script_pubkey: ScriptBuf::new_op_return(&[]),
+        }],
+    };
+    let report = verify_tx_signers(&coinbase, &[]);
+    assert!(report.coinbase);
+    assert!(report.inputs.is_empty());
+    assert_eq!(report.summary, TxSummary::default());
+}